@@ -0,0 +1,58 @@
+// Copyright 2015, 2016 Ethcore (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! EIP-55 checksummed address encoding for API responses.
+//! None of the current responses carry addresses; new address-typed fields
+//! should be encoded with `to_checksum`, while plain hashes stay lowercase hex.
+
+use rustc_serialize::hex::ToHex;
+use util::{Address, Hashable};
+
+/// Encodes address as `0x`-prefixed hex using EIP-55 mixed-case checksum.
+pub fn to_checksum(address: &Address) -> String {
+	let hex = address.to_hex();
+	let hash = hex.sha3();
+
+	let mut checksummed = "0x".to_owned();
+	for (i, c) in hex.chars().enumerate() {
+		let nibble = if i % 2 == 0 { hash[i / 2] >> 4 } else { hash[i / 2] & 0x0f };
+		if nibble >= 8 {
+			checksummed.extend(c.to_uppercase());
+		} else {
+			checksummed.push(c);
+		}
+	}
+	checksummed
+}
+
+#[cfg(test)]
+mod tests {
+	use std::str::FromStr;
+	use util::Address;
+	use super::to_checksum;
+
+	#[test]
+	fn should_encode_address_with_checksum() {
+		// given
+		let address = Address::from_str("5aaeb6053f3e94c9b9a09f33669435e7ef1beaed").unwrap();
+
+		// when
+		let checksummed = to_checksum(&address);
+
+		// then
+		assert_eq!(checksummed, "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed");
+	}
+}
@@ -14,21 +14,20 @@
 // You should have received a copy of the GNU General Public License
 // along with Parity.  If not, see <http://www.gnu.org/licenses/>.
 
-use std::sync::Arc;
 use hyper::{server, net, Decoder, Encoder, Next};
 use api::types::{App, ApiError};
 use api::response::{as_json, as_json_error, ping_response};
 use handlers::extract_url;
-use endpoint::{Endpoint, Endpoints, Handler, EndpointPath};
+use endpoint::{Endpoint, SharedEndpoints, Handler, EndpointPath};
 
 #[derive(Clone)]
 pub struct RestApi {
 	local_domain: String,
-	endpoints: Arc<Endpoints>,
+	endpoints: SharedEndpoints,
 }
 
 impl RestApi {
-	pub fn new(local_domain: String, endpoints: Arc<Endpoints>) -> Box<Endpoint> {
+	pub fn new(local_domain: String, endpoints: SharedEndpoints) -> Box<Endpoint> {
 		Box::new(RestApi {
 			local_domain: local_domain,
 			endpoints: endpoints,
@@ -36,7 +35,7 @@ impl RestApi {
 	}
 
 	fn list_apps(&self) -> Vec<App> {
-		self.endpoints.iter().filter_map(|(ref k, ref e)| {
+		self.endpoints.current().iter().filter_map(|(ref k, ref e)| {
 			e.info().map(|ref info| App::from_info(k, info))
 		}).collect()
 	}
@@ -99,3 +98,48 @@ impl server::Handler<net::HttpStream> for RestApiRouter {
 	}
 
 }
+
+#[cfg(test)]
+mod tests {
+	use endpoint::{Endpoint, Endpoints, EndpointInfo, EndpointPath, Handler, SharedEndpoints};
+	use api::response::to_json;
+	use super::RestApi;
+
+	struct FakeEndpoint(EndpointInfo);
+
+	impl Endpoint for FakeEndpoint {
+		fn info(&self) -> Option<&EndpointInfo> {
+			Some(&self.0)
+		}
+
+		fn to_handler(&self, _path: EndpointPath) -> Box<Handler> {
+			unimplemented!()
+		}
+	}
+
+	#[test]
+	fn should_escape_app_id_in_json() {
+		// given
+		let mut endpoints = Endpoints::new();
+		endpoints.insert("my\"dapp".into(), Box::new(FakeEndpoint(EndpointInfo {
+			name: "Test".into(),
+			description: "".into(),
+			version: "".into(),
+			author: "".into(),
+			icon_url: "".into(),
+		})));
+		let api = RestApi {
+			local_domain: "127.0.0.1:8080".into(),
+			endpoints: SharedEndpoints::new(endpoints),
+		};
+
+		// when
+		let json = to_json(&api.list_apps()).unwrap();
+
+		// then
+		assert_eq!(
+			json,
+			r#"[{"id":"my\"dapp","name":"Test","description":"","version":"","author":"","iconUrl":""}]"#
+		);
+	}
+}
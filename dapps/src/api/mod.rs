@@ -19,9 +19,13 @@
 #![cfg_attr(feature="nightly", feature(custom_derive, custom_attribute, plugin))]
 #![cfg_attr(feature="nightly", plugin(serde_macros, clippy))]
 
+mod address;
 mod api;
 mod response;
 mod types;
+mod utils;
 
+pub use self::address::to_checksum;
 pub use self::api::RestApi;
-pub use self::types::App;
+pub use self::types::{App, VersionInfo};
+pub use self::utils::Utils;
@@ -16,15 +16,37 @@
 
 use serde::Serialize;
 use serde_json;
+use hyper::status::StatusCode;
 use endpoint::Handler;
 use handlers::{ContentHandler, EchoHandler};
 
+/// Serializes given value to a JSON string.
+/// All API responses go through this, so escaping is handled by `serde_json` in one place.
+pub fn to_json<T : Serialize>(val: &T) -> Result<String, serde_json::Error> {
+	serde_json::to_string(val)
+}
+
 pub fn as_json<T : Serialize>(val: &T) -> Box<Handler> {
-	Box::new(ContentHandler::ok(serde_json::to_string(val).unwrap(), "application/json".to_owned()))
+	match to_json(val) {
+		Ok(json) => Box::new(ContentHandler::ok(json, "application/json".to_owned())),
+		Err(e) => serialization_error(e),
+	}
 }
 
 pub fn as_json_error<T : Serialize>(val: &T) -> Box<Handler> {
-	Box::new(ContentHandler::not_found(serde_json::to_string(val).unwrap(), "application/json".to_owned()))
+	match to_json(val) {
+		Ok(json) => Box::new(ContentHandler::not_found(json, "application/json".to_owned())),
+		Err(e) => serialization_error(e),
+	}
+}
+
+fn serialization_error(e: serde_json::Error) -> Box<Handler> {
+	warn!(target: "dapps", "Unable to serialize API response: {:?}", e);
+	Box::new(ContentHandler::new(
+		StatusCode::InternalServerError,
+		r#"{"code":"500","title":"Internal Server Error","detail":"Unable to serialize response."}"#.into(),
+		"application/json".to_owned(),
+	))
 }
 
 pub fn ping_response(local_domain: &str) -> Box<Handler> {
@@ -0,0 +1,111 @@
+// Copyright 2015, 2016 Ethcore (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+use hyper::{server, net, Decoder, Encoder, Next};
+use api::VersionInfo;
+use api::response::as_json;
+use handlers::extract_url;
+use endpoint::{Endpoint, Handler, EndpointPath};
+use util::misc;
+
+/// Name of the file under `parity-utils` with build information.
+pub const VERSION_FILE: &'static str = "version";
+
+/// Endpoint serving `parity-utils` files, along with build information of the server
+/// (as JSON) under `version`.
+pub struct Utils {
+	files: Box<Endpoint>,
+}
+
+impl Utils {
+	pub fn new(files: Box<Endpoint>) -> Box<Endpoint> {
+		Box::new(Utils {
+			files: files,
+		})
+	}
+}
+
+impl Endpoint for Utils {
+	fn to_handler(&self, path: EndpointPath) -> Box<Handler> {
+		Box::new(UtilsRouter {
+			handler: self.files.to_handler(path),
+		})
+	}
+}
+
+/// Build information of the running server.
+pub fn version_info() -> VersionInfo {
+	VersionInfo {
+		version: env!("CARGO_PKG_VERSION").into(),
+		commit: misc::sha().into(),
+		features: enabled_features(),
+	}
+}
+
+// cargo features the server was compiled with.
+fn enabled_features() -> Vec<String> {
+	let features = [
+		("extra-dapps", cfg!(feature = "extra-dapps")),
+		("parity-dapps-wallet", cfg!(feature = "parity-dapps-wallet")),
+		("use-precompiled-js", cfg!(feature = "use-precompiled-js")),
+	];
+	features.iter()
+		.filter(|&&(_, enabled)| enabled)
+		.map(|&(name, _)| name.to_owned())
+		.collect()
+}
+
+struct UtilsRouter {
+	handler: Box<Handler>,
+}
+
+impl server::Handler<net::HttpStream> for UtilsRouter {
+	fn on_request(&mut self, request: server::Request<net::HttpStream>) -> Next {
+		let is_version = extract_url(&request)
+			.map_or(false, |url| url.path.len() == 2 && url.path[1] == VERSION_FILE);
+
+		if is_version {
+			self.handler = as_json(&version_info());
+		}
+
+		self.handler.on_request(request)
+	}
+
+	fn on_request_readable(&mut self, decoder: &mut Decoder<net::HttpStream>) -> Next {
+		self.handler.on_request_readable(decoder)
+	}
+
+	fn on_response(&mut self, res: &mut server::Response) -> Next {
+		self.handler.on_response(res)
+	}
+
+	fn on_response_writable(&mut self, encoder: &mut Encoder<net::HttpStream>) -> Next {
+		self.handler.on_response_writable(encoder)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::version_info;
+
+	#[test]
+	fn should_report_features_matching_cfg() {
+		let info = version_info();
+		assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+		assert_eq!(info.features.contains(&"parity-dapps-wallet".to_owned()), cfg!(feature = "parity-dapps-wallet"));
+		assert_eq!(info.features.contains(&"extra-dapps".to_owned()), cfg!(feature = "extra-dapps"));
+	}
+}
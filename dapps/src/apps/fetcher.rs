@@ -19,25 +19,30 @@
 //! Uses `URLHint` to resolve addresses into Dapps bundle file location.
 
 use zip;
+use tar;
+use flate2::read::GzDecoder;
 use std::{fs, env};
-use std::io::{self, Read, Write};
-use std::path::PathBuf;
+use std::io::{self, Read, Write, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
 use std::sync::Arc;
 use std::collections::HashMap;
 use rustc_serialize::hex::FromHex;
 
 use hyper::Control;
-use hyper::status::StatusCode;
 
 use random_filename;
 use util::{Mutex, H256};
 use util::sha3::sha3;
 use page::LocalPageEndpoint;
-use handlers::{ContentHandler, AppFetcherHandler, DappHandler};
+use error::DappsError;
+use handlers::{AppFetcherHandler, DappHandler};
 use endpoint::{Endpoint, EndpointPath, Handler};
 use apps::manifest::{MANIFEST_FILENAME, deserialize_manifest, serialize_manifest, Manifest};
 use apps::urlhint::{URLHintContract, URLHint};
 
+/// Upper bound on the total size of files extracted from a single dapp bundle.
+pub const MAX_EXTRACTED_SIZE: u64 = 64 * 1024 * 1024;
+
 enum AppStatus {
 	Fetching,
 	Ready(LocalPageEndpoint),
@@ -100,14 +105,7 @@ impl<R: URLHint> AppFetcher<R> {
 				},
 				// App is already being fetched
 				Some(&AppStatus::Fetching) => {
-					(None, Box::new(ContentHandler::html(
-						StatusCode::ServiceUnavailable,
-						format!(
-							"<html><head>{}</head><body>{}</body></html>",
-							"<meta http-equiv=\"refresh\" content=\"1\">",
-							"<h1>This dapp is already being downloaded.</h1><h2>Please wait...</h2>",
-						)
-					)) as Box<Handler>)
+					(None, Box::new(DappsError::FetchInProgress.to_handler()) as Box<Handler>)
 				},
 				// We need to start fetching app
 				None => {
@@ -122,6 +120,7 @@ impl<R: URLHint> AppFetcher<R> {
 							dapp_id: app_id.clone(),
 							dapps_path: self.dapps_path.clone(),
 							dapps: self.dapps.clone(),
+							max_extracted_size: MAX_EXTRACTED_SIZE,
 						}
 					)) as Box<Handler>)
 				},
@@ -144,6 +143,8 @@ pub enum ValidationError {
 	ManifestNotFound,
 	ManifestSerialization(String),
 	HashMismatch { expected: H256, got: H256, },
+	UnknownFormat,
+	TooLarge { limit: u64 },
 }
 
 impl From<io::Error> for ValidationError {
@@ -158,10 +159,54 @@ impl From<zip::result::ZipError> for ValidationError {
 	}
 }
 
+/// Archive formats dapp bundles may come in.
+#[derive(Debug, PartialEq)]
+enum BundleFormat {
+	Zip,
+	TarGz,
+}
+
+impl BundleFormat {
+	/// Detects the format from the magic bytes at the start of the bundle.
+	fn detect(magic: &[u8]) -> Option<Self> {
+		if magic.starts_with(b"PK\x03\x04") {
+			Some(BundleFormat::Zip)
+		} else if magic.starts_with(&[0x1f, 0x8b]) {
+			Some(BundleFormat::TarGz)
+		} else {
+			None
+		}
+	}
+}
+
+// location of a bundled file within the dapp, if it's inside the manifest directory.
+fn location_in_dapp(manifest_dir: &Path, file_path: &Path) -> Option<PathBuf> {
+	// never write outside of the dapp directory: absolute paths and `..` are rejected.
+	let relative = file_path.components().all(|c| match c {
+		Component::Normal(_) | Component::CurDir => true,
+		Component::ParentDir | Component::RootDir | Component::Prefix(_) => false,
+	});
+	if !relative {
+		return None;
+	}
+	file_path.strip_prefix(manifest_dir).ok().map(Path::to_path_buf)
+}
+
+// copy a bundled file, failing once more than `budget` bytes were extracted in total.
+fn copy_limited<R: Read, W: Write>(reader: &mut R, writer: &mut W, budget: &mut u64, limit: u64) -> Result<(), ValidationError> {
+	let copied = try!(io::copy(&mut reader.take(*budget + 1), writer));
+	if copied > *budget {
+		return Err(ValidationError::TooLarge { limit: limit });
+	}
+	*budget -= copied;
+	Ok(())
+}
+
 struct DappInstaller {
 	dapp_id: String,
 	dapps_path: PathBuf,
 	dapps: Arc<Mutex<HashMap<String, AppStatus>>>,
+	max_extracted_size: u64,
 }
 
 impl DappInstaller {
@@ -189,11 +234,105 @@ impl DappInstaller {
 		Err(ValidationError::ManifestNotFound)
 	}
 
+	// reads the whole tarball looking for the manifest, so a bomb is rejected before anything is written.
+	fn find_tar_manifest(&self, file: &mut fs::File) -> Result<(Manifest, PathBuf), ValidationError> {
+		try!(file.seek(SeekFrom::Start(0)));
+		let mut archive = tar::Archive::new(try!(GzDecoder::new(file)));
+		let mut budget = self.max_extracted_size;
+		let mut found = None;
+
+		for entry in try!(archive.entries()) {
+			let mut entry = try!(entry);
+			let path = try!(entry.path()).into_owned();
+
+			if found.is_none() && path.ends_with(MANIFEST_FILENAME) {
+				let mut manifest = Vec::new();
+				try!(copy_limited(&mut entry, &mut manifest, &mut budget, self.max_extracted_size));
+				let manifest = String::from_utf8(manifest).ok().and_then(|m| deserialize_manifest(m).ok());
+
+				if let Some(manifest) = manifest {
+					let mut manifest_location = path;
+					manifest_location.pop(); // get rid of filename
+					found = Some((manifest, manifest_location));
+				}
+			} else {
+				try!(copy_limited(&mut entry, &mut io::sink(), &mut budget, self.max_extracted_size));
+			}
+		}
+
+		found.ok_or(ValidationError::ManifestNotFound)
+	}
+
 	fn dapp_target_path(&self, manifest: &Manifest) -> PathBuf {
 		let mut target = self.dapps_path.clone();
 		target.push(&manifest.id);
 		target
 	}
+
+	// checks the sizes declared by the zip before anything is written.
+	// files may still lie about their size, so extraction is limited as well.
+	fn check_zip_size(&self, zip: &mut zip::ZipArchive<fs::File>, manifest_dir: &Path) -> Result<(), ValidationError> {
+		let mut total = 0u64;
+		for i in 0..zip.len() {
+			let file = try!(zip.by_index(i));
+			if location_in_dapp(manifest_dir, Path::new(file.name())).is_some() {
+				total = total.saturating_add(file.size());
+			}
+		}
+
+		match total > self.max_extracted_size {
+			true => Err(ValidationError::TooLarge { limit: self.max_extracted_size }),
+			false => Ok(()),
+		}
+	}
+
+	fn unpack_zip(&self, zip: &mut zip::ZipArchive<fs::File>, manifest_dir: &Path, target: &Path) -> Result<(), ValidationError> {
+		let mut budget = self.max_extracted_size;
+		for i in 0..zip.len() {
+			let mut file = try!(zip.by_index(i));
+			// TODO [todr] Check if it's consistent on windows.
+			let is_dir = file.name().chars().rev().next() == Some('/');
+
+			// Create files that are inside manifest directory
+			if let Some(location_in_manifest_base) = location_in_dapp(manifest_dir, Path::new(file.name())) {
+				let p = target.join(location_in_manifest_base);
+				// Check if it's a directory
+				if is_dir {
+					try!(fs::create_dir_all(p));
+				} else {
+					let mut target = try!(fs::File::create(p));
+					try!(copy_limited(&mut file, &mut target, &mut budget, self.max_extracted_size));
+				}
+			}
+		}
+		Ok(())
+	}
+
+	fn unpack_tar_gz(&self, file: &mut fs::File, manifest_dir: &Path, target: &Path) -> Result<(), ValidationError> {
+		try!(file.seek(SeekFrom::Start(0)));
+		let mut archive = tar::Archive::new(try!(GzDecoder::new(file)));
+		let mut budget = self.max_extracted_size;
+
+		for entry in try!(archive.entries()) {
+			let mut entry = try!(entry);
+			let path = try!(entry.path()).into_owned();
+
+			if let Some(location_in_manifest_base) = location_in_dapp(manifest_dir, &path) {
+				let p = target.join(location_in_manifest_base);
+				let entry_type = entry.header().entry_type();
+				if entry_type.is_dir() {
+					try!(fs::create_dir_all(p));
+				} else if entry_type.is_file() {
+					if let Some(parent) = p.parent() {
+						try!(fs::create_dir_all(parent));
+					}
+					let mut target = try!(fs::File::create(p));
+					try!(copy_limited(&mut entry, &mut target, &mut budget, self.max_extracted_size));
+				}
+			}
+		}
+		Ok(())
+	}
 }
 
 impl DappHandler for DappInstaller {
@@ -210,10 +349,24 @@ impl DappHandler for DappInstaller {
 				got: hash,
 			});
 		}
-		// Unpack archive
-		let mut zip = try!(zip::ZipArchive::new(file));
+
+		let mut magic = [0u8; 4];
+		try!(file.seek(SeekFrom::Start(0)));
+		let magic_len = try!(file.read(&mut magic));
+		let format = try!(BundleFormat::detect(&magic[..magic_len]).ok_or(ValidationError::UnknownFormat));
+
 		// First find manifest file
-		let (mut manifest, manifest_dir) = try!(Self::find_manifest(&mut zip));
+		let mut zip_archive = None;
+		let (mut manifest, manifest_dir) = match format {
+			BundleFormat::Zip => {
+				let mut archive = try!(zip::ZipArchive::new(try!(file.try_clone())));
+				let found = try!(Self::find_manifest(&mut archive));
+				try!(self.check_zip_size(&mut archive, &found.1));
+				zip_archive = Some(archive);
+				found
+			},
+			BundleFormat::TarGz => try!(self.find_tar_manifest(&mut file)),
+		};
 		// Overwrite id to match hash
 		manifest.id = self.dapp_id.clone();
 
@@ -225,25 +378,15 @@ impl DappHandler for DappInstaller {
 			try!(fs::remove_dir_all(target.clone()));
 		}
 
-		// Unpack zip
-		for i in 0..zip.len() {
-			let mut file = try!(zip.by_index(i));
-			// TODO [todr] Check if it's consistent on windows.
-			let is_dir = file.name().chars().rev().next() == Some('/');
-
-			let file_path = PathBuf::from(file.name());
-			let location_in_manifest_base = file_path.strip_prefix(&manifest_dir);
-			// Create files that are inside manifest directory
-			if let Ok(location_in_manifest_base) = location_in_manifest_base {
-				let p = target.join(location_in_manifest_base);
-				// Check if it's a directory
-				if is_dir {
-					try!(fs::create_dir_all(p));
-				} else {
-					let mut target = try!(fs::File::create(p));
-					try!(io::copy(&mut file, &mut target));
-				}
-			}
+		// Unpack archive, leaving nothing behind if it fails half-way.
+		try!(fs::create_dir_all(&target));
+		let unpacked = match zip_archive {
+			Some(ref mut zip_archive) => self.unpack_zip(zip_archive, &manifest_dir, &target),
+			None => self.unpack_tar_gz(&mut file, &manifest_dir, &target),
+		};
+		if let Err(e) = unpacked {
+			let _ = fs::remove_dir_all(&target);
+			return Err(e);
 		}
 
 		// Write manifest
@@ -274,12 +417,23 @@ impl DappHandler for DappInstaller {
 
 #[cfg(test)]
 mod tests {
-	use std::path::PathBuf;
-	use super::{AppFetcher, AppStatus};
+	use std::{env, fs};
+	use std::io::{Cursor, Read, Write};
+	use std::path::{Path, PathBuf};
+	use std::sync::Arc;
+	use std::collections::HashMap;
+	use flate2::Compression;
+	use flate2::write::GzEncoder;
+	use tar;
+	use zip;
+	use super::{AppFetcher, AppStatus, BundleFormat, DappInstaller, ValidationError, location_in_dapp};
 	use apps::urlhint::{GithubApp, URLHint};
 	use endpoint::EndpointInfo;
+	use handlers::DappHandler;
 	use page::LocalPageEndpoint;
-	use util::Bytes;
+	use random_filename;
+	use util::{Bytes, Mutex};
+	use util::sha3::Hashable;
 
 	struct FakeResolver;
 	impl URLHint for FakeResolver {
@@ -309,5 +463,164 @@ mod tests {
 		assert_eq!(fetcher.contains("test2"), true);
 		assert_eq!(fetcher.contains("test3"), false);
 	}
-}
 
+	// writes a tar.gz bundle with given files into a temporary directory.
+	fn tar_gz_bundle(dir: &PathBuf, files: &[(&str, &[u8])]) -> (PathBuf, String) {
+		let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::Default));
+		for &(path, content) in files {
+			let mut header = tar::Header::new_gnu();
+			header.set_path(path).unwrap();
+			header.set_size(content.len() as u64);
+			header.set_mode(0o644);
+			header.set_cksum();
+			builder.append(&header, content).unwrap();
+		}
+		let bundle = builder.into_inner().unwrap().finish().unwrap();
+
+		let path = dir.join("bundle.tar.gz");
+		fs::File::create(&path).unwrap().write_all(&bundle).unwrap();
+		(path, bundle.sha3().hex())
+	}
+
+	// writes a zip bundle with given files into a temporary directory.
+	fn zip_bundle(dir: &PathBuf, files: &[(&str, &[u8])]) -> (PathBuf, String) {
+		let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+		for &(path, content) in files {
+			writer.start_file(path, zip::CompressionMethod::Stored).unwrap();
+			writer.write_all(content).unwrap();
+		}
+		let bundle = writer.finish().unwrap().into_inner();
+
+		let path = dir.join("bundle.zip");
+		fs::File::create(&path).unwrap().write_all(&bundle).unwrap();
+		(path, bundle.sha3().hex())
+	}
+
+	fn installer(dir: &PathBuf, dapp_id: String, max_extracted_size: u64) -> DappInstaller {
+		DappInstaller {
+			dapp_id: dapp_id,
+			dapps_path: dir.join("dapps"),
+			dapps: Arc::new(Mutex::new(HashMap::new())),
+			max_extracted_size: max_extracted_size,
+		}
+	}
+
+	const MANIFEST: &'static [u8] = br#"{"id":"test","name":"Test","description":"","version":"0.1","author":"","iconUrl":"icon.png"}"#;
+
+	#[test]
+	fn should_detect_bundle_format() {
+		assert_eq!(BundleFormat::detect(b"PK\x03\x04rest"), Some(BundleFormat::Zip));
+		assert_eq!(BundleFormat::detect(&[0x1f, 0x8b, 0x08, 0x00]), Some(BundleFormat::TarGz));
+		assert_eq!(BundleFormat::detect(b"<html>"), None);
+		assert_eq!(BundleFormat::detect(&[]), None);
+	}
+
+	#[test]
+	fn should_install_tar_gz_bundle() {
+		// given
+		let mut dir = env::temp_dir();
+		dir.push(random_filename());
+		fs::create_dir_all(&dir).unwrap();
+		let (bundle, hash) = tar_gz_bundle(&dir, &[
+			("app/manifest.json", MANIFEST),
+			("app/index.html", b"<html></html>"),
+			("app/js/app.js", b"alert(1);"),
+		]);
+
+		// when
+		let result = installer(&dir, hash.clone(), 1024).validate_and_install(bundle);
+
+		// then
+		let manifest = result.unwrap();
+		assert_eq!(manifest.id, hash);
+		let mut index = String::new();
+		fs::File::open(dir.join("dapps").join(&hash).join("index.html")).unwrap().read_to_string(&mut index).unwrap();
+		assert_eq!(index, "<html></html>");
+		assert!(dir.join("dapps").join(&hash).join("js").join("app.js").exists());
+		let _ = fs::remove_dir_all(&dir);
+	}
+
+	#[test]
+	fn should_reject_too_large_tar_gz_bundle() {
+		// given
+		let mut dir = env::temp_dir();
+		dir.push(random_filename());
+		fs::create_dir_all(&dir).unwrap();
+		// compresses to almost nothing.
+		let zeros = vec![0u8; 64 * 1024];
+		let (bundle, hash) = tar_gz_bundle(&dir, &[
+			("app/manifest.json", MANIFEST),
+			("app/index.html", &zeros),
+		]);
+
+		// when
+		let result = installer(&dir, hash.clone(), 1024).validate_and_install(bundle);
+
+		// then
+		match result {
+			Err(ValidationError::TooLarge { limit: 1024 }) => {},
+			other => panic!("unexpected result: {:?}", other),
+		}
+		assert!(!dir.join("dapps").join(&hash).exists());
+		let _ = fs::remove_dir_all(&dir);
+	}
+
+	#[test]
+	fn should_reject_too_large_zip_bundle() {
+		// given
+		let mut dir = env::temp_dir();
+		dir.push(random_filename());
+		fs::create_dir_all(&dir).unwrap();
+		let zeros = vec![0u8; 64 * 1024];
+		let (bundle, hash) = zip_bundle(&dir, &[
+			("app/manifest.json", MANIFEST),
+			("app/index.html", &zeros),
+		]);
+
+		// when
+		let result = installer(&dir, hash.clone(), 1024).validate_and_install(bundle);
+
+		// then
+		match result {
+			Err(ValidationError::TooLarge { limit: 1024 }) => {},
+			other => panic!("unexpected result: {:?}", other),
+		}
+		assert!(!dir.join("dapps").join(&hash).exists());
+		let _ = fs::remove_dir_all(&dir);
+	}
+
+	#[test]
+	fn should_never_locate_files_outside_of_dapp() {
+		let root = Path::new("");
+		assert_eq!(location_in_dapp(root, Path::new("index.html")), Some(PathBuf::from("index.html")));
+		assert_eq!(location_in_dapp(root, Path::new("./js/app.js")), Some(PathBuf::from("./js/app.js")));
+		assert_eq!(location_in_dapp(root, Path::new("/tmp/evil")), None);
+		assert_eq!(location_in_dapp(root, Path::new("../evil")), None);
+		assert_eq!(location_in_dapp(Path::new("app"), Path::new("app/../../evil")), None);
+		assert_eq!(location_in_dapp(Path::new("app"), Path::new("other/index.html")), None);
+	}
+
+	#[test]
+	fn should_not_write_absolute_zip_entries() {
+		// given
+		let mut dir = env::temp_dir();
+		dir.push(random_filename());
+		fs::create_dir_all(&dir).unwrap();
+		let outside = dir.join("outside.html");
+		let outside_name = outside.to_string_lossy().into_owned();
+		let (bundle, hash) = zip_bundle(&dir, &[
+			("manifest.json", MANIFEST),
+			("index.html", b"<html></html>"),
+			(&outside_name, b"evil"),
+		]);
+
+		// when
+		let result = installer(&dir, hash.clone(), 1024).validate_and_install(bundle);
+
+		// then
+		assert!(result.is_ok());
+		assert!(dir.join("dapps").join(&hash).join("index.html").exists());
+		assert!(!outside.exists());
+		let _ = fs::remove_dir_all(&dir);
+	}
+}
@@ -14,7 +14,12 @@
 // You should have received a copy of the GNU General Public License
 // along with Parity.  If not, see <http://www.gnu.org/licenses/>.
 
+use std::io;
+use std::fs;
+use std::path::Path;
+use std::collections::BTreeMap;
 use serde_json;
+use util::H256;
 pub use api::App as Manifest;
 
 pub const MANIFEST_FILENAME: &'static str = "manifest.json";
@@ -27,3 +32,37 @@ pub fn deserialize_manifest(manifest: String) -> Result<Manifest, String> {
 pub fn serialize_manifest(manifest: &Manifest) -> Result<String, String> {
 	serde_json::to_string_pretty(manifest).map_err(|e| format!("{:?}", e))
 }
+
+/// Collects Keccak-256 hashes of all assets of a dapp located at `dapp_path`,
+/// each computed by `hash_file` from the asset's path.
+/// Assets are keyed by their path relative to the dapp root (`/`-separated),
+/// the manifest file itself is skipped.
+pub fn asset_hashes(dapp_path: &Path, hash_file: &Fn(&Path) -> io::Result<H256>) -> io::Result<BTreeMap<String, String>> {
+	let mut hashes = BTreeMap::new();
+	try!(hash_dir(dapp_path, "", hash_file, &mut hashes));
+	hashes.remove(MANIFEST_FILENAME);
+	Ok(hashes)
+}
+
+fn hash_dir(dir: &Path, prefix: &str, hash_file: &Fn(&Path) -> io::Result<H256>, hashes: &mut BTreeMap<String, String>) -> io::Result<()> {
+	for entry in try!(fs::read_dir(dir)) {
+		let entry = try!(entry);
+		let name = match entry.file_name().into_string() {
+			Ok(name) => name,
+			Err(name) => {
+				debug!(target: "dapps", "Skipping asset with invalid name: {:?}", name);
+				continue;
+			}
+		};
+		let name = format!("{}{}", prefix, name);
+
+		if try!(entry.file_type()).is_dir() {
+			try!(hash_dir(&entry.path(), &format!("{}/", name), hash_file, hashes));
+		} else {
+			let hash = try!(hash_file(&entry.path()));
+			hashes.insert(name, format!("0x{}", hash.hex()));
+		}
+	}
+	Ok(())
+}
+
@@ -14,8 +14,9 @@
 // You should have received a copy of the GNU General Public License
 // along with Parity.  If not, see <http://www.gnu.org/licenses/>.
 
+use std::sync::Arc;
 use endpoint::{Endpoints, Endpoint};
-use page::PageEndpoint;
+use page::{PageEndpoint, ZippedPageEndpoint};
 use proxypac::ProxyPac;
 use parity_dapps::WebApp;
 
@@ -47,9 +48,19 @@ pub fn utils() -> Box<Endpoint> {
 	Box::new(PageEndpoint::with_prefix(parity_dapps_home::App::default(), UTILS_PATH.to_owned()))
 }
 
-pub fn all_endpoints(dapps_path: String) -> Endpoints {
+/// Dapps served out of zip archives kept in memory, keyed by their ids.
+pub type ZippedDapps = Vec<(String, Arc<Vec<u8>>)>;
+
+/// All dapps to serve. Dapps found in `dapps_path` are included only if it's given.
+pub fn all_endpoints(dapps_path: Option<String>, zipped_dapps: &ZippedDapps) -> Endpoints {
 	// fetch fs dapps at first to avoid overwriting builtins
-	let mut pages = fs::local_endpoints(dapps_path);
+	let mut pages = match dapps_path {
+		Some(dapps_path) => fs::local_endpoints(dapps_path),
+		None => Endpoints::new(),
+	};
+	for &(ref id, ref archive) in zipped_dapps {
+		pages.insert(id.clone(), Box::new(ZippedPageEndpoint::new(id, archive.clone())));
+	}
 	// Home page needs to be safe embed
 	// because we use Cross-Origin LocalStorage.
 	// TODO [ToDr] Account naming should be moved to parity.
@@ -18,6 +18,8 @@
 
 use hyper::{server, net};
 use std::collections::BTreeMap;
+use std::sync::Arc;
+use util::RwLock;
 
 #[derive(Debug, PartialEq, Default, Clone)]
 pub struct EndpointPath {
@@ -39,6 +41,27 @@ pub struct EndpointInfo {
 pub type Endpoints = BTreeMap<String, Box<Endpoint>>;
 pub type Handler = server::Handler<net::HttpStream> + Send;
 
+/// Endpoints shared by all server threads, which can be replaced while serving.
+#[derive(Clone)]
+pub struct SharedEndpoints(Arc<RwLock<Arc<Endpoints>>>);
+
+impl SharedEndpoints {
+	pub fn new(endpoints: Endpoints) -> Self {
+		SharedEndpoints(Arc::new(RwLock::new(Arc::new(endpoints))))
+	}
+
+	/// Endpoints served at the moment. Later replacements don't affect the returned map,
+	/// so a request is resolved against a single consistent set.
+	pub fn current(&self) -> Arc<Endpoints> {
+		self.0.read().clone()
+	}
+
+	/// Atomically replace all endpoints.
+	pub fn replace(&self, endpoints: Endpoints) {
+		*self.0.write() = Arc::new(endpoints);
+	}
+}
+
 pub trait Endpoint : Send + Sync {
 	fn info(&self) -> Option<&EndpointInfo> { None }
 
@@ -0,0 +1,126 @@
+// Copyright 2015, 2016 Ethcore (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Errors encountered while serving requests and their HTTP responses.
+
+use std::fmt;
+use hyper::status::StatusCode;
+use handlers::ContentHandler;
+
+/// Error serving a request.
+#[derive(Debug)]
+pub enum DappsError {
+	/// No dapp or special endpoint (or a disabled one) was requested.
+	UnknownEndpoint,
+	/// Request with a disallowed `Host` header.
+	InvalidHost,
+	/// Invalid credentials were given.
+	Unauthorized,
+	/// Request method not supported by the endpoint.
+	MethodNotAllowed,
+	/// Requested dapp is already being fetched.
+	FetchInProgress,
+	/// Dapp download couldn't be started.
+	FetchStart(String),
+	/// Dapp download failed.
+	Fetch,
+	/// Dapp wasn't downloaded within given number of seconds.
+	FetchTimeout(u64),
+	/// Downloaded bundle couldn't be extracted or doesn't contain a valid dapp.
+	InvalidBundle(String),
+}
+
+impl DappsError {
+	/// Status code of the response sent for this error.
+	pub fn status_code(&self) -> StatusCode {
+		match *self {
+			DappsError::UnknownEndpoint => StatusCode::NotFound,
+			DappsError::InvalidHost => StatusCode::Forbidden,
+			DappsError::Unauthorized => StatusCode::Unauthorized,
+			DappsError::MethodNotAllowed => StatusCode::MethodNotAllowed,
+			DappsError::FetchInProgress => StatusCode::ServiceUnavailable,
+			DappsError::FetchStart(_) | DappsError::Fetch | DappsError::InvalidBundle(_) => StatusCode::BadGateway,
+			DappsError::FetchTimeout(_) => StatusCode::GatewayTimeout,
+		}
+	}
+
+	/// Handler sending an HTML page describing this error.
+	pub fn to_handler(&self) -> ContentHandler {
+		ContentHandler::html(self.status_code(), self.to_html())
+	}
+
+	fn to_html(&self) -> String {
+		match *self {
+			DappsError::UnknownEndpoint => "<h1>404 Not Found</h1>".into(),
+			DappsError::InvalidHost => r#"
+		<h1>Request with disallowed <code>Host</code> header has been blocked.</h1>
+		<p>Check the URL in your browser address bar.</p>
+		"#.into(),
+			DappsError::Unauthorized => "<h1>Unauthorized</h1>".into(),
+			DappsError::MethodNotAllowed => "<h1>Only <code>GET</code> requests are allowed.</h1>".into(),
+			DappsError::FetchInProgress => format!(
+				"<html><head>{}</head><body>{}</body></html>",
+				"<meta http-equiv=\"refresh\" content=\"1\">",
+				"<h1>This dapp is already being downloaded.</h1><h2>Please wait...</h2>",
+			),
+			DappsError::FetchStart(ref e) => format!("<h1>Error starting dapp download.</h1><pre>{}</pre>", e),
+			DappsError::Fetch => "<h1>There was an error when fetching the dapp.</h1>".into(),
+			DappsError::FetchTimeout(secs) => format!("<h1>Could not fetch app bundle within {} seconds.</h1>", secs),
+			DappsError::InvalidBundle(ref e) => format!("<h1>Downloaded bundle does not contain valid app.</h1><pre>{}</pre>", e),
+		}
+	}
+}
+
+impl fmt::Display for DappsError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			DappsError::UnknownEndpoint => write!(f, "Unknown endpoint"),
+			DappsError::InvalidHost => write!(f, "Disallowed Host header"),
+			DappsError::Unauthorized => write!(f, "Unauthorized"),
+			DappsError::MethodNotAllowed => write!(f, "Method not allowed"),
+			DappsError::FetchInProgress => write!(f, "Dapp is already being fetched"),
+			DappsError::FetchStart(ref e) => write!(f, "Error starting dapp download: {}", e),
+			DappsError::Fetch => write!(f, "Error fetching dapp"),
+			DappsError::FetchTimeout(secs) => write!(f, "Dapp not fetched within {} seconds", secs),
+			DappsError::InvalidBundle(ref e) => write!(f, "Invalid dapp bundle: {}", e),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use hyper::status::StatusCode;
+	use super::DappsError;
+
+	#[test]
+	fn should_map_errors_to_status_codes() {
+		assert_eq!(DappsError::UnknownEndpoint.status_code(), StatusCode::NotFound);
+		assert_eq!(DappsError::InvalidHost.status_code(), StatusCode::Forbidden);
+		assert_eq!(DappsError::Unauthorized.status_code(), StatusCode::Unauthorized);
+		assert_eq!(DappsError::MethodNotAllowed.status_code(), StatusCode::MethodNotAllowed);
+		assert_eq!(DappsError::FetchInProgress.status_code(), StatusCode::ServiceUnavailable);
+		assert_eq!(DappsError::FetchStart("refused".into()).status_code(), StatusCode::BadGateway);
+		assert_eq!(DappsError::Fetch.status_code(), StatusCode::BadGateway);
+		assert_eq!(DappsError::FetchTimeout(30).status_code(), StatusCode::GatewayTimeout);
+		assert_eq!(DappsError::InvalidBundle("ManifestNotFound".into()).status_code(), StatusCode::BadGateway);
+	}
+
+	#[test]
+	fn should_describe_fetch_timeout() {
+		assert_eq!(format!("{}", DappsError::FetchTimeout(30)), "Dapp not fetched within 30 seconds");
+		assert!(DappsError::FetchTimeout(30).to_html().contains("within 30 seconds"));
+	}
+}
@@ -20,7 +20,7 @@ use std::{env, io, fs, fmt};
 use std::path::PathBuf;
 use std::sync::mpsc;
 use std::time::Duration;
-use random_filename;
+use random_filename_checked;
 
 use hyper::status::StatusCode;
 use hyper::client::{Request, Response, DefaultTransport as HttpStream};
@@ -72,17 +72,17 @@ impl Drop for Fetch {
 }
 
 impl Fetch {
-	pub fn new(sender: mpsc::Sender<FetchResult>, on_done: OnDone) -> Self {
+	pub fn new(sender: mpsc::Sender<FetchResult>, on_done: OnDone) -> io::Result<Self> {
 		let mut dir = env::temp_dir();
-		dir.push(random_filename());
+		dir.push(try!(random_filename_checked()));
 
-		Fetch {
+		Ok(Fetch {
 			path: dir,
 			file: None,
 			result: None,
 			sender: sender,
 			on_done: Some(on_done),
-		}
+		})
 	}
 }
 
@@ -25,6 +25,7 @@ use hyper::{header, server, Decoder, Encoder, Next, Method, Control, Client};
 use hyper::net::HttpStream;
 use hyper::status::StatusCode;
 
+use error::DappsError;
 use handlers::ContentHandler;
 use handlers::client::{Fetch, FetchResult};
 use apps::redirection_address;
@@ -99,11 +100,12 @@ impl<H: DappHandler> AppFetcherHandler<H> {
 		trace!(target: "dapps", "Fetching from: {:?}", url);
 
 		let (tx, rx) = mpsc::channel();
-		let res = client.request(url, Fetch::new(tx, Box::new(move || {
+		let fetch = try!(Fetch::new(tx, Box::new(move || {
 			trace!(target: "dapps", "Fetching finished.");
 			// Ignoring control errors
 			let _ = control.ready(Next::read());
-		})));
+		})).map_err(|e| format!("{:?}", e)));
+		let res = client.request(url, fetch);
 		match res {
 			Ok(_) => Ok(rx),
 			Err(e) => Err(format!("{:?}", e)),
@@ -126,17 +128,11 @@ impl<H: DappHandler> server::Handler<HttpStream> for AppFetcherHandler<H> {
 							deadline: Instant::now() + Duration::from_secs(FETCH_TIMEOUT),
 							receiver: receiver,
 						},
-						Err(e) => FetchState::Error(ContentHandler::html(
-							StatusCode::BadGateway,
-							format!("<h1>Error starting dapp download.</h1><pre>{}</pre>", e),
-						)),
+						Err(e) => FetchState::Error(DappsError::FetchStart(e).to_handler()),
 					}
 				},
 				// or return error
-				_ => FetchState::Error(ContentHandler::html(
-					StatusCode::MethodNotAllowed,
-					"<h1>Only <code>GET</code> requests are allowed.</h1>".into(),
-				)),
+				_ => FetchState::Error(DappsError::MethodNotAllowed.to_handler()),
 			})
 		} else { None };
 
@@ -152,10 +148,7 @@ impl<H: DappHandler> server::Handler<HttpStream> for AppFetcherHandler<H> {
 			// Request may time out
 			FetchState::InProgress { ref deadline, .. } if *deadline < Instant::now() => {
 				trace!(target: "dapps", "Fetching dapp failed because of timeout.");
-				let timeout = ContentHandler::html(
-					StatusCode::GatewayTimeout,
-					format!("<h1>Could not fetch app bundle within {} seconds.</h1>", FETCH_TIMEOUT),
-					);
+				let timeout = DappsError::FetchTimeout(FETCH_TIMEOUT).to_handler();
 				Self::close_client(&mut self.client);
 				(Some(FetchState::Error(timeout)), Next::write())
 			},
@@ -171,10 +164,7 @@ impl<H: DappHandler> server::Handler<HttpStream> for AppFetcherHandler<H> {
 						let state = match self.dapp.validate_and_install(path.clone()) {
 							Err(e) => {
 								trace!(target: "dapps", "Error while validating dapp: {:?}", e);
-								FetchState::Error(ContentHandler::html(
-									StatusCode::BadGateway,
-									format!("<h1>Downloaded bundle does not contain valid app.</h1><pre>{:?}</pre>", e),
-								))
+								FetchState::Error(DappsError::InvalidBundle(format!("{:?}", e)).to_handler())
 							},
 							Ok(manifest) => FetchState::Done(manifest)
 						};
@@ -184,10 +174,7 @@ impl<H: DappHandler> server::Handler<HttpStream> for AppFetcherHandler<H> {
 					},
 					Ok(Err(e)) => {
 						warn!(target: "dapps", "Unable to fetch new dapp: {:?}", e);
-						let error = ContentHandler::html(
-							StatusCode::BadGateway,
-							"<h1>There was an error when fetching the dapp.</h1>".into(),
-						);
+						let error = DappsError::Fetch.to_handler();
 						(Some(FetchState::Error(error)), Next::write())
 					},
 					// wait some more
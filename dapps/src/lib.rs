@@ -51,6 +51,8 @@ extern crate unicase;
 extern crate serde;
 extern crate serde_json;
 extern crate zip;
+extern crate tar;
+extern crate flate2;
 extern crate rand;
 extern crate ethabi;
 extern crate jsonrpc_core;
@@ -62,6 +64,7 @@ extern crate ethcore_rpc;
 extern crate ethcore_util as util;
 
 mod endpoint;
+mod error;
 mod apps;
 mod page;
 mod router;
@@ -71,7 +74,10 @@ mod api;
 mod proxypac;
 mod url;
 
+pub use self::api::to_checksum;
 pub use self::apps::urlhint::ContractClient;
+pub use self::router::ProxyMode;
+pub use self::router::auth::{AuthLogger, AuthRecord, AuthOutcome, LogAuthLogger};
 
 use std::sync::{Arc, Mutex};
 use std::net::SocketAddr;
@@ -83,9 +89,57 @@ use ethcore_rpc::Extendable;
 
 static DAPPS_DOMAIN : &'static str = ".parity";
 
+/// Webapps server configuration.
+/// Every setting has a counterpart on `ServerBuilder`; use `ServerBuilder::with_config`
+/// to set them all at once.
+#[derive(Clone)]
+pub struct DappsConfig {
+	/// Directory to discover local dapps in (none by default).
+	pub dapps_path: String,
+	/// Whether to serve dapps found in `dapps_path` (enabled by default).
+	/// Has no effect while `dapps_path` is empty.
+	pub fs_dapps_enabled: bool,
+	/// Dapps served out of in-memory zip archives, by id. See `ServerBuilder::zipped_dapp`.
+	pub zipped_dapps: Vec<(String, Arc<Vec<u8>>)>,
+	/// Id of the dapp the root of the server redirects to (`home` by default).
+	pub landing_page: String,
+	/// Number of threads accepting and handling connections (one by default).
+	pub threads: usize,
+	/// How to treat requests forwarded by a reverse proxy.
+	pub proxy_mode: ProxyMode,
+	/// Whether to expose the JSON-RPC endpoint (enabled by default).
+	pub rpc_enabled: bool,
+	/// Whether to expose the dapps introspection API under `/api/` (enabled by default).
+	pub api_enabled: bool,
+	/// Username and password for `HTTP Basic Authentication` of servers started with
+	/// `ServerBuilder::start_http`. `None` (the default) requires no authentication.
+	pub credentials: Option<(String, String)>,
+	/// Logger for authorization attempts (none by default).
+	pub auth_logger: Option<Arc<AuthLogger>>,
+}
+
+impl Default for DappsConfig {
+	fn default() -> Self {
+		DappsConfig {
+			dapps_path: String::new(),
+			fs_dapps_enabled: true,
+			zipped_dapps: Vec::new(),
+			landing_page: apps::main_page().into(),
+			threads: 1,
+			proxy_mode: ProxyMode::Disabled,
+			rpc_enabled: true,
+			api_enabled: true,
+			credentials: None,
+			auth_logger: None,
+		}
+	}
+}
+
 /// Webapps HTTP+RPC server build.
+/// Clones share the RPC handler, so servers started from them expose the same APIs.
+#[derive(Clone)]
 pub struct ServerBuilder {
-	dapps_path: String,
+	config: DappsConfig,
 	handler: Arc<IoHandler>,
 	registrar: Arc<ContractClient>,
 }
@@ -97,77 +151,305 @@ impl Extendable for ServerBuilder {
 }
 
 impl ServerBuilder {
-	/// Construct new dapps server
+	/// Construct new dapps server serving local dapps from given directory.
 	pub fn new(dapps_path: String, registrar: Arc<ContractClient>) -> Self {
-		ServerBuilder {
+		ServerBuilder::with_config(DappsConfig {
 			dapps_path: dapps_path,
+			..Default::default()
+		}, registrar)
+	}
+
+	/// Construct new dapps server with given configuration.
+	pub fn with_config(config: DappsConfig, registrar: Arc<ContractClient>) -> Self {
+		ServerBuilder {
+			config: config,
 			handler: Arc::new(IoHandler::new()),
 			registrar: registrar,
 		}
 	}
 
+	/// Whether to expose the JSON-RPC endpoint (enabled by default).
+	/// When disabled, RPC requests are answered with `404 Not Found`.
+	pub fn rpc_enabled(mut self, enabled: bool) -> Self {
+		self.config.rpc_enabled = enabled;
+		self
+	}
+
+	/// Whether to expose the dapps introspection API under `/api/` (enabled by default).
+	/// When disabled, API requests are answered with `404 Not Found`.
+	pub fn api_enabled(mut self, enabled: bool) -> Self {
+		self.config.api_enabled = enabled;
+		self
+	}
+
+	/// Whether to serve dapps found in the dapps directory (enabled by default).
+	/// When disabled, only builtin dapps and dapps fetched from the registry are served.
+	pub fn fs_dapps_enabled(mut self, enabled: bool) -> Self {
+		self.config.fs_dapps_enabled = enabled;
+		self
+	}
+
+	/// Serve a dapp out of given zip archive under given id. The archive is kept in memory
+	/// and files are extracted from it when requested. Replaces a dapp with the same id
+	/// found in the dapps directory; builtin dapps can't be replaced.
+	pub fn zipped_dapp(mut self, app_id: String, archive: Vec<u8>) -> Self {
+		self.config.zipped_dapps.push((app_id, Arc::new(archive)));
+		self
+	}
+
+	/// Report authorization attempts of servers started with `HTTP Basic Authentication`
+	/// to given logger (none are reported by default).
+	pub fn log_auth_attempts(mut self, logger: Arc<AuthLogger>) -> Self {
+		self.config.auth_logger = Some(logger);
+		self
+	}
+
+	/// Id of the dapp the root of the server redirects to (`home` by default).
+	/// Starting the server fails if no such dapp is available.
+	pub fn landing_page(mut self, app_id: String) -> Self {
+		self.config.landing_page = app_id;
+		self
+	}
+
+	/// Number of threads accepting and handling connections (one by default).
+	pub fn threads(mut self, threads: usize) -> Self {
+		self.config.threads = ::std::cmp::max(threads, 1);
+		self
+	}
+
+	/// Resolve dapps as configured by given `ProxyMode`,
+	/// e.g. to run the server behind a reverse proxy.
+	pub fn with_proxy_mode(mut self, proxy_mode: ProxyMode) -> Self {
+		self.config.proxy_mode = proxy_mode;
+		self
+	}
+
+	/// Configuration servers are started with.
+	pub fn config(&self) -> &DappsConfig {
+		&self.config
+	}
+
+	/// Asynchronously start server, with `HTTP Basic Authentication` if credentials are configured,
+	/// returns result with `Server` handle on success or an error.
+	pub fn start_http(&self, addr: &SocketAddr) -> Result<Server, ServerError> {
+		self.prepare_http(addr).map(Server::spawn)
+	}
+
 	/// Asynchronously start server with no authentication,
 	/// returns result with `Server` handle on success or an error.
 	pub fn start_unsecure_http(&self, addr: &SocketAddr) -> Result<Server, ServerError> {
-		Server::start_http(addr, NoAuth, self.handler.clone(), self.dapps_path.clone(), self.registrar.clone())
+		self.prepare(addr).map(Server::spawn)
 	}
 
 	/// Asynchronously start server with `HTTP Basic Authentication`,
 	/// return result with `Server` handle on success or an error.
 	pub fn start_basic_auth_http(&self, addr: &SocketAddr, username: &str, password: &str) -> Result<Server, ServerError> {
-		Server::start_http(addr, HttpBasicAuth::single_user(username, password), self.handler.clone(), self.dapps_path.clone(), self.registrar.clone())
+		self.prepare_basic_auth(addr, username, password).map(Server::spawn)
+	}
+
+	/// Bind server, with `HTTP Basic Authentication` if credentials are configured,
+	/// without serving any requests yet. See `prepare`.
+	pub fn prepare_http(&self, addr: &SocketAddr) -> Result<(Server, ServerLoop), ServerError> {
+		match self.config.credentials {
+			Some((ref username, ref password)) => self.prepare_basic_auth(addr, username, password),
+			None => self.prepare(addr),
+		}
+	}
+
+	/// Bind server with no authentication without serving any requests yet.
+	/// Returns the `Server` handle and a `ServerLoop` which the caller should `run`
+	/// on a thread of its choice.
+	pub fn prepare(&self, addr: &SocketAddr) -> Result<(Server, ServerLoop), ServerError> {
+		self.prepare_with_auth(addr, NoAuth)
+	}
+
+	/// Bind server with `HTTP Basic Authentication` without serving any requests yet.
+	/// See `prepare`.
+	pub fn prepare_basic_auth(&self, addr: &SocketAddr, username: &str, password: &str) -> Result<(Server, ServerLoop), ServerError> {
+		let mut authorization = HttpBasicAuth::single_user(username, password);
+		if let Some(ref logger) = self.config.auth_logger {
+			authorization = authorization.with_logger(logger.clone());
+		}
+		self.prepare_with_auth(addr, authorization)
+	}
+
+	fn prepare_with_auth<A: Authorization + 'static>(&self, addr: &SocketAddr, authorization: A) -> Result<(Server, ServerLoop), ServerError> {
+		let config = &self.config;
+		Server::prepare_http(
+			addr,
+			authorization,
+			self.handler.clone(),
+			self.dapps_path(),
+			config.zipped_dapps.clone(),
+			self.registrar.clone(),
+			config.proxy_mode,
+			::std::cmp::max(config.threads, 1),
+			config.landing_page.clone(),
+			self.special_endpoints(),
+		)
+	}
+
+	// directory to discover local dapps in, if enabled and set.
+	fn dapps_path(&self) -> Option<String> {
+		match self.config.fs_dapps_enabled && !self.config.dapps_path.is_empty() {
+			true => Some(self.config.dapps_path.clone()),
+			false => None,
+		}
+	}
+
+	// special endpoints to register.
+	fn special_endpoints(&self) -> Vec<router::SpecialEndpoint> {
+		let mut special = vec![router::SpecialEndpoint::Utils];
+		if self.config.rpc_enabled {
+			special.push(router::SpecialEndpoint::Rpc);
+		}
+		if self.config.api_enabled {
+			special.push(router::SpecialEndpoint::Api);
+		}
+		special
+	}
+}
+
+/// Event loop of a bound webapps server.
+pub struct ServerLoop {
+	run: Box<FnMut() + Send>,
+}
+
+impl ServerLoop {
+	/// Serve requests on the current thread.
+	/// Blocks until the corresponding `Server` is closed.
+	pub fn run(mut self) {
+		(self.run)()
 	}
 }
 
 /// Webapps HTTP server.
 pub struct Server {
-	server: Option<hyper::server::Listening>,
+	servers: Vec<hyper::server::Listening>,
 	panic_handler: Arc<Mutex<Option<Box<Fn() -> () + Send>>>>,
+	endpoints: endpoint::SharedEndpoints,
+	dapps_path: Option<String>,
+	zipped_dapps: apps::ZippedDapps,
+	landing_page: String,
 }
 
 impl Server {
-	fn start_http<A: Authorization + 'static>(
+	fn spawn((server, server_loop): (Server, ServerLoop)) -> Server {
+		::std::thread::spawn(move || server_loop.run());
+		server
+	}
+
+	fn prepare_http<A: Authorization + 'static>(
 		addr: &SocketAddr,
 		authorization: A,
 		handler: Arc<IoHandler>,
-		dapps_path: String,
+		dapps_path: Option<String>,
+		zipped_dapps: apps::ZippedDapps,
 		registrar: Arc<ContractClient>,
-	) -> Result<Server, ServerError> {
+		proxy_mode: ProxyMode,
+		threads: usize,
+		landing_page: String,
+		special_endpoints: Vec<router::SpecialEndpoint>,
+	) -> Result<(Server, ServerLoop), ServerError> {
 		let panic_handler = Arc::new(Mutex::new(None));
 		let authorization = Arc::new(authorization);
 		let apps_fetcher = Arc::new(apps::fetcher::AppFetcher::new(apps::urlhint::URLHintContract::new(registrar)));
-		let endpoints = Arc::new(apps::all_endpoints(dapps_path));
+		let endpoints = apps::all_endpoints(dapps_path.clone(), &zipped_dapps);
+		if !endpoints.contains_key(&landing_page) {
+			return Err(ServerError::UnknownLandingPage(landing_page));
+		}
+		let endpoints = endpoint::SharedEndpoints::new(endpoints);
+
 		let special = Arc::new({
 			let mut special = HashMap::new();
-			special.insert(router::SpecialEndpoint::Rpc, rpc::rpc(handler, panic_handler.clone()));
-			special.insert(router::SpecialEndpoint::Api, api::RestApi::new(format!("{}", addr), endpoints.clone()));
-			special.insert(router::SpecialEndpoint::Utils, apps::utils());
+			for kind in special_endpoints {
+				let special_endpoint: Box<endpoint::Endpoint> = match kind {
+					router::SpecialEndpoint::Rpc => rpc::rpc(handler.clone(), panic_handler.clone()),
+					router::SpecialEndpoint::Api => api::RestApi::new(format!("{}", addr), endpoints.clone()),
+					router::SpecialEndpoint::Utils => api::Utils::new(apps::utils()),
+					router::SpecialEndpoint::None => continue,
+				};
+				special.insert(kind, special_endpoint);
+			}
 			special
 		});
 		let bind_address = format!("{}", addr);
 
-		try!(hyper::Server::http(addr))
-			.handle(move |ctrl| router::Router::new(
-				ctrl,
-				apps::main_page(),
-				apps_fetcher.clone(),
-				endpoints.clone(),
-				special.clone(),
-				authorization.clone(),
-				bind_address.clone(),
-			))
-			.map(|(l, srv)| {
-
-				::std::thread::spawn(move || {
-					srv.run();
-				});
+		// every thread runs its own server loop accepting on a clone of the same listener.
+		let listener = try!(hyper::net::HttpListener::bind(addr));
+		let mut listening = Vec::with_capacity(threads);
+		let mut loops = Vec::with_capacity(threads);
+		for _ in 0..threads {
+			let apps_fetcher = apps_fetcher.clone();
+			let endpoints = endpoints.clone();
+			let special = special.clone();
+			let authorization = authorization.clone();
+			let bind_address = bind_address.clone();
+			let landing_page = landing_page.clone();
+
+			let (l, srv) = try!(hyper::Server::new(try!(listener.try_clone()))
+				.handle(move |ctrl| router::Router::new(
+					ctrl,
+					landing_page.clone(),
+					apps_fetcher.clone(),
+					endpoints.current(),
+					special.clone(),
+					authorization.clone(),
+					bind_address.clone(),
+					proxy_mode,
+				)));
+			listening.push(l);
+			loops.push(srv);
+		}
+
+		let server_loop = ServerLoop {
+			run: Box::new(move || {
+				let mut loops = ::std::mem::replace(&mut loops, Vec::new());
+				let main = loops.pop();
+				let workers: Vec<_> = loops.into_iter()
+					.map(|srv| ::std::thread::spawn(move || srv.run()))
+					.collect();
 
-				Server {
-					server: Some(l),
-					panic_handler: panic_handler,
+				if let Some(srv) = main {
+					srv.run();
+				}
+				for worker in workers {
+					let _ = worker.join();
 				}
-			})
-			.map_err(ServerError::from)
+			}),
+		};
+
+		Ok((Server {
+			servers: listening,
+			panic_handler: panic_handler,
+			endpoints: endpoints,
+			dapps_path: dapps_path,
+			zipped_dapps: zipped_dapps,
+			landing_page: landing_page,
+		}, server_loop))
+	}
+
+	/// Ids of all dapps served, both builtin and found in the dapps directory (if enabled).
+	pub fn endpoint_ids(&self) -> Vec<String> {
+		self.endpoints.current().keys().cloned().collect()
+	}
+
+	/// Discover dapps in the dapps directory again, serving the ones added since startup
+	/// and dropping the removed ones. Builtin dapps are kept.
+	/// Requests already being handled finish with the dapps they started with.
+	/// Fails, keeping the current dapps, if the landing page would no longer be available.
+	pub fn reload_local_dapps(&self) -> Result<(), ServerError> {
+		let dapps_path = match self.dapps_path {
+			Some(ref path) => path.clone(),
+			None => return Ok(()),
+		};
+
+		let endpoints = apps::all_endpoints(Some(dapps_path), &self.zipped_dapps);
+		if !endpoints.contains_key(&self.landing_page) {
+			return Err(ServerError::UnknownLandingPage(self.landing_page.clone()));
+		}
+		self.endpoints.replace(endpoints);
+		Ok(())
 	}
 
 	/// Set callback for panics.
@@ -178,7 +460,9 @@ impl Server {
 
 impl Drop for Server {
 	fn drop(&mut self) {
-		self.server.take().unwrap().close()
+		for server in self.servers.drain(..) {
+			server.close();
+		}
 	}
 }
 
@@ -189,6 +473,8 @@ pub enum ServerError {
 	IoError(std::io::Error),
 	/// Other `hyper` error
 	Other(hyper::error::Error),
+	/// Landing page is not a known dapp
+	UnknownLandingPage(String),
 }
 
 impl From<hyper::error::Error> for ServerError {
@@ -201,9 +487,434 @@ impl From<hyper::error::Error> for ServerError {
 }
 
 /// Random filename
+///
+/// Panics if the OS random number generator is unavailable, see `random_filename_checked`.
 pub fn random_filename() -> String {
+	random_filename_checked().expect("Failed to open OS random number generator")
+}
+
+/// Random filename, failing instead of panicking if the OS random number generator is unavailable.
+pub fn random_filename_checked() -> std::io::Result<String> {
 	use ::rand::Rng;
-	let mut rng = ::rand::OsRng::new().unwrap();
-	rng.gen_ascii_chars().take(12).collect()
+	let mut rng = try!(::rand::OsRng::new());
+	Ok(rng.gen_ascii_chars().take(12).collect())
 }
 
+
+#[cfg(test)]
+mod tests {
+	use std::env;
+	use std::fs;
+	use std::io::{Cursor, Read, Write};
+	use std::net::{SocketAddr, TcpListener, TcpStream};
+	use std::sync::Arc;
+	use std::thread;
+	use std::time::Duration;
+	use util::{Address, Bytes, Mutex};
+	use jsonrpc_core::{Params, Value};
+	use rustc_serialize::base64::{ToBase64, STANDARD};
+	use super::{ContractClient, DappsConfig, ServerBuilder, ServerError, ProxyMode, AuthLogger, AuthRecord, AuthOutcome, random_filename, random_filename_checked};
+	use rpc::CHUNKED_RESPONSE_THRESHOLD;
+	use api::VersionInfo;
+	use serde_json;
+	use zip;
+
+	struct NoRegistrar;
+
+	impl ContractClient for NoRegistrar {
+		fn registrar(&self) -> Result<Address, String> {
+			Err("No registrar".into())
+		}
+
+		fn call(&self, _address: Address, _data: Bytes) -> Result<Bytes, String> {
+			Err("No registrar".into())
+		}
+	}
+
+	fn request(addr: &SocketAddr) -> Option<String> {
+		get(addr, "/")
+	}
+
+	fn get(addr: &SocketAddr, path: &str) -> Option<String> {
+		send(addr, format!("GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n", path, addr))
+	}
+
+	fn rpc_request(addr: &SocketAddr, body: &str) -> Option<String> {
+		post(addr, "/", body)
+	}
+
+	fn post(addr: &SocketAddr, path: &str, body: &str) -> Option<String> {
+		send(addr, format!(
+			"POST {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+			path, addr, body.len(), body
+		))
+	}
+
+	fn send(addr: &SocketAddr, raw: String) -> Option<String> {
+		let mut stream = TcpStream::connect(addr).unwrap();
+		stream.set_read_timeout(Some(Duration::from_millis(500))).unwrap();
+		stream.write_all(raw.as_bytes()).unwrap();
+
+		// read until the connection is closed or times out.
+		let mut response = Vec::new();
+		let mut buf = [0u8; 1024];
+		while let Ok(n) = stream.read(&mut buf) {
+			if n == 0 { break; }
+			response.extend_from_slice(&buf[..n]);
+		}
+
+		match response.is_empty() {
+			true => None,
+			false => Some(String::from_utf8_lossy(&response).into_owned()),
+		}
+	}
+
+	#[test]
+	fn should_not_serve_until_run() {
+		// given
+		let addr: SocketAddr = "127.0.0.1:18543".parse().unwrap();
+		let builder = ServerBuilder::new("dapps".into(), Arc::new(NoRegistrar));
+
+		// when
+		let (server, server_loop) = builder.prepare(&addr).unwrap();
+
+		// then
+		// port is taken, but requests are not answered.
+		assert!(TcpListener::bind(&addr).is_err());
+		assert_eq!(request(&addr), None);
+
+		thread::spawn(move || server_loop.run());
+		assert!(request(&addr).unwrap().starts_with("HTTP/1.1"));
+
+		drop(server);
+	}
+
+	#[test]
+	fn should_serve_with_multiple_threads() {
+		// given
+		let addr: SocketAddr = "127.0.0.1:18545".parse().unwrap();
+		let builder = ServerBuilder::new("dapps".into(), Arc::new(NoRegistrar)).threads(4);
+
+		// when
+		let (server, server_loop) = builder.prepare(&addr).unwrap();
+		thread::spawn(move || server_loop.run());
+
+		// then
+		let clients: Vec<_> = (0..8).map(|_| thread::spawn(move || request(&addr))).collect();
+		for client in clients {
+			assert!(client.join().unwrap().unwrap().starts_with("HTTP/1.1"));
+		}
+
+		drop(server);
+	}
+
+	#[test]
+	fn should_start_servers_from_cloned_builder() {
+		// given
+		let addr1: SocketAddr = "127.0.0.1:18546".parse().unwrap();
+		let addr2: SocketAddr = "127.0.0.1:18547".parse().unwrap();
+		let builder = ServerBuilder::new("dapps".into(), Arc::new(NoRegistrar));
+
+		// when
+		let server1 = builder.clone().start_unsecure_http(&addr1).unwrap();
+		let server2 = builder.start_unsecure_http(&addr2).unwrap();
+
+		// then
+		assert!(request(&addr1).unwrap().starts_with("HTTP/1.1"));
+		assert!(request(&addr2).unwrap().starts_with("HTTP/1.1"));
+
+		drop(server1);
+		drop(server2);
+	}
+
+	#[test]
+	fn should_redirect_root_to_landing_page() {
+		// given
+		let addr: SocketAddr = "127.0.0.1:18548".parse().unwrap();
+		let builder = ServerBuilder::new("dapps".into(), Arc::new(NoRegistrar)).landing_page("status".into());
+
+		// when
+		let server = builder.start_unsecure_http(&addr).unwrap();
+		let response = request(&addr).unwrap();
+
+		// then
+		assert!(response.starts_with("HTTP/1.1 301"));
+		assert!(response.contains("Location: /status/"));
+
+		drop(server);
+	}
+
+	#[test]
+	fn should_reject_unknown_landing_page() {
+		let addr: SocketAddr = "127.0.0.1:18549".parse().unwrap();
+		let builder = ServerBuilder::new("dapps".into(), Arc::new(NoRegistrar)).landing_page("unknown".into());
+
+		match builder.prepare(&addr) {
+			Err(ServerError::UnknownLandingPage(ref id)) if id == "unknown" => {},
+			_ => panic!("server should not start with an unknown landing page"),
+		}
+	}
+
+	#[test]
+	fn should_stream_large_rpc_responses_chunked() {
+		// given
+		let addr: SocketAddr = "127.0.0.1:18550".parse().unwrap();
+		let builder = ServerBuilder::new("dapps".into(), Arc::new(NoRegistrar));
+		builder.handler.add_method("test_small", |_params: Params| Ok(Value::String("small".into())));
+		builder.handler.add_method("test_large", |_params: Params| {
+			Ok(Value::String(::std::iter::repeat('a').take(2 * CHUNKED_RESPONSE_THRESHOLD as usize).collect()))
+		});
+		let server = builder.start_unsecure_http(&addr).unwrap();
+
+		// when
+		let small = rpc_request(&addr, r#"{"jsonrpc":"2.0","method":"test_small","params":[],"id":1}"#).unwrap();
+		let large = rpc_request(&addr, r#"{"jsonrpc":"2.0","method":"test_large","params":[],"id":1}"#).unwrap();
+
+		// then
+		assert!(small.starts_with("HTTP/1.1 200"));
+		assert!(!small.contains("Transfer-Encoding: chunked"));
+		assert!(small.contains(r#""result":"small""#));
+
+		assert!(large.starts_with("HTTP/1.1 200"));
+		assert!(large.contains("Transfer-Encoding: chunked"));
+		assert!(!large.contains("Content-Length"));
+		// the stream is properly terminated.
+		assert!(large.ends_with("\r\n0\r\n\r\n"));
+
+		drop(server);
+	}
+
+	#[test]
+	fn should_not_serve_disabled_rpc() {
+		// given
+		let addr: SocketAddr = "127.0.0.1:18551".parse().unwrap();
+		let builder = ServerBuilder::new("dapps".into(), Arc::new(NoRegistrar)).rpc_enabled(false);
+		builder.handler.add_method("test_small", |_params: Params| Ok(Value::String("small".into())));
+		let server = builder.start_unsecure_http(&addr).unwrap();
+		let request = r#"{"jsonrpc":"2.0","method":"test_small","params":[],"id":1}"#;
+
+		// when
+		let rpc = post(&addr, "/rpc/", request).unwrap();
+		let root = post(&addr, "/", request).unwrap();
+		let page = get(&addr, "/home/").unwrap();
+
+		// then
+		assert!(rpc.starts_with("HTTP/1.1 404"));
+		assert!(root.starts_with("HTTP/1.1 404"));
+		assert!(page.starts_with("HTTP/1.1 200"));
+
+		drop(server);
+	}
+
+	#[test]
+	fn should_list_builtin_endpoint_ids() {
+		// given
+		let addr: SocketAddr = "127.0.0.1:18544".parse().unwrap();
+		let builder = ServerBuilder::new("dapps".into(), Arc::new(NoRegistrar));
+
+		// when
+		let (server, _) = builder.prepare(&addr).unwrap();
+		let ids = server.endpoint_ids();
+
+		// then
+		for id in &["home", "proxy", "parity", "status"] {
+			assert!(ids.contains(&id.to_string()), "missing {}", id);
+		}
+	}
+
+	#[test]
+	fn should_not_serve_local_dapps_with_discovery_disabled() {
+		// given
+		let mut dapps_path = env::temp_dir();
+		dapps_path.push(random_filename());
+		fs::create_dir_all(dapps_path.join("mydapp")).unwrap();
+		fs::File::create(dapps_path.join("mydapp").join("index.html")).unwrap().write_all(b"<html></html>").unwrap();
+		let builder = ServerBuilder::new(dapps_path.to_str().unwrap().into(), Arc::new(NoRegistrar));
+
+		// when
+		let (enabled, _) = builder.prepare(&"127.0.0.1:18552".parse().unwrap()).unwrap();
+		let (disabled, _) = builder.clone().fs_dapps_enabled(false).prepare(&"127.0.0.1:18553".parse().unwrap()).unwrap();
+		let _ = fs::remove_dir_all(&dapps_path);
+
+		// then
+		assert!(enabled.endpoint_ids().contains(&"mydapp".to_owned()));
+		assert!(!disabled.endpoint_ids().contains(&"mydapp".to_owned()));
+		assert!(disabled.endpoint_ids().contains(&"home".to_owned()));
+	}
+
+	#[test]
+	fn should_enable_local_dapps_by_default() {
+		assert!(DappsConfig::default().fs_dapps_enabled);
+		assert!(ServerBuilder::new("dapps".into(), Arc::new(NoRegistrar)).config.fs_dapps_enabled);
+	}
+
+	#[derive(Default)]
+	struct CapturingLogger {
+		records: Mutex<Vec<AuthRecord>>,
+	}
+
+	impl AuthLogger for CapturingLogger {
+		fn log(&self, record: &AuthRecord) {
+			self.records.lock().push(record.clone());
+		}
+	}
+
+	#[test]
+	fn should_log_failed_auth_with_client_address() {
+		// given
+		let addr: SocketAddr = "127.0.0.1:18554".parse().unwrap();
+		let logger = Arc::new(CapturingLogger::default());
+		let builder = ServerBuilder::new("dapps".into(), Arc::new(NoRegistrar)).log_auth_attempts(logger.clone());
+		let server = builder.start_basic_auth_http(&addr, "user", "secret").unwrap();
+
+		// when
+		let response = send(&addr, format!(
+			"GET /home/ HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nAuthorization: Basic {}\r\n\r\n",
+			addr, b"user:wrong".to_base64(STANDARD)
+		)).unwrap();
+
+		// then
+		assert!(response.starts_with("HTTP/1.1 401"));
+		let records = logger.records.lock();
+		assert_eq!(records.len(), 1);
+		assert_eq!(records[0].outcome, AuthOutcome::Denied);
+		assert_eq!(records[0].username, Some("user".to_owned()));
+		assert_eq!(records[0].remote_addr.map(|a| a.ip()), Some(addr.ip()));
+		assert!(!format!("{:?}", records[0]).contains("wrong"));
+
+		drop(server);
+	}
+
+	#[test]
+	fn checked_random_filename_has_twelve_chars() {
+		let name = random_filename_checked().unwrap();
+		assert_eq!(name.len(), 12);
+		assert!(name.chars().all(|c| c.is_alphanumeric()));
+		assert!(name != random_filename_checked().unwrap());
+	}
+
+	#[test]
+	fn should_serve_local_dapp_added_after_reload() {
+		// given
+		let addr: SocketAddr = "127.0.0.1:18555".parse().unwrap();
+		let mut dapps_path = env::temp_dir();
+		dapps_path.push(random_filename());
+		fs::create_dir_all(&dapps_path).unwrap();
+		let builder = ServerBuilder::new(dapps_path.to_str().unwrap().into(), Arc::new(NoRegistrar));
+		let server = builder.start_unsecure_http(&addr).unwrap();
+		assert!(!server.endpoint_ids().contains(&"newdapp".to_owned()));
+
+		// when
+		fs::create_dir_all(dapps_path.join("newdapp")).unwrap();
+		fs::File::create(dapps_path.join("newdapp").join("index.html")).unwrap().write_all(b"<html>new dapp</html>").unwrap();
+		server.reload_local_dapps().unwrap();
+		let response = get(&addr, "/newdapp/").unwrap();
+		let _ = fs::remove_dir_all(&dapps_path);
+
+		// then
+		assert!(response.starts_with("HTTP/1.1 200"), "unexpected response: {}", response);
+		assert!(response.contains("<html>new dapp</html>"));
+		assert!(server.endpoint_ids().contains(&"newdapp".to_owned()));
+		assert!(server.endpoint_ids().contains(&"home".to_owned()));
+	}
+
+	#[test]
+	fn should_serve_zipped_dapp() {
+		// given
+		let addr: SocketAddr = "127.0.0.1:18556".parse().unwrap();
+		let mut archive = zip::ZipWriter::new(Cursor::new(Vec::new()));
+		archive.start_file("index.html", zip::CompressionMethod::Stored).unwrap();
+		archive.write_all(b"<html>zipped dapp</html>").unwrap();
+		let archive = archive.finish().unwrap().into_inner();
+		let builder = ServerBuilder::new("dapps".into(), Arc::new(NoRegistrar))
+			.fs_dapps_enabled(false)
+			.zipped_dapp("zipped".into(), archive);
+		let server = builder.start_unsecure_http(&addr).unwrap();
+
+		// when
+		let response = get(&addr, "/zipped/").unwrap();
+		let missing = get(&addr, "/zipped/missing.js").unwrap();
+
+		// then
+		assert!(response.starts_with("HTTP/1.1 200"), "unexpected response: {}", response);
+		assert!(response.contains("Content-Type: text/html"));
+		assert!(response.contains("<html>zipped dapp</html>"));
+		assert!(missing.starts_with("HTTP/1.1 404"), "unexpected response: {}", missing);
+		assert!(server.endpoint_ids().contains(&"zipped".to_owned()));
+	}
+
+	#[test]
+	fn should_serve_version_info() {
+		// given
+		let addr: SocketAddr = "127.0.0.1:18557".parse().unwrap();
+		let builder = ServerBuilder::new("dapps".into(), Arc::new(NoRegistrar));
+		let server = builder.start_unsecure_http(&addr).unwrap();
+
+		// when
+		let response = get(&addr, "/parity-utils/version").unwrap();
+		let missing = get(&addr, "/parity-utils/missing.js").unwrap();
+
+		// then
+		assert!(response.starts_with("HTTP/1.1 200"), "unexpected response: {}", response);
+		assert!(response.contains("Content-Type: application/json"));
+		let json = &response[response.find('{').unwrap()..response.rfind('}').unwrap() + 1];
+		let info: VersionInfo = serde_json::from_str(json).unwrap();
+		assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+		assert!(missing.starts_with("HTTP/1.1 404"), "unexpected response: {}", missing);
+
+		drop(server);
+	}
+
+	#[test]
+	fn should_start_server_from_full_config() {
+		// given
+		let addr: SocketAddr = "127.0.0.1:18558".parse().unwrap();
+		let mut dapps_path = env::temp_dir();
+		dapps_path.push(random_filename());
+		fs::create_dir_all(dapps_path.join("localdapp")).unwrap();
+		fs::File::create(dapps_path.join("localdapp").join("index.html")).unwrap().write_all(b"<html>local dapp</html>").unwrap();
+		let mut archive = zip::ZipWriter::new(Cursor::new(Vec::new()));
+		archive.start_file("index.html", zip::CompressionMethod::Stored).unwrap();
+		archive.write_all(b"<html>zipped dapp</html>").unwrap();
+		let archive = archive.finish().unwrap().into_inner();
+		let logger = Arc::new(CapturingLogger::default());
+		let config = DappsConfig {
+			dapps_path: dapps_path.to_str().unwrap().into(),
+			fs_dapps_enabled: true,
+			zipped_dapps: vec![("zipped".into(), Arc::new(archive))],
+			landing_page: "zipped".into(),
+			threads: 2,
+			proxy_mode: ProxyMode::Disabled,
+			rpc_enabled: false,
+			api_enabled: false,
+			credentials: Some(("user".into(), "secret".into())),
+			auth_logger: Some(logger.clone()),
+		};
+		let builder = ServerBuilder::with_config(config, Arc::new(NoRegistrar));
+		let server = builder.start_http(&addr).unwrap();
+		let get_auth = |path: &str| send(&addr, format!(
+			"GET {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nAuthorization: Basic {}\r\n\r\n",
+			path, addr, b"user:secret".to_base64(STANDARD)
+		)).unwrap();
+
+		// when
+		let unauthorized = get(&addr, "/zipped/").unwrap();
+		let root = get_auth("/");
+		let zipped = get_auth("/zipped/");
+		let local = get_auth("/localdapp/");
+		let api = get_auth("/api/apps");
+		let _ = fs::remove_dir_all(&dapps_path);
+
+		// then
+		assert!(unauthorized.starts_with("HTTP/1.1 401"), "unexpected response: {}", unauthorized);
+		assert!(root.starts_with("HTTP/1.1 301"), "unexpected response: {}", root);
+		assert!(root.contains("Location: /zipped/"));
+		assert!(zipped.contains("<html>zipped dapp</html>"));
+		assert!(local.contains("<html>local dapp</html>"));
+		assert!(api.starts_with("HTTP/1.1 404"), "unexpected response: {}", api);
+		assert_eq!(logger.records.lock()[0].outcome, AuthOutcome::Missing);
+		assert_eq!(builder.config().threads, 2);
+
+		drop(server);
+	}
+}
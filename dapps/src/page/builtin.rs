@@ -26,9 +26,22 @@ pub struct PageEndpoint<T : WebApp + 'static> {
 	pub prefix: Option<String>,
 	/// Safe to be loaded in frame by other origin. (use wisely!)
 	safe_to_embed: bool,
+	/// Document served instead of files which don't exist.
+	fallback: Option<Arc<FallbackPage>>,
+	/// `max-age` (in seconds) clients may cache served files for.
+	cache_max_age: Option<u32>,
 	info: EndpointInfo,
 }
 
+/// Document served with `200 OK` for paths which don't exist within a dapp.
+/// Single-page apps use it to serve their `index.html` for client-side routes.
+pub struct FallbackPage {
+	/// Content of the document.
+	pub content: Vec<u8>,
+	/// MIME type of the content.
+	pub mime: String,
+}
+
 impl<T: WebApp + 'static> PageEndpoint<T> {
 	/// Creates new `PageEndpoint` for builtin (compile time) Dapp.
 	pub fn new(app: T) -> Self {
@@ -37,6 +50,8 @@ impl<T: WebApp + 'static> PageEndpoint<T> {
 			app: Arc::new(app),
 			prefix: None,
 			safe_to_embed: false,
+			fallback: None,
+			cache_max_age: None,
 			info: EndpointInfo::from(info),
 		}
 	}
@@ -50,6 +65,8 @@ impl<T: WebApp + 'static> PageEndpoint<T> {
 			app: Arc::new(app),
 			prefix: Some(prefix),
 			safe_to_embed: false,
+			fallback: None,
+			cache_max_age: None,
 			info: EndpointInfo::from(info),
 		}
 	}
@@ -63,9 +80,28 @@ impl<T: WebApp + 'static> PageEndpoint<T> {
 			app: Arc::new(app),
 			prefix: None,
 			safe_to_embed: true,
+			fallback: None,
+			cache_max_age: None,
 			info: EndpointInfo::from(info),
 		}
 	}
+
+	/// Serve given document for any path which doesn't exist, instead of
+	/// responding with `404 Not Found`.
+	pub fn with_fallback(mut self, content: Vec<u8>, mime: String) -> Self {
+		self.fallback = Some(Arc::new(FallbackPage {
+			content: content,
+			mime: mime,
+		}));
+		self
+	}
+
+	/// Allow clients to cache served files for given number of seconds.
+	/// Files are always served with an `ETag`, so clients can revalidate afterwards.
+	pub fn with_cache_max_age(mut self, max_age: u32) -> Self {
+		self.cache_max_age = Some(max_age);
+		self
+	}
 }
 
 impl<T: WebApp> Endpoint for PageEndpoint<T> {
@@ -76,11 +112,15 @@ impl<T: WebApp> Endpoint for PageEndpoint<T> {
 
 	fn to_handler(&self, path: EndpointPath) -> Box<Handler> {
 		Box::new(handler::PageHandler {
-			app: BuiltinDapp::new(self.app.clone()),
+			app: BuiltinDapp::new(self.app.clone(), self.fallback.clone()),
 			prefix: self.prefix.clone(),
 			path: path,
 			file: None,
 			safe_to_embed: self.safe_to_embed,
+			range: handler::ByteRange::Full,
+			cache_max_age: self.cache_max_age,
+			etag: None,
+			not_modified: false,
 		})
 	}
 }
@@ -99,12 +139,14 @@ impl From<Info> for EndpointInfo {
 
 struct BuiltinDapp<T: WebApp + 'static> {
 	app: Arc<T>,
+	fallback: Option<Arc<FallbackPage>>,
 }
 
 impl<T: WebApp + 'static> BuiltinDapp<T> {
-	fn new(app: Arc<T>) -> Self {
+	fn new(app: Arc<T>, fallback: Option<Arc<FallbackPage>>) -> Self {
 		BuiltinDapp {
 			app: app,
+			fallback: fallback,
 		}
 	}
 }
@@ -113,42 +155,130 @@ impl<T: WebApp + 'static> handler::Dapp for BuiltinDapp<T> {
 	type DappFile = BuiltinDappFile<T>;
 
 	fn file(&self, path: &str) -> Option<Self::DappFile> {
-		self.app.file(path).map(|_| {
-			BuiltinDappFile {
-				app: self.app.clone(),
-				path: path.into(),
-				write_pos: 0,
-			}
-		})
+		let fallback = match self.app.file(path) {
+			Some(_) => None,
+			None => match self.fallback {
+				Some(ref fallback) => Some(fallback.clone()),
+				None => return None,
+			},
+		};
+
+		let mut file = BuiltinDappFile {
+			app: self.app.clone(),
+			path: path.into(),
+			fallback: fallback,
+			write_pos: 0,
+			end_pos: 0,
+		};
+		file.end_pos = file.content().len();
+		Some(file)
 	}
 }
 
 struct BuiltinDappFile<T: WebApp + 'static> {
 	app: Arc<T>,
 	path: String,
+	/// Set when serving the fallback page in place of a missing file.
+	fallback: Option<Arc<FallbackPage>>,
 	write_pos: usize,
+	end_pos: usize,
 }
 
 impl<T: WebApp + 'static> BuiltinDappFile<T> {
 	fn file(&self) -> &File {
 		self.app.file(&self.path).expect("Check is done when structure is created.")
 	}
+
+	fn content(&self) -> &[u8] {
+		match self.fallback {
+			Some(ref fallback) => &fallback.content,
+			None => self.file().content,
+		}
+	}
 }
 
 impl<T: WebApp + 'static> handler::DappFile for BuiltinDappFile<T> {
 	fn content_type(&self) -> &str {
-		self.file().content_type
+		match self.fallback {
+			Some(ref fallback) => &fallback.mime,
+			None => self.file().content_type,
+		}
 	}
 
 	fn is_drained(&self) -> bool {
-		self.write_pos == self.file().content.len()
+		self.write_pos == self.end_pos
 	}
 
 	fn next_chunk(&mut self) -> &[u8] {
-		&self.file().content[self.write_pos..]
+		&self.content()[self.write_pos..self.end_pos]
 	}
 
 	fn bytes_written(&mut self, bytes: usize) {
 		self.write_pos += bytes;
 	}
+
+	fn content_length(&self) -> u64 {
+		self.content().len() as u64
+	}
+
+	fn set_range(&mut self, start: u64, end: u64) {
+		self.write_pos = start as usize;
+		self.end_pos = end as usize;
+	}
+
+	fn etag(&mut self) -> Option<String> {
+		Some(handler::etag(self.content()))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Arc;
+	use parity_dapps::{WebApp, File, Info};
+	use page::handler::{etag, Dapp, DappFile};
+	use super::{BuiltinDapp, FallbackPage};
+
+	#[derive(Default)]
+	struct EmptyApp;
+
+	impl WebApp for EmptyApp {
+		fn file(&self, _path: &str) -> Option<&File> {
+			None
+		}
+
+		fn info(&self) -> Info {
+			unimplemented!()
+		}
+	}
+
+	#[test]
+	fn should_serve_fallback_for_missing_file() {
+		// given
+		let fallback = Arc::new(FallbackPage {
+			content: b"<html>app</html>".to_vec(),
+			mime: "text/html".into(),
+		});
+		let dapp = BuiltinDapp::new(Arc::new(EmptyApp), Some(fallback));
+
+		// when
+		let mut file = dapp.file("wallet/accounts").expect("fallback should be served");
+
+		// then
+		assert_eq!(file.content_type(), "text/html");
+		assert_eq!(file.content_length(), 16);
+		assert_eq!(file.next_chunk(), b"<html>app</html>");
+		assert_eq!(file.etag(), Some(etag(b"<html>app</html>")));
+	}
+
+	#[test]
+	fn should_not_find_missing_file_without_fallback() {
+		// given
+		let dapp = BuiltinDapp::new(Arc::new(EmptyApp), None);
+
+		// when
+		let file = dapp.file("wallet/accounts");
+
+		// then
+		assert!(file.is_none());
+	}
 }
@@ -15,6 +15,7 @@
 // along with Parity.  If not, see <http://www.gnu.org/licenses/>.
 
 use std::io::Write;
+use std::str;
 use hyper::header;
 use hyper::server;
 use hyper::uri::RequestUri;
@@ -22,6 +23,7 @@ use hyper::net::HttpStream;
 use hyper::status::StatusCode;
 use hyper::{Decoder, Encoder, Next};
 use endpoint::EndpointPath;
+use util::sha3::Hashable;
 
 /// Represents a file that can be sent to client.
 /// Implementation should keep track of bytes already sent internally.
@@ -37,6 +39,31 @@ pub trait DappFile: Send {
 
 	/// How many files have been written to the client.
 	fn bytes_written(&mut self, bytes: usize);
+
+	/// Returns the total length of this file in bytes.
+	fn content_length(&self) -> u64;
+
+	/// Restricts the bytes to write to the client to the half-open range `start..end`.
+	fn set_range(&mut self, start: u64, end: u64);
+
+	/// Returns a strong entity tag identifying the content of this file
+	/// (or `None` if it can't be computed).
+	fn etag(&mut self) -> Option<String>;
+}
+
+/// Computes a strong entity tag (quoted Keccak-256 hash) of given content.
+pub fn etag(content: &[u8]) -> String {
+	format!("\"{}\"", content.sha3().hex())
+}
+
+/// Checks if an `If-None-Match` header value matches given entity tag.
+/// Weak comparison is used, as required for `If-None-Match`.
+pub fn etag_matches(header: &str, etag: &str) -> bool {
+	let header = header.trim();
+	header == "*" || header.split(',')
+		.map(|tag| tag.trim())
+		.map(|tag| if tag.starts_with("W/") { &tag[2..] } else { tag })
+		.any(|tag| tag == etag)
 }
 
 /// Dapp as a (dynamic) set of files.
@@ -62,6 +89,61 @@ pub struct PageHandler<T: Dapp> {
 	pub path: EndpointPath,
 	/// Flag indicating if the file can be safely embeded (put in iframe).
 	pub safe_to_embed: bool,
+	/// Byte range of the file requested by the client.
+	pub range: ByteRange,
+	/// `max-age` (in seconds) sent in `Cache-Control` header (or `None` to leave it unset).
+	pub cache_max_age: Option<u32>,
+	/// Entity tag of the file being served.
+	pub etag: Option<String>,
+	/// Set when client's cached copy is still valid and `304 Not Modified` should be sent.
+	pub not_modified: bool,
+}
+
+/// Byte range requested with a `Range` header.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ByteRange {
+	/// Whole file (no range or an unsupported one was requested).
+	Full,
+	/// Inclusive range of bytes `first..=last`.
+	Partial(u64, u64),
+	/// Range that can't be satisfied for a file of given length.
+	Unsatisfiable(u64),
+}
+
+impl ByteRange {
+	/// Parses a `Range` header value for a file of given length.
+	/// Only single `bytes=` ranges are supported, anything else (including
+	/// malformed ranges) is ignored and the whole file is served.
+	pub fn parse(header: &str, len: u64) -> Self {
+		let spec = match header.trim().splitn(2, '=').collect::<Vec<_>>() {
+			ref parts if parts.len() == 2 && parts[0].trim() == "bytes" => parts[1].trim(),
+			_ => return ByteRange::Full,
+		};
+
+		if spec.contains(',') {
+			return ByteRange::Full;
+		}
+
+		let (first, last) = match spec.find('-') {
+			Some(pos) => (spec[..pos].trim(), spec[pos + 1..].trim()),
+			None => return ByteRange::Full,
+		};
+
+		match (first.parse::<u64>(), last.parse::<u64>()) {
+			// suffix range: last `n` bytes
+			(Err(_), Ok(n)) if first.is_empty() => match n {
+				0 => ByteRange::Unsatisfiable(len),
+				_ if len == 0 => ByteRange::Unsatisfiable(len),
+				n if n >= len => ByteRange::Partial(0, len - 1),
+				n => ByteRange::Partial(len - n, len - 1),
+			},
+			(Ok(first), _) if first >= len => ByteRange::Unsatisfiable(len),
+			// open range: from `first` to the end
+			(Ok(first), Err(_)) if last.is_empty() => ByteRange::Partial(first, len - 1),
+			(Ok(first), Ok(last)) if first <= last => ByteRange::Partial(first, ::std::cmp::min(last, len - 1)),
+			_ => ByteRange::Full,
+		}
+	}
 }
 
 impl<T: Dapp> PageHandler<T> {
@@ -83,19 +165,62 @@ impl<T: Dapp> PageHandler<T> {
 			}
 		}
 	}
+
+	fn prepare_file(&mut self, path: Option<String>, range: Option<&str>, if_none_match: Option<&str>) {
+		self.file = path.and_then(|path| self.app.file(&path));
+		self.etag = self.file.as_mut().and_then(|f| f.etag());
+		self.not_modified = match (self.etag.as_ref(), if_none_match) {
+			(Some(etag), Some(header)) => etag_matches(header, etag),
+			_ => false,
+		};
+
+		if self.not_modified {
+			self.file = None;
+			self.range = ByteRange::Full;
+			return;
+		}
+
+		self.range = match (self.file.as_mut(), range) {
+			(Some(f), Some(header)) => {
+				let range = ByteRange::parse(header, f.content_length());
+				if let ByteRange::Partial(first, last) = range {
+					f.set_range(first, last + 1);
+				}
+				range
+			},
+			_ => ByteRange::Full,
+		};
+
+		if let ByteRange::Unsatisfiable(_) = self.range {
+			self.file = None;
+		}
+	}
+
+	fn set_cache_headers(&self, res: &mut server::Response) {
+		if let Some(ref etag) = self.etag {
+			res.headers_mut().set_raw("ETag", vec![etag.clone().into_bytes()]);
+		}
+		if let Some(max_age) = self.cache_max_age {
+			res.headers_mut().set_raw("Cache-Control", vec![format!("max-age={}", max_age).into_bytes()]);
+		}
+	}
+}
+
+fn raw_header<'a>(req: &'a server::Request<HttpStream>, name: &str) -> Option<&'a str> {
+	req.headers().get_raw(name)
+		.and_then(|raw| raw.first())
+		.and_then(|raw| str::from_utf8(raw).ok())
 }
 
 impl<T: Dapp> server::Handler<HttpStream> for PageHandler<T> {
 	fn on_request(&mut self, req: server::Request<HttpStream>) -> Next {
-		self.file = match *req.uri() {
-			RequestUri::AbsolutePath(ref path) => {
-				self.app.file(&self.extract_path(path))
-			},
-			RequestUri::AbsoluteUri(ref url) => {
-				self.app.file(&self.extract_path(url.path()))
-			},
+		let path = match *req.uri() {
+			RequestUri::AbsolutePath(ref path) => Some(self.extract_path(path)),
+			RequestUri::AbsoluteUri(ref url) => Some(self.extract_path(url.path())),
 			_ => None,
 		};
+
+		self.prepare_file(path, raw_header(&req, "Range"), raw_header(&req, "If-None-Match"));
 		Next::write()
 	}
 
@@ -104,8 +229,28 @@ impl<T: Dapp> server::Handler<HttpStream> for PageHandler<T> {
 	}
 
 	fn on_response(&mut self, res: &mut server::Response) -> Next {
+		if self.not_modified {
+			res.set_status(StatusCode::NotModified);
+			self.set_cache_headers(res);
+			return Next::write();
+		}
+
+		if let ByteRange::Unsatisfiable(len) = self.range {
+			res.set_status(StatusCode::RangeNotSatisfiable);
+			res.headers_mut().set_raw("Content-Range", vec![format!("bytes */{}", len).into_bytes()]);
+			return Next::write();
+		}
+
 		if let Some(ref f) = self.file {
-			res.set_status(StatusCode::Ok);
+			if let ByteRange::Partial(first, last) = self.range {
+				res.set_status(StatusCode::PartialContent);
+				res.headers_mut().set_raw("Content-Range", vec![format!("bytes {}-{}/{}", first, last, f.content_length()).into_bytes()]);
+				res.headers_mut().set(header::ContentLength(last - first + 1));
+			} else {
+				res.set_status(StatusCode::Ok);
+			}
+			res.headers_mut().set_raw("Accept-Ranges", vec![b"bytes".to_vec()]);
+			self.set_cache_headers(res);
 			res.headers_mut().set(header::ContentType(f.content_type().parse().unwrap()));
 			if !self.safe_to_embed {
 				res.headers_mut().set_raw("X-Frame-Options", vec![b"SAMEORIGIN".to_vec()]);
@@ -159,6 +304,18 @@ mod test {
 		fn bytes_written(&mut self, _bytes: usize) {
 			unimplemented!()
 		}
+
+		fn content_length(&self) -> u64 {
+			unimplemented!()
+		}
+
+		fn set_range(&mut self, _start: u64, _end: u64) {
+			unimplemented!()
+		}
+
+		fn etag(&mut self) -> Option<String> {
+			Some(etag(b"index"))
+		}
 	}
 
 	#[derive(Default)]
@@ -167,8 +324,30 @@ mod test {
 	impl Dapp for TestWebapp {
 		type DappFile = TestWebAppFile;
 
-		fn file(&self, _path: &str) -> Option<Self::DappFile> {
-			None
+		fn file(&self, path: &str) -> Option<Self::DappFile> {
+			match path {
+				"index.html" => Some(TestWebAppFile),
+				_ => None,
+			}
+		}
+	}
+
+	pub fn handler() -> PageHandler<TestWebapp> {
+		PageHandler {
+			app: TestWebapp,
+			prefix: None,
+			path: EndpointPath {
+				app_id: "app".to_owned(),
+				host: "".to_owned(),
+				port: 8080,
+				using_dapps_domains: true,
+			},
+			file: None,
+			safe_to_embed: true,
+			range: ByteRange::Full,
+			cache_max_age: None,
+			etag: None,
+			not_modified: false,
 		}
 	}
 }
@@ -192,6 +371,10 @@ fn should_extract_path_with_appid() {
 		},
 		file: None,
 		safe_to_embed: true,
+		range: ByteRange::Full,
+		cache_max_age: None,
+		etag: None,
+		not_modified: false,
 	};
 
 	// when
@@ -206,3 +389,85 @@ fn should_extract_path_with_appid() {
 	assert_eq!(&res3, "myfile.txt");
 	assert_eq!(&res4, "myfile.txt");
 }
+
+#[test]
+fn should_parse_valid_range() {
+	assert_eq!(ByteRange::parse("bytes=0-99", 1000), ByteRange::Partial(0, 99));
+	assert_eq!(ByteRange::parse("bytes=500-", 1000), ByteRange::Partial(500, 999));
+	assert_eq!(ByteRange::parse("bytes=900-2000", 1000), ByteRange::Partial(900, 999));
+}
+
+#[test]
+fn should_parse_suffix_range() {
+	assert_eq!(ByteRange::parse("bytes=-100", 1000), ByteRange::Partial(900, 999));
+	assert_eq!(ByteRange::parse("bytes=-2000", 1000), ByteRange::Partial(0, 999));
+}
+
+#[test]
+fn should_detect_unsatisfiable_range() {
+	assert_eq!(ByteRange::parse("bytes=1000-", 1000), ByteRange::Unsatisfiable(1000));
+	assert_eq!(ByteRange::parse("bytes=1500-2000", 1000), ByteRange::Unsatisfiable(1000));
+	assert_eq!(ByteRange::parse("bytes=-0", 1000), ByteRange::Unsatisfiable(1000));
+	assert_eq!(ByteRange::parse("bytes=-10", 0), ByteRange::Unsatisfiable(0));
+}
+
+#[test]
+fn should_ignore_unsupported_range() {
+	assert_eq!(ByteRange::parse("bytes=0-1,5-6", 1000), ByteRange::Full);
+	assert_eq!(ByteRange::parse("items=0-1", 1000), ByteRange::Full);
+	assert_eq!(ByteRange::parse("bytes=10-5", 1000), ByteRange::Full);
+	assert_eq!(ByteRange::parse("bytes=abc", 1000), ByteRange::Full);
+}
+
+#[test]
+fn should_match_etags() {
+	let tag = etag(b"index");
+
+	assert!(etag_matches(&tag, &tag));
+	assert!(etag_matches("*", &tag));
+	assert!(etag_matches(&format!("\"abc\", W/{}", tag), &tag));
+	assert!(!etag_matches("\"abc\"", &tag));
+	assert!(!etag_matches("", &tag));
+}
+
+#[test]
+fn should_serve_file_with_etag_on_first_fetch() {
+	// given
+	let mut handler = test::handler();
+
+	// when
+	handler.prepare_file(Some("index.html".into()), None, None);
+
+	// then
+	assert!(handler.file.is_some());
+	assert!(!handler.not_modified);
+	assert_eq!(handler.etag, Some(etag(b"index")));
+}
+
+#[test]
+fn should_not_modify_when_etag_matches() {
+	// given
+	let mut handler = test::handler();
+	let tag = etag(b"index");
+
+	// when
+	handler.prepare_file(Some("index.html".into()), None, Some(&tag));
+
+	// then
+	assert!(handler.file.is_none());
+	assert!(handler.not_modified);
+	assert_eq!(handler.etag, Some(tag));
+}
+
+#[test]
+fn should_serve_file_when_etag_differs() {
+	// given
+	let mut handler = test::handler();
+
+	// when
+	handler.prepare_file(Some("index.html".into()), None, Some("\"stale\""));
+
+	// then
+	assert!(handler.file.is_some());
+	assert!(!handler.not_modified);
+}
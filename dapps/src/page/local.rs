@@ -15,15 +15,23 @@
 // along with Parity.  If not, see <http://www.gnu.org/licenses/>.
 
 use mime_guess;
-use std::io::{Seek, Read, SeekFrom};
+use std::cmp;
+use std::collections::HashMap;
+use std::io::{self, Seek, Read, SeekFrom};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
 use page::handler;
+use util::{Mutex, H256};
+use util::sha3::sha3;
+use apps::manifest::{Manifest, MANIFEST_FILENAME, deserialize_manifest, serialize_manifest, asset_hashes};
 use endpoint::{Endpoint, EndpointInfo, EndpointPath, Handler};
 
 pub struct LocalPageEndpoint {
 	path: PathBuf,
 	info: EndpointInfo,
+	hashes: Arc<FileHashes>,
 }
 
 impl LocalPageEndpoint {
@@ -31,6 +39,7 @@ impl LocalPageEndpoint {
 		LocalPageEndpoint {
 			path: path,
 			info: info,
+			hashes: Arc::new(FileHashes::default()),
 		}
 	}
 }
@@ -42,56 +51,229 @@ impl Endpoint for LocalPageEndpoint {
 
 	fn to_handler(&self, path: EndpointPath) -> Box<Handler> {
 		Box::new(handler::PageHandler {
-			app: LocalDapp::new(self.path.clone()),
+			app: LocalDapp::new(self.path.clone(), self.hashes.clone()),
 			prefix: None,
 			path: path,
 			file: None,
 			safe_to_embed: false,
+			range: handler::ByteRange::Full,
+			cache_max_age: None,
+			etag: None,
+			not_modified: false,
 		})
 	}
 }
 
+/// Keccak-256 hashes of local files, reused until a file's modification time or length changes.
+#[derive(Default)]
+struct FileHashes {
+	hashes: Mutex<HashMap<PathBuf, (SystemTime, u64, H256)>>,
+}
+
+impl FileHashes {
+	/// Hash of the given file found at `path`, computed only if it changed since it was last hashed.
+	fn hash(&self, path: &Path, file: &mut fs::File) -> io::Result<H256> {
+		let meta = try!(file.metadata());
+		let (modified, len) = (try!(meta.modified()), meta.len());
+		if let Some(&(cached_modified, cached_len, hash)) = self.hashes.lock().get(path) {
+			if cached_modified == modified && cached_len == len {
+				return Ok(hash);
+			}
+		}
+
+		try!(file.seek(SeekFrom::Start(0)));
+		let hash = try!(sha3(file));
+		self.hashes.lock().insert(path.to_owned(), (modified, len, hash));
+		Ok(hash)
+	}
+}
+
 struct LocalDapp {
 	path: PathBuf,
+	hashes: Arc<FileHashes>,
 }
 
 impl LocalDapp {
-	fn new(path: PathBuf) -> Self {
+	fn new(path: PathBuf, hashes: Arc<FileHashes>) -> Self {
 		LocalDapp {
-			path: path
+			path: path,
+			hashes: hashes,
 		}
 	}
 }
 
+impl LocalDapp {
+	/// Reads and parses the dapp manifest.
+	fn manifest(&self) -> Result<Manifest, String> {
+		let mut content = String::new();
+		try!(fs::File::open(self.path.join(MANIFEST_FILENAME))
+			.and_then(|mut f| f.read_to_string(&mut content))
+			.map_err(|e| format!("{:?}", e)));
+		deserialize_manifest(content)
+	}
+
+	/// Extends the manifest with Keccak-256 hashes of all dapp assets.
+	/// Local files may change at any time, so only hashes of files which look unchanged are reused.
+	fn manifest_with_hashes(&self, mut manifest: Manifest) -> Result<Vec<u8>, String> {
+		let hashes = &self.hashes;
+		manifest.assets = Some(try!(asset_hashes(&self.path, &|path: &Path| {
+			let mut file = try!(fs::File::open(path));
+			hashes.hash(path, &mut file)
+		}).map_err(|e| format!("{:?}", e))));
+		serialize_manifest(&manifest).map(String::into_bytes)
+	}
+}
+
 impl handler::Dapp for LocalDapp {
-	type DappFile = LocalFile;
+	type DappFile = LocalDappFile;
 
 	fn file(&self, file_path: &str) -> Option<Self::DappFile> {
+		if file_path == MANIFEST_FILENAME {
+			match self.manifest() {
+				Ok(manifest) => return match self.manifest_with_hashes(manifest) {
+					Ok(content) => Some(LocalDappFile::Manifest(ManifestFile::new(content))),
+					// without hashes the manifest would pass for one of a dapp with no assets.
+					Err(e) => {
+						warn!(target: "dapps", "Unable to hash assets of local dapp at {:?}: {}", self.path, e);
+						None
+					},
+				},
+				Err(e) => debug!(target: "dapps", "Serving manifest without asset hashes. Reason: {:?}", e),
+			}
+		}
+
 		let mut path = self.path.clone();
 		for part in file_path.split('/') {
 			path.push(part);
 		}
 		// Check if file exists
 		fs::File::open(path.clone()).ok().map(|file| {
-			let content_type = mime_guess::guess_mime_type(path);
+			let content_type = mime_guess::guess_mime_type(&path);
 			let len = file.metadata().ok().map_or(0, |meta| meta.len());
-			LocalFile {
+			LocalDappFile::File(LocalFile {
 				content_type: content_type.to_string(),
 				buffer: [0; 4096],
+				path: path,
+				hashes: self.hashes.clone(),
 				file: file,
 				pos: 0,
+				end: len,
 				len: len,
-			}
+			})
 		})
 	}
 }
 
+enum LocalDappFile {
+	/// File read directly from disk.
+	File(LocalFile),
+	/// Manifest extended with asset hashes.
+	Manifest(ManifestFile),
+}
+
+impl LocalDappFile {
+	fn inner(&self) -> &handler::DappFile {
+		match *self {
+			LocalDappFile::File(ref file) => file,
+			LocalDappFile::Manifest(ref file) => file,
+		}
+	}
+
+	fn inner_mut(&mut self) -> &mut handler::DappFile {
+		match *self {
+			LocalDappFile::File(ref mut file) => file,
+			LocalDappFile::Manifest(ref mut file) => file,
+		}
+	}
+}
+
+impl handler::DappFile for LocalDappFile {
+	fn content_type(&self) -> &str {
+		self.inner().content_type()
+	}
+
+	fn is_drained(&self) -> bool {
+		self.inner().is_drained()
+	}
+
+	fn next_chunk(&mut self) -> &[u8] {
+		self.inner_mut().next_chunk()
+	}
+
+	fn bytes_written(&mut self, bytes: usize) {
+		self.inner_mut().bytes_written(bytes)
+	}
+
+	fn content_length(&self) -> u64 {
+		self.inner().content_length()
+	}
+
+	fn set_range(&mut self, start: u64, end: u64) {
+		self.inner_mut().set_range(start, end)
+	}
+
+	fn etag(&mut self) -> Option<String> {
+		self.inner_mut().etag()
+	}
+}
+
+struct ManifestFile {
+	content: Vec<u8>,
+	write_pos: usize,
+	end_pos: usize,
+}
+
+impl ManifestFile {
+	fn new(content: Vec<u8>) -> Self {
+		let len = content.len();
+		ManifestFile {
+			content: content,
+			write_pos: 0,
+			end_pos: len,
+		}
+	}
+}
+
+impl handler::DappFile for ManifestFile {
+	fn content_type(&self) -> &str {
+		"application/json"
+	}
+
+	fn is_drained(&self) -> bool {
+		self.write_pos == self.end_pos
+	}
+
+	fn next_chunk(&mut self) -> &[u8] {
+		&self.content[self.write_pos..self.end_pos]
+	}
+
+	fn bytes_written(&mut self, bytes: usize) {
+		self.write_pos += bytes;
+	}
+
+	fn content_length(&self) -> u64 {
+		self.content.len() as u64
+	}
+
+	fn set_range(&mut self, start: u64, end: u64) {
+		self.write_pos = start as usize;
+		self.end_pos = end as usize;
+	}
+
+	fn etag(&mut self) -> Option<String> {
+		Some(handler::etag(&self.content))
+	}
+}
+
 struct LocalFile {
 	content_type: String,
 	buffer: [u8; 4096],
+	path: PathBuf,
+	hashes: Arc<FileHashes>,
 	file: fs::File,
 	len: u64,
 	pos: u64,
+	end: u64,
 }
 
 impl handler::DappFile for LocalFile {
@@ -100,12 +282,13 @@ impl handler::DappFile for LocalFile {
 	}
 
 	fn is_drained(&self) -> bool {
-		self.pos == self.len
+		self.pos == self.end
 	}
 
 	fn next_chunk(&mut self) -> &[u8] {
+		let max = cmp::min(self.buffer.len() as u64, self.end - self.pos) as usize;
 		let _ = self.file.seek(SeekFrom::Start(self.pos));
-		if let Ok(n) = self.file.read(&mut self.buffer) {
+		if let Ok(n) = self.file.read(&mut self.buffer[0..max]) {
 			&self.buffer[0..n]
 		} else {
 			&self.buffer[0..0]
@@ -115,4 +298,138 @@ impl handler::DappFile for LocalFile {
 	fn bytes_written(&mut self, bytes: usize) {
 		self.pos += bytes as u64;
 	}
+
+	fn content_length(&self) -> u64 {
+		self.len
+	}
+
+	fn set_range(&mut self, start: u64, end: u64) {
+		self.pos = start;
+		self.end = end;
+	}
+
+	fn etag(&mut self) -> Option<String> {
+		// local files may change at any time, so the hash is only reused while the file looks unchanged.
+		self.hashes.hash(&self.path, &mut self.file).ok().map(|hash| format!("\"{}\"", hash.hex()))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::env;
+	use std::fs;
+	use std::io::Write;
+	use std::path::Path;
+	use util::H256;
+	use util::sha3::Hashable;
+	use random_filename;
+	use apps::manifest::{MANIFEST_FILENAME, deserialize_manifest};
+	use page::handler::{Dapp, DappFile};
+	use super::LocalDapp;
+
+	fn write(path: &Path, content: &[u8]) {
+		fs::File::create(path).unwrap().write_all(content).unwrap();
+	}
+
+	#[test]
+	fn should_serve_manifest_with_asset_hashes() {
+		// given
+		let mut path = env::temp_dir();
+		path.push(random_filename());
+		fs::create_dir_all(path.join("js")).unwrap();
+		write(&path.join(MANIFEST_FILENAME), br#"{"id":"test","name":"Test","description":"","version":"0.1","author":"","iconUrl":"icon.png"}"#);
+		write(&path.join("index.html"), b"<html></html>");
+		write(&path.join("js").join("app.js"), b"alert(1);");
+		let dapp = LocalDapp::new(path.clone(), Default::default());
+
+		// when
+		let mut file = dapp.file(MANIFEST_FILENAME).expect("manifest should be served");
+		let content = String::from_utf8(file.next_chunk().to_vec()).unwrap();
+		let _ = fs::remove_dir_all(&path);
+
+		// then
+		assert_eq!(file.content_type(), "application/json");
+		let manifest = deserialize_manifest(content).unwrap();
+		assert_eq!(manifest.id, "test");
+		let assets = manifest.assets.expect("asset hashes should be included");
+		assert_eq!(assets.len(), 2);
+		assert_eq!(assets["index.html"], format!("0x{}", b"<html></html>".sha3().hex()));
+		assert_eq!(assets["js/app.js"], format!("0x{}", b"alert(1);".sha3().hex()));
+	}
+
+	#[test]
+	fn should_reuse_etag_until_file_changes() {
+		// given
+		let mut path = env::temp_dir();
+		path.push(random_filename());
+		fs::create_dir_all(&path).unwrap();
+		write(&path.join("index.html"), b"<html></html>");
+		let dapp = LocalDapp::new(path.clone(), Default::default());
+		let first = dapp.file("index.html").unwrap().etag();
+
+		// when
+		for entry in dapp.hashes.hashes.lock().values_mut() {
+			entry.2 = H256::default();
+		}
+		let cached = dapp.file("index.html").unwrap().etag();
+		write(&path.join("index.html"), b"<html>changed</html>");
+		let changed = dapp.file("index.html").unwrap().etag();
+		let _ = fs::remove_dir_all(&path);
+
+		// then
+		assert_eq!(first, Some(format!("\"{}\"", b"<html></html>".sha3().hex())));
+		assert_eq!(cached, Some(format!("\"{}\"", H256::default().hex())));
+		assert_eq!(changed, Some(format!("\"{}\"", b"<html>changed</html>".sha3().hex())));
+	}
+
+	#[test]
+	fn should_reuse_asset_hashes_until_files_change() {
+		// given
+		let mut path = env::temp_dir();
+		path.push(random_filename());
+		fs::create_dir_all(&path).unwrap();
+		write(&path.join(MANIFEST_FILENAME), br#"{"id":"test","name":"Test","description":"","version":"0.1","author":"","iconUrl":"icon.png"}"#);
+		write(&path.join("index.html"), b"<html></html>");
+		let dapp = LocalDapp::new(path.clone(), Default::default());
+		let assets = |dapp: &LocalDapp| {
+			let mut file = dapp.file(MANIFEST_FILENAME).expect("manifest should be served");
+			let content = String::from_utf8(file.next_chunk().to_vec()).unwrap();
+			deserialize_manifest(content).unwrap().assets.expect("asset hashes should be included")
+		};
+		assets(&dapp);
+
+		// when
+		for entry in dapp.hashes.hashes.lock().values_mut() {
+			entry.2 = H256::default();
+		}
+		let cached = assets(&dapp);
+		write(&path.join("index.html"), b"<html>changed</html>");
+		let changed = assets(&dapp);
+		let _ = fs::remove_dir_all(&path);
+
+		// then
+		assert_eq!(cached["index.html"], format!("0x{}", H256::default().hex()));
+		assert_eq!(changed["index.html"], format!("0x{}", b"<html>changed</html>".sha3().hex()));
+	}
+
+	#[test]
+	#[cfg(unix)]
+	fn should_not_serve_manifest_if_assets_cannot_be_hashed() {
+		use std::os::unix::fs::symlink;
+
+		// given
+		let mut path = env::temp_dir();
+		path.push(random_filename());
+		fs::create_dir_all(&path).unwrap();
+		write(&path.join(MANIFEST_FILENAME), br#"{"id":"test","name":"Test","description":"","version":"0.1","author":"","iconUrl":"icon.png"}"#);
+		symlink(path.join("missing.js"), path.join("broken.js")).unwrap();
+		let dapp = LocalDapp::new(path.clone(), Default::default());
+
+		// when
+		let file = dapp.file(MANIFEST_FILENAME);
+		let _ = fs::remove_dir_all(&path);
+
+		// then
+		assert!(file.is_none());
+	}
 }
@@ -18,7 +18,9 @@
 mod builtin;
 mod local;
 mod handler;
+mod zipped;
 
 pub use self::local::LocalPageEndpoint;
 pub use self::builtin::PageEndpoint;
+pub use self::zipped::ZippedPageEndpoint;
 
@@ -0,0 +1,211 @@
+// Copyright 2015, 2016 Ethcore (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+use mime_guess;
+use std::io::{Cursor, Read};
+use std::sync::Arc;
+use zip;
+use page::handler;
+use apps::manifest::{MANIFEST_FILENAME, deserialize_manifest};
+use endpoint::{Endpoint, EndpointInfo, EndpointPath, Handler};
+
+/// Endpoint serving a dapp out of a zip archive kept in memory.
+/// Files are extracted from the archive when requested.
+pub struct ZippedPageEndpoint {
+	archive: Arc<Vec<u8>>,
+	info: EndpointInfo,
+}
+
+impl ZippedPageEndpoint {
+	/// Creates new endpoint for the dapp in given archive. Dapp info is read from
+	/// the manifest at the root of the archive (if there is one).
+	pub fn new(id: &str, archive: Arc<Vec<u8>>) -> Self {
+		let info = read_manifest(id, &archive);
+		ZippedPageEndpoint {
+			archive: archive,
+			info: info,
+		}
+	}
+}
+
+impl Endpoint for ZippedPageEndpoint {
+	fn info(&self) -> Option<&EndpointInfo> {
+		Some(&self.info)
+	}
+
+	fn to_handler(&self, path: EndpointPath) -> Box<Handler> {
+		Box::new(handler::PageHandler {
+			app: ZippedDapp {
+				archive: self.archive.clone(),
+			},
+			prefix: None,
+			path: path,
+			file: None,
+			safe_to_embed: false,
+			range: handler::ByteRange::Full,
+			cache_max_age: None,
+			etag: None,
+			not_modified: false,
+		})
+	}
+}
+
+fn read_entry(archive: &[u8], name: &str) -> Result<Vec<u8>, zip::result::ZipError> {
+	let mut zip = try!(zip::ZipArchive::new(Cursor::new(archive)));
+	let mut file = try!(zip.by_name(name));
+	let mut content = Vec::new();
+	try!(file.read_to_end(&mut content));
+	Ok(content)
+}
+
+fn read_manifest(name: &str, archive: &[u8]) -> EndpointInfo {
+	read_entry(archive, MANIFEST_FILENAME)
+		.map_err(|e| format!("{:?}", e))
+		.and_then(|content| String::from_utf8(content).map_err(|e| format!("{:?}", e)))
+		.and_then(deserialize_manifest)
+		.map(Into::into)
+		.unwrap_or_else(|e| {
+			warn!(target: "dapps", "Cannot read manifest of zipped dapp: {}. Error: {:?}", name, e);
+
+			EndpointInfo {
+				name: name.into(),
+				description: name.into(),
+				version: "0.0.0".into(),
+				author: "?".into(),
+				icon_url: "icon.png".into(),
+			}
+		})
+}
+
+struct ZippedDapp {
+	archive: Arc<Vec<u8>>,
+}
+
+impl handler::Dapp for ZippedDapp {
+	type DappFile = ZippedFile;
+
+	fn file(&self, path: &str) -> Option<Self::DappFile> {
+		match read_entry(&self.archive, path) {
+			Ok(content) => Some(ZippedFile::new(mime_guess::guess_mime_type(path).to_string(), content)),
+			Err(e) => {
+				trace!(target: "dapps", "Zipped dapp has no file {}: {:?}", path, e);
+				None
+			},
+		}
+	}
+}
+
+struct ZippedFile {
+	content_type: String,
+	content: Vec<u8>,
+	write_pos: usize,
+	end_pos: usize,
+}
+
+impl ZippedFile {
+	fn new(content_type: String, content: Vec<u8>) -> Self {
+		let len = content.len();
+		ZippedFile {
+			content_type: content_type,
+			content: content,
+			write_pos: 0,
+			end_pos: len,
+		}
+	}
+}
+
+impl handler::DappFile for ZippedFile {
+	fn content_type(&self) -> &str {
+		&self.content_type
+	}
+
+	fn is_drained(&self) -> bool {
+		self.write_pos == self.end_pos
+	}
+
+	fn next_chunk(&mut self) -> &[u8] {
+		&self.content[self.write_pos..self.end_pos]
+	}
+
+	fn bytes_written(&mut self, bytes: usize) {
+		self.write_pos += bytes;
+	}
+
+	fn content_length(&self) -> u64 {
+		self.content.len() as u64
+	}
+
+	fn set_range(&mut self, start: u64, end: u64) {
+		self.write_pos = start as usize;
+		self.end_pos = end as usize;
+	}
+
+	fn etag(&mut self) -> Option<String> {
+		Some(handler::etag(&self.content))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::io::{Cursor, Write};
+	use std::sync::Arc;
+	use zip;
+	use endpoint::Endpoint;
+	use page::handler::{Dapp, DappFile};
+	use super::{ZippedDapp, ZippedPageEndpoint};
+
+	fn zipped(files: &[(&str, &[u8])]) -> Arc<Vec<u8>> {
+		let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+		for &(name, content) in files {
+			writer.start_file(name, zip::CompressionMethod::Stored).unwrap();
+			writer.write_all(content).unwrap();
+		}
+		Arc::new(writer.finish().unwrap().into_inner())
+	}
+
+	#[test]
+	fn should_serve_files_from_archive() {
+		// given
+		let dapp = ZippedDapp {
+			archive: zipped(&[("index.html", b"<html></html>"), ("js/app.js", b"alert(1);")]),
+		};
+
+		// when
+		let mut index = dapp.file("index.html").expect("index should be served");
+		let mut script = dapp.file("js/app.js").expect("script should be served");
+
+		// then
+		assert_eq!(index.content_type(), "text/html");
+		assert_eq!(index.next_chunk(), b"<html></html>");
+		assert_eq!(script.content_type(), "application/javascript");
+		assert_eq!(script.content_length(), 9);
+		assert_eq!(script.next_chunk(), b"alert(1);");
+		assert!(dapp.file("missing.html").is_none());
+	}
+
+	#[test]
+	fn should_read_info_from_manifest() {
+		// given
+		let manifest: &[u8] = br#"{"id":"zipped","name":"Zipped","description":"In memory","version":"0.1","author":"Parity","iconUrl":"icon.png"}"#;
+		let with_manifest = ZippedPageEndpoint::new("zipped", zipped(&[("manifest.json", manifest)]));
+		let without_manifest = ZippedPageEndpoint::new("plain", zipped(&[("index.html", b"")]));
+
+		// then
+		assert_eq!(with_manifest.info().unwrap().name, "Zipped");
+		assert_eq!(with_manifest.info().unwrap().version, "0.1");
+		assert_eq!(without_manifest.info().unwrap().name, "plain");
+	}
+}
@@ -17,9 +17,13 @@
 //! HTTP Authorization implementations
 
 use std::collections::HashMap;
-use hyper::{server, net, header, status};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use hyper::{server, net, header};
 use endpoint::Handler;
-use handlers::{AuthRequiredHandler, ContentHandler};
+use error::DappsError;
+use handlers::AuthRequiredHandler;
 
 /// Authorization result
 pub enum Authorized {
@@ -35,9 +39,62 @@ pub trait Authorization : Send + Sync {
 	fn is_authorized(&self, req: &server::Request<net::HttpStream>)-> Authorized;
 }
 
+/// Outcome of an authorization attempt.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum AuthOutcome {
+	/// Valid credentials were given.
+	Granted,
+	/// Invalid credentials were given.
+	Denied,
+	/// No credentials were given.
+	Missing,
+}
+
+/// Record of a single authorization attempt. The attempted password is never recorded.
+#[derive(Debug, Clone)]
+pub struct AuthRecord {
+	/// Address of the client.
+	pub remote_addr: Option<SocketAddr>,
+	/// When the attempt was made.
+	pub time: SystemTime,
+	/// Username the client tried to authorize as.
+	pub username: Option<String>,
+	/// Outcome of the attempt.
+	pub outcome: AuthOutcome,
+}
+
+/// Receives records of authorization attempts, e.g. for intrusion detection.
+pub trait AuthLogger: Send + Sync {
+	/// Called once for every request checked.
+	fn log(&self, record: &AuthRecord);
+}
+
+/// Logs failed (and optionally successful) authorization attempts under the `dapps` target.
+pub struct LogAuthLogger {
+	/// Whether to log successful attempts too.
+	pub log_granted: bool,
+}
+
+impl AuthLogger for LogAuthLogger {
+	fn log(&self, record: &AuthRecord) {
+		let timestamp = record.time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+		let remote_addr = record.remote_addr.map_or_else(|| "unknown".to_owned(), |addr| format!("{}", addr));
+		match record.outcome {
+			AuthOutcome::Granted if self.log_granted => {
+				info!(target: "dapps", "Authorized request from {} as {:?} at {}", remote_addr, record.username, timestamp);
+			},
+			AuthOutcome::Granted => {},
+			AuthOutcome::Denied | AuthOutcome::Missing => {
+				warn!(target: "dapps", "Unauthorized request from {} as {:?} at {} ({:?})", remote_addr, record.username, timestamp, record.outcome);
+			},
+		}
+	}
+}
+
 /// HTTP Basic Authorization handler
 pub struct HttpBasicAuth {
 	users: HashMap<String, String>,
+	logger: Option<Arc<AuthLogger>>,
 }
 
 /// No-authorization implementation (authorization disabled)
@@ -53,13 +110,22 @@ impl Authorization for HttpBasicAuth {
 	fn is_authorized(&self, req: &server::Request<net::HttpStream>) -> Authorized {
 		let auth = self.check_auth(&req);
 
+		if let Some(ref logger) = self.logger {
+			logger.log(&AuthRecord {
+				remote_addr: remote_addr(req),
+				time: SystemTime::now(),
+				username: username(req),
+				outcome: match auth {
+					Access::Granted => AuthOutcome::Granted,
+					Access::Denied => AuthOutcome::Denied,
+					Access::AuthRequired => AuthOutcome::Missing,
+				},
+			});
+		}
+
 		match auth {
 			Access::Denied => {
-				Authorized::No(Box::new(ContentHandler::new(
-					status::StatusCode::Unauthorized,
-					"<h1>Unauthorized</h1>".into(),
-					"text/html".into(),
-				)))
+				Authorized::No(Box::new(DappsError::Unauthorized.to_handler()))
 			},
 			Access::AuthRequired => {
 				Authorized::No(Box::new(AuthRequiredHandler))
@@ -84,12 +150,24 @@ impl HttpBasicAuth {
 		let mut users = HashMap::new();
 		users.insert(username.to_owned(), password.to_owned());
 		HttpBasicAuth {
-			users: users
+			users: users,
+			logger: None,
 		}
 	}
 
+	/// Report every authorization attempt to given logger.
+	pub fn with_logger(mut self, logger: Arc<AuthLogger>) -> Self {
+		self.logger = Some(logger);
+		self
+	}
+
+	// all users are checked, so the time taken doesn't reveal which username or password matched.
 	fn is_authorized(&self, username: &str, password: &str) -> bool {
-		self.users.get(&username.to_owned()).map_or(false, |pass| pass == password)
+		self.users.iter().fold(false, |authorized, (user, pass)| {
+			let user_matches = constant_time_eq(user.as_bytes(), username.as_bytes());
+			let pass_matches = constant_time_eq(pass.as_bytes(), password.as_bytes());
+			authorized | (user_matches & pass_matches)
+		})
 	}
 
 	fn check_auth(&self, req: &server::Request<net::HttpStream>) -> Access {
@@ -102,3 +180,47 @@ impl HttpBasicAuth {
 		}
 	}
 }
+
+/// Compares two byte strings in time depending only on their lengths, not their contents.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+	if a.len() != b.len() {
+		return false;
+	}
+	a.iter().zip(b.iter()).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+// username the request tries to authorize as, if any.
+fn username(req: &server::Request<net::HttpStream>) -> Option<String> {
+	req.headers().get::<header::Authorization<header::Basic>>()
+		.map(|&header::Authorization(ref basic)| basic.username.clone())
+}
+
+// address of the client which sent the request.
+fn remote_addr(req: &server::Request<net::HttpStream>) -> Option<SocketAddr> {
+	req.transport().0.peer_addr().ok()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{HttpBasicAuth, constant_time_eq};
+
+	#[test]
+	fn should_compare_in_constant_time() {
+		assert!(constant_time_eq(b"", b""));
+		assert!(constant_time_eq(b"secret", b"secret"));
+		assert!(!constant_time_eq(b"secret", b"secreT"));
+		assert!(!constant_time_eq(b"secret", b"secret1"));
+		assert!(!constant_time_eq(b"secret", b""));
+	}
+
+	#[test]
+	fn should_check_username_and_password() {
+		let auth = HttpBasicAuth::single_user("user", "secret");
+
+		assert!(auth.is_authorized("user", "secret"));
+		assert!(!auth.is_authorized("user", "wrong"));
+		assert!(!auth.is_authorized("other", "secret"));
+		assert!(!auth.is_authorized("user", ""));
+		assert!(!auth.is_authorized("", ""));
+	}
+}
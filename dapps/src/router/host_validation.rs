@@ -20,7 +20,7 @@ use hyper::{server, header};
 use hyper::net::HttpStream;
 
 use jsonrpc_http_server::{is_host_header_valid};
-use handlers::ContentHandler;
+use error::DappsError;
 
 pub fn is_valid(request: &server::Request<HttpStream>, bind_address: &str, endpoints: Vec<String>) -> bool {
 	let mut endpoints = endpoints.into_iter()
@@ -40,11 +40,5 @@ pub fn is_valid(request: &server::Request<HttpStream>, bind_address: &str, endpo
 }
 
 pub fn host_invalid_response() -> Box<server::Handler<HttpStream> + Send> {
-	Box::new(ContentHandler::forbidden(
-		r#"
-		<h1>Request with disallowed <code>Host</code> header has been blocked.</h1>
-		<p>Check the URL in your browser address bar.</p>
-		"#.into(),
-		"text/html".into()
-	))
+	Box::new(DappsError::InvalidHost.to_handler())
 }
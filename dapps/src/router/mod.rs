@@ -23,12 +23,14 @@ mod host_validation;
 use DAPPS_DOMAIN;
 use std::sync::Arc;
 use std::collections::HashMap;
-use url::{Url, Host};
+use url::Url;
+use std::str;
 use hyper::{self, server, Next, Encoder, Decoder, Control};
 use hyper::net::HttpStream;
 use apps;
 use apps::fetcher::AppFetcher;
 use endpoint::{Endpoint, Endpoints, EndpointPath};
+use error::DappsError;
 use handlers::{Redirection, extract_url};
 use self::auth::{Authorization, Authorized};
 
@@ -41,14 +43,43 @@ pub enum SpecialEndpoint {
 	None,
 }
 
+/// Endpoint a request maps to, determined by its host and path alone.
+#[derive(Debug, PartialEq)]
+pub enum Resolved {
+	/// Special endpoint, takes precedence over any application.
+	Special(SpecialEndpoint),
+	/// Application with given id, selected either by subdomain or by path prefix.
+	App(String),
+	/// No application selected.
+	MainPage,
+}
+
+/// How to treat requests forwarded by a reverse proxy.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ProxyMode {
+	/// Resolve dapps using the `Host` of the request only.
+	Disabled,
+	/// Trust the `X-Forwarded-Host` header set by a reverse proxy and resolve
+	/// dapps using the host it contains. Only enable when the server is reachable
+	/// through the proxy alone, since clients could spoof the header otherwise.
+	TrustForwardedHost,
+}
+
+impl Default for ProxyMode {
+	fn default() -> Self {
+		ProxyMode::Disabled
+	}
+}
+
 pub struct Router<A: Authorization + 'static> {
 	control: Option<Control>,
-	main_page: &'static str,
+	main_page: String,
 	endpoints: Arc<Endpoints>,
 	fetch: Arc<AppFetcher>,
 	special: Arc<HashMap<SpecialEndpoint, Box<Endpoint>>>,
 	authorization: Arc<A>,
 	bind_address: String,
+	proxy_mode: ProxyMode,
 	handler: Box<server::Handler<HttpStream> + Send>,
 }
 
@@ -70,31 +101,42 @@ impl<A: Authorization + 'static> server::Handler<HttpStream> for Router<A> {
 
 		// Choose proper handler depending on path / domain
 		let url = extract_url(&req);
-		let endpoint = extract_endpoint(&url);
+		let host = {
+			let forwarded_host = req.headers().get_raw("X-Forwarded-Host")
+				.and_then(|raw| raw.first())
+				.and_then(|raw| str::from_utf8(raw).ok());
+			trusted_host(self.proxy_mode, forwarded_host)
+		};
+		let endpoint = extract_endpoint(&url, host.as_ref().map(|host| host.as_str()));
 
 		self.handler = match endpoint {
 			// First check special endpoints
-			(ref path, ref endpoint) if self.special.contains_key(endpoint) => {
+			(ref path, Resolved::Special(ref endpoint)) if self.special.contains_key(endpoint) => {
 				self.special.get(endpoint).unwrap().to_handler(path.clone().unwrap_or_default())
 			},
+			// Special endpoints which were disabled
+			(_, Resolved::Special(_)) => not_found(),
 			// Then delegate to dapp
-			(Some(ref path), _) if self.endpoints.contains_key(&path.app_id) => {
-				self.endpoints.get(&path.app_id).unwrap().to_handler(path.clone())
+			(Some(ref path), Resolved::App(ref app_id)) if self.endpoints.contains_key(app_id) => {
+				self.endpoints.get(app_id).unwrap().to_handler(path.clone())
 			},
 			// Try to resolve and fetch dapp
-			(Some(ref path), _) if self.fetch.contains(&path.app_id) => {
+			(Some(ref path), Resolved::App(ref app_id)) if self.fetch.contains(app_id) => {
 				let control = self.control.take().expect("on_request is called only once, thus control is always defined.");
 				self.fetch.to_handler(path.clone(), control)
 			},
 			// Redirection to main page (maybe 404 instead?)
 			(Some(ref path), _) if *req.method() == hyper::method::Method::Get => {
-				let address = apps::redirection_address(path.using_dapps_domains, self.main_page);
+				let address = apps::redirection_address(path.using_dapps_domains, &self.main_page);
+				Redirection::new(address.as_str())
+			},
+			// Root goes to the landing page
+			(None, Resolved::MainPage) if *req.method() == hyper::method::Method::Get => {
+				let address = apps::redirection_address(false, &self.main_page);
 				Redirection::new(address.as_str())
 			},
 			// RPC by default
-			_ => {
-				self.special.get(&SpecialEndpoint::Rpc).unwrap().to_handler(EndpointPath::default())
-			}
+			_ => rpc_handler(&self.special),
 		};
 
 		// Delegate on_request to proper handler
@@ -120,15 +162,16 @@ impl<A: Authorization + 'static> server::Handler<HttpStream> for Router<A> {
 impl<A: Authorization> Router<A> {
 	pub fn new(
 		control: Control,
-		main_page: &'static str,
+		main_page: String,
 		app_fetcher: Arc<AppFetcher>,
 		endpoints: Arc<Endpoints>,
 		special: Arc<HashMap<SpecialEndpoint, Box<Endpoint>>>,
 		authorization: Arc<A>,
 		bind_address: String,
+		proxy_mode: ProxyMode,
 		) -> Self {
 
-		let handler = special.get(&SpecialEndpoint::Rpc).unwrap().to_handler(EndpointPath::default());
+		let handler = rpc_handler(&special);
 		Router {
 			control: Some(control),
 			main_page: main_page,
@@ -137,119 +180,240 @@ impl<A: Authorization> Router<A> {
 			special: special,
 			authorization: authorization,
 			bind_address: bind_address,
+			proxy_mode: proxy_mode,
 			handler: handler,
 		}
 	}
 }
 
-fn extract_endpoint(url: &Option<Url>) -> (Option<EndpointPath>, SpecialEndpoint) {
-	fn special_endpoint(url: &Url) -> SpecialEndpoint {
-		if url.path.len() <= 1 {
-			return SpecialEndpoint::None;
-		}
+// handler of the RPC endpoint, or a `404` if it's disabled.
+fn rpc_handler(special: &HashMap<SpecialEndpoint, Box<Endpoint>>) -> Box<server::Handler<HttpStream> + Send> {
+	match special.get(&SpecialEndpoint::Rpc) {
+		Some(rpc) => rpc.to_handler(EndpointPath::default()),
+		None => not_found(),
+	}
+}
+
+fn not_found() -> Box<server::Handler<HttpStream> + Send> {
+	Box::new(DappsError::UnknownEndpoint.to_handler())
+}
 
-		match url.path[0].as_ref() {
-			apps::RPC_PATH => SpecialEndpoint::Rpc,
-			apps::API_PATH => SpecialEndpoint::Api,
-			apps::UTILS_PATH => SpecialEndpoint::Utils,
-			_ => SpecialEndpoint::None,
+/// Resolve the endpoint a request maps to from its host and path.
+///
+/// Special endpoints (`/rpc/`, `/api/`, `/parity-utils/`) are available on every domain
+/// and take precedence. Otherwise, when `using_dapps_domain` is set, a `*.parity` host
+/// selects the application by subdomain; failing that, the first path segment does.
+/// A request with neither resolves to the main page.
+pub fn resolve_endpoint(host: Option<&str>, path: &str, using_dapps_domain: bool) -> Resolved {
+	let segments = path_segments(path);
+	if segments.len() > 1 {
+		match segments[0] {
+			apps::RPC_PATH => return Resolved::Special(SpecialEndpoint::Rpc),
+			apps::API_PATH => return Resolved::Special(SpecialEndpoint::Api),
+			apps::UTILS_PATH => return Resolved::Special(SpecialEndpoint::Utils),
+			_ => {},
 		}
 	}
 
-	match *url {
-		Some(ref url) => match url.host {
-			Host::Domain(ref domain) if domain.ends_with(DAPPS_DOMAIN) => {
-				let len = domain.len() - DAPPS_DOMAIN.len();
-				let id = domain[0..len].to_owned();
-
-				(Some(EndpointPath {
-					app_id: id,
-					host: domain.clone(),
-					port: url.port,
-					using_dapps_domains: true,
-				}), special_endpoint(url))
-			},
-			_ if url.path.len() > 1 => {
-				let id = url.path[0].clone();
-				(Some(EndpointPath {
-					app_id: id.clone(),
-					host: format!("{}", url.host),
-					port: url.port,
-					using_dapps_domains: false,
-				}), special_endpoint(url))
-			},
-			_ => (None, special_endpoint(url)),
+	match app_id(host, &segments, using_dapps_domain) {
+		Some((id, _)) => Resolved::App(id),
+		None => Resolved::MainPage,
+	}
+}
+
+// split a path into segments, ignoring the leading slash and query string.
+fn path_segments(path: &str) -> Vec<&str> {
+	let path = path.split('?').next().unwrap_or("");
+	let path = if path.starts_with('/') { &path[1..] } else { path };
+	path.split('/').collect()
+}
+
+// get the application id and whether it was selected by subdomain.
+fn app_id(host: Option<&str>, segments: &[&str], using_dapps_domain: bool) -> Option<(String, bool)> {
+	match host {
+		Some(host) if using_dapps_domain && host.ends_with(DAPPS_DOMAIN) => {
+			let len = host.len() - DAPPS_DOMAIN.len();
+			Some((host[0..len].to_owned(), true))
 		},
-		_ => (None, SpecialEndpoint::None)
+		_ if segments.len() > 1 => Some((segments[0].to_owned(), false)),
+		_ => None,
 	}
 }
 
+// get the host a reverse proxy forwarded the request for, if it is trusted.
+// only the first (client-facing) host is used when the request passed several proxies.
+fn trusted_host(mode: ProxyMode, forwarded_host: Option<&str>) -> Option<String> {
+	let forwarded_host = match (mode, forwarded_host) {
+		(ProxyMode::TrustForwardedHost, Some(host)) => host,
+		_ => return None,
+	};
+
+	let host = forwarded_host.split(',').next().unwrap_or("").trim();
+	let host = match host.find(':') {
+		Some(pos) if !host.starts_with('[') => &host[..pos],
+		_ => host,
+	};
+
+	match host.is_empty() {
+		true => None,
+		false => Some(host.to_owned()),
+	}
+}
+
+// resolve the endpoint for given url, using `host` in place of the url's host when given.
+fn extract_endpoint(url: &Option<Url>, host: Option<&str>) -> (Option<EndpointPath>, Resolved) {
+	let url = match *url {
+		Some(ref url) => url,
+		None => return (None, Resolved::MainPage),
+	};
+
+	let host = match host {
+		Some(host) => host.to_owned(),
+		None => format!("{}", url.host),
+	};
+	let path = format!("/{}", url.path.join("/"));
+
+	let resolved = resolve_endpoint(Some(&host), &path, true);
+	let endpoint = app_id(Some(&host), &path_segments(&path), true).map(|(id, using_dapps_domains)| EndpointPath {
+		app_id: id,
+		host: host.clone(),
+		port: url.port,
+		using_dapps_domains: using_dapps_domains,
+	});
+
+	(endpoint, resolved)
+}
+
 #[test]
 fn should_extract_endpoint() {
-	assert_eq!(extract_endpoint(&None), (None, SpecialEndpoint::None));
+	assert_eq!(extract_endpoint(&None, None), (None, Resolved::MainPage));
 
 	// With path prefix
 	assert_eq!(
-		extract_endpoint(&Url::parse("http://localhost:8080/status/index.html").ok()),
+		extract_endpoint(&Url::parse("http://localhost:8080/status/index.html").ok(), None),
 		(Some(EndpointPath {
 			app_id: "status".to_owned(),
 			host: "localhost".to_owned(),
 			port: 8080,
 			using_dapps_domains: false,
-		}), SpecialEndpoint::None)
+		}), Resolved::App("status".to_owned()))
 	);
 
 	// With path prefix
 	assert_eq!(
-		extract_endpoint(&Url::parse("http://localhost:8080/rpc/").ok()),
+		extract_endpoint(&Url::parse("http://localhost:8080/rpc/").ok(), None),
 		(Some(EndpointPath {
 			app_id: "rpc".to_owned(),
 			host: "localhost".to_owned(),
 			port: 8080,
 			using_dapps_domains: false,
-		}), SpecialEndpoint::Rpc)
+		}), Resolved::Special(SpecialEndpoint::Rpc))
 	);
 
 	assert_eq!(
-		extract_endpoint(&Url::parse("http://my.status.parity/parity-utils/inject.js").ok()),
+		extract_endpoint(&Url::parse("http://my.status.parity/parity-utils/inject.js").ok(), None),
 		(Some(EndpointPath {
 			app_id: "my.status".to_owned(),
 			host: "my.status.parity".to_owned(),
 			port: 80,
 			using_dapps_domains: true,
-		}), SpecialEndpoint::Utils)
+		}), Resolved::Special(SpecialEndpoint::Utils))
 	);
 
 	// By Subdomain
 	assert_eq!(
-		extract_endpoint(&Url::parse("http://my.status.parity/test.html").ok()),
+		extract_endpoint(&Url::parse("http://my.status.parity/test.html").ok(), None),
 		(Some(EndpointPath {
 			app_id: "my.status".to_owned(),
 			host: "my.status.parity".to_owned(),
 			port: 80,
 			using_dapps_domains: true,
-		}), SpecialEndpoint::None)
+		}), Resolved::App("my.status".to_owned()))
 	);
 
 	// RPC by subdomain
 	assert_eq!(
-		extract_endpoint(&Url::parse("http://my.status.parity/rpc/").ok()),
+		extract_endpoint(&Url::parse("http://my.status.parity/rpc/").ok(), None),
 		(Some(EndpointPath {
 			app_id: "my.status".to_owned(),
 			host: "my.status.parity".to_owned(),
 			port: 80,
 			using_dapps_domains: true,
-		}), SpecialEndpoint::Rpc)
+		}), Resolved::Special(SpecialEndpoint::Rpc))
 	);
 
 	// API by subdomain
 	assert_eq!(
-		extract_endpoint(&Url::parse("http://my.status.parity/api/").ok()),
+		extract_endpoint(&Url::parse("http://my.status.parity/api/").ok(), None),
 		(Some(EndpointPath {
 			app_id: "my.status".to_owned(),
 			host: "my.status.parity".to_owned(),
 			port: 80,
 			using_dapps_domains: true,
-		}), SpecialEndpoint::Api)
+		}), Resolved::Special(SpecialEndpoint::Api))
 	);
 }
+
+#[test]
+fn should_resolve_endpoint() {
+	let cases = vec![
+		// (host, path, using_dapps_domain, expected)
+		(Some("foo.parity"), "/", true, Resolved::App("foo".to_owned())),
+		(Some("foo.parity"), "/index.html", true, Resolved::App("foo".to_owned())),
+		(Some("foo.parity"), "/rpc/", true, Resolved::Special(SpecialEndpoint::Rpc)),
+		(Some("foo.parity"), "/parity-utils/inject.js", true, Resolved::Special(SpecialEndpoint::Utils)),
+		(Some("foo.parity"), "/bar/", false, Resolved::App("bar".to_owned())),
+		(Some("foo.parity"), "/", false, Resolved::MainPage),
+		(Some("localhost"), "/foo/", true, Resolved::App("foo".to_owned())),
+		(Some("localhost"), "/foo/index.html?x=1", true, Resolved::App("foo".to_owned())),
+		(Some("localhost"), "/foo", true, Resolved::MainPage),
+		(Some("localhost"), "/rpc/", true, Resolved::Special(SpecialEndpoint::Rpc)),
+		(Some("localhost"), "/api/apps", true, Resolved::Special(SpecialEndpoint::Api)),
+		(Some("localhost"), "/rpc", true, Resolved::MainPage),
+		(Some("localhost"), "/", true, Resolved::MainPage),
+		(None, "/foo/", true, Resolved::App("foo".to_owned())),
+		(None, "/", true, Resolved::MainPage),
+	];
+
+	for (host, path, using_dapps_domain, expected) in cases {
+		assert_eq!(resolve_endpoint(host, path, using_dapps_domain), expected);
+	}
+}
+
+#[test]
+fn should_only_trust_forwarded_host_in_proxy_mode() {
+	assert_eq!(trusted_host(ProxyMode::Disabled, None), None);
+	assert_eq!(trusted_host(ProxyMode::Disabled, Some("foo.parity")), None);
+	assert_eq!(trusted_host(ProxyMode::TrustForwardedHost, None), None);
+	assert_eq!(trusted_host(ProxyMode::TrustForwardedHost, Some("")), None);
+	assert_eq!(trusted_host(ProxyMode::TrustForwardedHost, Some("foo.parity")), Some("foo.parity".to_owned()));
+	assert_eq!(trusted_host(ProxyMode::TrustForwardedHost, Some("foo.parity:8080")), Some("foo.parity".to_owned()));
+	assert_eq!(trusted_host(ProxyMode::TrustForwardedHost, Some("foo.parity, proxy.local")), Some("foo.parity".to_owned()));
+}
+
+#[test]
+fn should_extract_endpoint_behind_proxy() {
+	let url = Url::parse("http://127.0.0.1:8080/index.html").ok();
+
+	for &mode in &[ProxyMode::Disabled, ProxyMode::TrustForwardedHost] {
+		// without the header the request host is used.
+		let host = trusted_host(mode, None);
+		assert_eq!(
+			extract_endpoint(&url, host.as_ref().map(|h| h.as_str())),
+			(None, Resolved::MainPage)
+		);
+
+		// the header is only honored in proxy mode.
+		let host = trusted_host(mode, Some("foo.parity"));
+		let expected = match mode {
+			ProxyMode::Disabled => (None, Resolved::MainPage),
+			ProxyMode::TrustForwardedHost => (Some(EndpointPath {
+				app_id: "foo".to_owned(),
+				host: "foo.parity".to_owned(),
+				port: 8080,
+				using_dapps_domains: true,
+			}), Resolved::App("foo".to_owned())),
+		};
+		assert_eq!(extract_endpoint(&url, host.as_ref().map(|h| h.as_str())), expected);
+	}
+}
@@ -15,10 +15,16 @@
 // along with Parity.  If not, see <http://www.gnu.org/licenses/>.
 
 use std::sync::{Arc, Mutex};
+use hyper::{header, server, Decoder, Encoder, Next};
+use hyper::server::Handler as HttpHandler;
+use hyper::net::HttpStream;
 use jsonrpc_core::IoHandler;
 use jsonrpc_http_server::{ServerHandler, PanicHandler, AccessControlAllowOrigin};
 use endpoint::{Endpoint, EndpointPath, Handler};
 
+/// Responses with bodies larger than this (in bytes) are sent using chunked transfer encoding.
+pub const CHUNKED_RESPONSE_THRESHOLD: u64 = 64 * 1024;
+
 pub fn rpc(handler: Arc<IoHandler>, panic_handler: Arc<Mutex<Option<Box<Fn() -> () + Send>>>>) -> Box<Endpoint> {
 	Box::new(RpcEndpoint {
 		handler: handler,
@@ -39,6 +45,44 @@ struct RpcEndpoint {
 impl Endpoint for RpcEndpoint {
 	fn to_handler(&self, _path: EndpointPath) -> Box<Handler> {
 		let panic_handler = PanicHandler { handler: self.panic_handler.clone() };
-		Box::new(ServerHandler::new(self.handler.clone(), self.cors_domain.clone(), self.allowed_hosts.clone(), panic_handler))
+		Box::new(ChunkedHandler {
+			handler: ServerHandler::new(self.handler.clone(), self.cors_domain.clone(), self.allowed_hosts.clone(), panic_handler),
+		})
+	}
+}
+
+/// Sends large responses of the upstream RPC handler using chunked transfer encoding.
+///
+/// Only the framing changes: `IoHandler::handle_request` still serializes the whole
+/// response into memory before anything is written to the client.
+struct ChunkedHandler {
+	handler: ServerHandler,
+}
+
+impl HttpHandler<HttpStream> for ChunkedHandler {
+	fn on_request(&mut self, request: server::Request<HttpStream>) -> Next {
+		self.handler.on_request(request)
+	}
+
+	fn on_request_readable(&mut self, decoder: &mut Decoder<HttpStream>) -> Next {
+		self.handler.on_request_readable(decoder)
+	}
+
+	fn on_response(&mut self, res: &mut server::Response) -> Next {
+		let next = self.handler.on_response(res);
+		let len = res.headers().get::<header::ContentLength>().map(|len| len.0);
+		if let Some(len) = len {
+			if len > CHUNKED_RESPONSE_THRESHOLD {
+				// length is not announced, the encoder frames each write as a separate chunk.
+				let headers = res.headers_mut();
+				headers.remove::<header::ContentLength>();
+				headers.set(header::TransferEncoding(vec![header::Encoding::Chunked]));
+			}
+		}
+		next
+	}
+
+	fn on_response_writable(&mut self, encoder: &mut Encoder<HttpStream>) -> Next {
+		self.handler.on_response_writable(encoder)
 	}
 }
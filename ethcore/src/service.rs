@@ -22,7 +22,7 @@ use spec::Spec;
 use error::*;
 use client::{Client, ClientConfig, ChainNotify};
 use miner::Miner;
-use snapshot::ManifestData;
+use snapshot::{ManifestData, RestorationMode};
 use snapshot::service::Service as SnapshotService;
 use std::sync::atomic::AtomicBool;
 
@@ -46,6 +46,10 @@ pub enum ClientIoMessage {
 	FeedStateChunk(H256, Bytes),
 	/// Feed a block chunk to the snapshot service
 	FeedBlockChunk(H256, Bytes),
+	/// Take a snapshot for the block with given number.
+	TakeSnapshot(u64),
+	/// Finalize a snapshot restoration whose last chunk was fed.
+	FinalizeRestoration,
 }
 
 /// Client service setup. Creates and registers client and network services with the IO subsystem.
@@ -164,12 +168,23 @@ impl IoHandler<ClientIoMessage> for ClientIoHandler {
 			ClientIoMessage::BlockVerified => { self.client.import_verified_blocks(); }
 			ClientIoMessage::NewTransactions(ref transactions) => { self.client.import_queued_transactions(transactions); }
 			ClientIoMessage::BeginRestoration(ref manifest) => {
-				if let Err(e) = self.snapshot.init_restore(manifest.clone()) {
+				if let Err(e) = self.snapshot.init_restore(manifest.clone(), RestorationMode::Full) {
 					warn!("Failed to initialize snapshot restoration: {}", e);
 				}
 			}
-			ClientIoMessage::FeedStateChunk(ref hash, ref chunk) => self.snapshot.feed_state_chunk(*hash, chunk),
-			ClientIoMessage::FeedBlockChunk(ref hash, ref chunk) => self.snapshot.feed_block_chunk(*hash, chunk),
+			ClientIoMessage::FeedStateChunk(ref hash, ref chunk) => {
+				self.snapshot.feed_state_chunk(*hash, chunk);
+				self.snapshot.queued_chunk_done();
+			}
+			ClientIoMessage::FeedBlockChunk(ref hash, ref chunk) => {
+				self.snapshot.feed_block_chunk(*hash, chunk);
+				self.snapshot.queued_chunk_done();
+			}
+			ClientIoMessage::FinalizeRestoration => self.snapshot.finalize_pending_restoration(),
+			ClientIoMessage::TakeSnapshot(num) => {
+				// errors are logged by the snapshot service.
+				let _ = self.snapshot.take_snapshot(&*self.client, num);
+			}
 			_ => {} // ignore other messages
 		}
 	}
@@ -43,6 +43,14 @@ pub enum Error {
 	Trie(TrieError),
 	/// Decoder error.
 	Decoder(DecoderError),
+	/// Chunk declaring an uncompressed size above the maximum.
+	ChunkTooLarge(usize),
+	/// Restored chain is missing the given block.
+	ChainGap(u64),
+	/// Chunk contents don't hash to the given chunk hash.
+	ChunkHashMismatch(H256),
+	/// Snapshot creation was aborted.
+	SnapshotAborted,
 	/// Io error.
 	Io(::std::io::Error),
 }
@@ -55,8 +63,18 @@ impl fmt::Display for Error {
 			Error::IncompleteChain => write!(f, "Cannot create snapshot due to incomplete chain."),
 			Error::OldBlockPrunedDB => write!(f, "Attempted to create a snapshot at an old block while using \
 				a pruned database. Please re-run with the --pruning archive flag."),
-			Error::MissingCode(ref missing) => write!(f, "Incomplete snapshot: {} contract codes not found.", missing.len()),
+			Error::MissingCode(ref missing) => {
+				try!(write!(f, "Incomplete snapshot: {} contract codes not found:", missing.len()));
+				for hash in missing {
+					try!(write!(f, " {:?}", hash));
+				}
+				Ok(())
+			}
 			Error::UnrecognizedCodeState(state) => write!(f, "Unrecognized code encoding ({})", state),
+			Error::ChunkTooLarge(size) => write!(f, "Chunk too large: {} bytes uncompressed, max is {}", size, super::MAX_CHUNK_SIZE),
+			Error::ChainGap(num) => write!(f, "Restored chain has a gap: block #{} is missing.", num),
+			Error::ChunkHashMismatch(ref hash) => write!(f, "Chunk contents don't match its hash {}", hash),
+			Error::SnapshotAborted => write!(f, "Snapshot was aborted."),
 			Error::Io(ref err) => err.fmt(f),
 			Error::Decoder(ref err) => err.fmt(f),
 			Error::Trie(ref err) => err.fmt(f),
@@ -20,12 +20,14 @@
 //! Packed snapshots are written to a single file, and loose snapshots are
 //! written to multiple files in one directory.
 
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::io::{self, Read, Seek, SeekFrom, Write};
 use std::fs::{self, File};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-use util::Bytes;
+use util::{Bytes, Hashable, Mutex};
 use util::hash::H256;
 use util::rlp::{self, Encodable, RlpStream, UntrustedRlp, Stream, View};
 
@@ -297,10 +299,67 @@ impl SnapshotReader for PackedReader {
 	}
 }
 
+// least recently used chunks, bounded by their total size in bytes.
+struct LruChunkCache {
+	max_bytes: usize,
+	bytes: usize,
+	order: VecDeque<H256>,
+	chunks: HashMap<H256, Bytes>,
+}
+
+impl LruChunkCache {
+	fn new(max_bytes: usize) -> Self {
+		LruChunkCache {
+			max_bytes: max_bytes,
+			bytes: 0,
+			order: VecDeque::new(),
+			chunks: HashMap::new(),
+		}
+	}
+
+	fn get(&mut self, hash: &H256) -> Option<Bytes> {
+		let chunk = match self.chunks.get(hash) {
+			Some(chunk) => chunk.clone(),
+			None => return None,
+		};
+
+		// mark as most recently used.
+		if let Some(pos) = self.order.iter().position(|h| h == hash) {
+			self.order.remove(pos);
+		}
+		self.order.push_back(*hash);
+		Some(chunk)
+	}
+
+	fn insert(&mut self, hash: H256, chunk: Bytes) {
+		// chunks which would never fit aren't worth evicting everything else for.
+		if self.chunks.contains_key(&hash) || chunk.len() > self.max_bytes {
+			return;
+		}
+
+		while self.bytes + chunk.len() > self.max_bytes {
+			match self.order.pop_front() {
+				Some(evicted) => {
+					let evicted = self.chunks.remove(&evicted).expect("every hash in order has a chunk; qed");
+					self.bytes -= evicted.len();
+				}
+				None => break,
+			}
+		}
+
+		self.bytes += chunk.len();
+		self.order.push_back(hash);
+		self.chunks.insert(hash, chunk);
+	}
+}
+
 /// reader for "loose" snapshots
 pub struct LooseReader {
 	dir: PathBuf,
-	manifest: ManifestData,
+	manifest: Arc<ManifestData>,
+	cache: Option<Mutex<LruChunkCache>>,
+	disk_reads: AtomicUsize,
+	verify: bool,
 }
 
 impl LooseReader {
@@ -319,9 +378,54 @@ impl LooseReader {
 
 		Ok(LooseReader {
 			dir: dir,
-			manifest: manifest,
+			manifest: Arc::new(manifest),
+			cache: None,
+			disk_reads: AtomicUsize::new(0),
+			verify: false,
 		})
 	}
+
+	/// Check that chunks read from disk still hash to the requested hash,
+	/// failing to read corrupted ones. Off by default.
+	pub fn with_verification(mut self) -> Self {
+		self.verify = true;
+		self
+	}
+
+	/// Keep recently read chunks in memory, up to `max_bytes` of chunk data in total.
+	/// Least recently used chunks are evicted first.
+	pub fn with_cache(mut self, max_bytes: usize) -> Self {
+		self.cache = Some(Mutex::new(LruChunkCache::new(max_bytes)));
+		self
+	}
+
+	/// Get the manifest, parsed once when the reader was created, without cloning it.
+	pub fn shared_manifest(&self) -> Arc<ManifestData> {
+		self.manifest.clone()
+	}
+
+	/// Number of chunks read from disk so far, i.e. not served from the cache.
+	pub fn disk_reads(&self) -> usize {
+		self.disk_reads.load(Ordering::Relaxed)
+	}
+
+	/// Create a `LooseReader` for each snapshot found directly within the given
+	/// directory. Subdirectories without a valid manifest are skipped.
+	pub fn find_all(root: &Path) -> io::Result<Vec<Self>> {
+		let mut readers = Vec::new();
+		for entry in try!(fs::read_dir(root)) {
+			let path = try!(entry).path();
+			if !path.is_dir() {
+				continue;
+			}
+
+			if let Ok(reader) = LooseReader::new(path) {
+				readers.push(reader);
+			}
+		}
+
+		Ok(readers)
+	}
 }
 
 impl SnapshotReader for LooseReader {
@@ -330,6 +434,10 @@ impl SnapshotReader for LooseReader {
 	}
 
 	fn chunk(&self, hash: H256) -> io::Result<Bytes> {
+		if let Some(chunk) = self.cache.as_ref().and_then(|cache| cache.lock().get(&hash)) {
+			return Ok(chunk);
+		}
+
 		let mut path = self.dir.clone();
 		path.push(hash.hex());
 
@@ -337,6 +445,16 @@ impl SnapshotReader for LooseReader {
 		let mut file = try!(File::open(&path));
 
 		try!(file.read_to_end(&mut buf));
+		self.disk_reads.fetch_add(1, Ordering::Relaxed);
+
+		if self.verify && buf.sha3() != hash {
+			warn!(target: "snapshot", "chunk {} on disk is corrupted", hash.hex());
+			return Err(io::Error::new(io::ErrorKind::InvalidData, "chunk contents don't match its hash"));
+		}
+
+		if let Some(ref cache) = self.cache {
+			cache.lock().insert(hash, buf.clone());
+		}
 
 		Ok(buf)
 	}
@@ -31,7 +31,7 @@ use util::memorydb::MemoryDB;
 use util::Mutex;
 use util::hash::{FixedHash, H256};
 use util::journaldb::{self, Algorithm, JournalDB};
-use util::kvdb::Database;
+use util::kvdb::KeyValueDB;
 use util::rlp::{DecoderError, RlpStream, Stream, UntrustedRlp, View, Compressible, RlpType};
 use util::rlp::SHA3_NULL_RLP;
 use util::trie::{TrieDB, TrieDBMut, Trie, TrieMut};
@@ -41,10 +41,11 @@ use self::block::AbridgedBlock;
 use self::io::SnapshotWriter;
 
 use crossbeam::{scope, ScopedJoinHandle};
+use ethkey::{self, Public, Signature};
 use rand::{Rng, OsRng};
 
 pub use self::error::Error;
-pub use self::service::{RestorationStatus, Service, SnapshotService};
+pub use self::service::{ReadOnlyService, RestorationMode, RestorationStatus, RestorationSummary, Service, SnapshotInfo, SnapshotService};
 
 pub mod io;
 pub mod service;
@@ -59,6 +60,10 @@ mod tests;
 // Try to have chunks be around 4MB (before compression)
 const PREFERRED_CHUNK_SIZE: usize = 4 * 1024 * 1024;
 
+/// Maximum uncompressed size of a chunk accepted during restoration.
+/// Chunks may slightly exceed the preferred size, but nothing close to this.
+pub const MAX_CHUNK_SIZE: usize = 8 * PREFERRED_CHUNK_SIZE;
+
 // How many blocks to include in a snapshot, starting from the head of the chain.
 const SNAPSHOT_BLOCKS: u64 = 30000;
 
@@ -69,6 +74,7 @@ pub struct Progress {
 	blocks: AtomicUsize,
 	size: AtomicUsize, // Todo [rob] use Atomicu64 when it stabilizes.
 	done: AtomicBool,
+	abort: AtomicBool,
 }
 
 impl Progress {
@@ -84,6 +90,25 @@ impl Progress {
 	/// Whether the snapshot is complete.
 	pub fn done(&self) -> bool  { self.done.load(Ordering::SeqCst) }
 
+	/// Request the snapshot to be aborted. Chunkers stop before writing their next chunk.
+	pub fn abort(&self) { self.abort.store(true, Ordering::SeqCst) }
+
+	/// Whether the snapshot was requested to be aborted.
+	pub fn aborted(&self) -> bool { self.abort.load(Ordering::SeqCst) }
+
+	// reset the counters before taking a new snapshot.
+	// an abort request is left alone: it's cleared once the snapshot it was meant for is over.
+	fn reset(&self) {
+		self.accounts.store(0, Ordering::SeqCst);
+		self.blocks.store(0, Ordering::SeqCst);
+		self.size.store(0, Ordering::SeqCst);
+		self.done.store(false, Ordering::SeqCst);
+	}
+
+	// forget an abort request once the snapshot it was meant for is over.
+	fn clear_abort(&self) {
+		self.abort.store(false, Ordering::SeqCst);
+	}
 }
 /// Take a snapshot using the given blockchain, starting block hash, and database, writing into the given writer.
 pub fn take_snapshot<W: SnapshotWriter + Send>(
@@ -187,6 +212,8 @@ impl<'a> BlockChunker<'a> {
 	//
 	// we preface each chunk with the parent of the first block's details.
 	fn write_chunk(&mut self) -> Result<(), Error> {
+		if self.progress.aborted() { return Err(Error::SnapshotAborted) }
+
 		// since the block we're inspecting now doesn't go into the
 		// chunk if it's too large, the current hash is the parent hash
 		// for the first block in that chunk.
@@ -291,6 +318,8 @@ impl<'a> StateChunker<'a> {
 	// Write out the buffer to disk, pushing the created chunk's hash to
 	// the list.
 	fn write_chunk(&mut self) -> Result<(), Error> {
+		if self.progress.aborted() { return Err(Error::SnapshotAborted) }
+
 		let num_entries = self.rlps.len();
 		let mut stream = RlpStream::new_list(num_entries);
 		for rlp in self.rlps.drain(..) {
@@ -382,6 +411,17 @@ impl ManifestData {
 		stream.out()
 	}
 
+	/// Keccak-256 hash of the RLP encoding, which nodes serving the snapshot sign.
+	pub fn hash(&self) -> H256 {
+		self.clone().into_rlp().sha3()
+	}
+
+	/// Check that the manifest was signed by the node with given public key,
+	/// see `Service::signed_manifest`.
+	pub fn verify_signature(&self, signer: &Public, signature: &Signature) -> bool {
+		ethkey::verify_public(signer, signature, &self.hash()).unwrap_or(false)
+	}
+
 	/// Try to restore manifest data from raw bytes, interpreted as RLP.
 	pub fn from_rlp(raw: &[u8]) -> Result<Self, DecoderError> {
 		let decoder = UntrustedRlp::new(raw);
@@ -400,6 +440,55 @@ impl ManifestData {
 			block_hash: block_hash,
 		})
 	}
+
+	/// Compare with a newer manifest, finding the chunks which only one of them has.
+	/// The added chunks are the ones to fetch when this snapshot is already present.
+	pub fn diff(&self, other: &ManifestData) -> ManifestDiff {
+		fn changes(old: &[H256], new: &[H256]) -> (HashSet<H256>, HashSet<H256>) {
+			let old: HashSet<H256> = old.iter().cloned().collect();
+			let new: HashSet<H256> = new.iter().cloned().collect();
+			(new.difference(&old).cloned().collect(), old.difference(&new).cloned().collect())
+		}
+
+		let (added_state, removed_state) = changes(&self.state_hashes, &other.state_hashes);
+		let (added_blocks, removed_blocks) = changes(&self.block_hashes, &other.block_hashes);
+
+		ManifestDiff {
+			added_state: added_state,
+			removed_state: removed_state,
+			added_blocks: added_blocks,
+			removed_blocks: removed_blocks,
+		}
+	}
+}
+
+/// Chunks which differ between two manifests.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ManifestDiff {
+	/// State chunks only in the newer manifest.
+	pub added_state: HashSet<H256>,
+	/// State chunks only in the older manifest.
+	pub removed_state: HashSet<H256>,
+	/// Block chunks only in the newer manifest.
+	pub added_blocks: HashSet<H256>,
+	/// Block chunks only in the older manifest.
+	pub removed_blocks: HashSet<H256>,
+}
+
+impl ManifestDiff {
+	/// Whether both manifests list the same chunks.
+	pub fn is_empty(&self) -> bool {
+		self.added_state.is_empty() && self.removed_state.is_empty()
+			&& self.added_blocks.is_empty() && self.removed_blocks.is_empty()
+	}
+}
+
+/// Check a chunk received from the network before feeding it anywhere:
+/// its contents must hash to `hash`, and `hash` must be one of the manifest's
+/// state or block chunks.
+pub fn verify_chunk(manifest: &ManifestData, hash: H256, chunk: &[u8]) -> bool {
+	let expected = manifest.state_hashes.contains(&hash) || manifest.block_hashes.contains(&hash);
+	expected && chunk.sha3() == hash
 }
 
 /// Used to rebuild the state trie piece by piece.
@@ -408,16 +497,18 @@ pub struct StateRebuilder {
 	state_root: H256,
 	code_map: HashMap<H256, Bytes>, // maps code hashes to code itself.
 	missing_code: HashMap<H256, Vec<H256>>, // maps code hashes to lists of accounts missing that code.
+	accounts_rebuilt: usize,
 }
 
 impl StateRebuilder {
 	/// Create a new state rebuilder to write into the given backing DB.
-	pub fn new(db: Arc<Database>, pruning: Algorithm) -> Self {
+	pub fn new(db: Arc<KeyValueDB>, pruning: Algorithm) -> Self {
 		StateRebuilder {
 			db: journaldb::new(db.clone(), pruning, ::db::COL_STATE),
 			state_root: SHA3_NULL_RLP,
 			code_map: HashMap::new(),
 			missing_code: HashMap::new(),
+			accounts_rebuilt: 0,
 		}
 	}
 
@@ -478,6 +569,8 @@ impl StateRebuilder {
 		}
 
 
+		let accounts = pairs.len();
+
 		// batch trie writes
 		{
 			let mut account_trie = if self.state_root != SHA3_NULL_RLP {
@@ -496,21 +589,27 @@ impl StateRebuilder {
 		try!(self.db.inject(&mut batch));
 		try!(backing.write(batch).map_err(::util::UtilError::SimpleString));
 		trace!(target: "snapshot", "current state root: {:?}", self.state_root);
+
+		self.accounts_rebuilt += accounts;
 		Ok(())
 	}
 
 	/// Check for accounts missing code. Once all chunks have been fed, there should
-	/// be none.
-	pub fn check_missing(self) -> Result<(), Error> {
-		let missing = self.missing_code.keys().cloned().collect::<Vec<_>>();
+	/// be none. Otherwise, returns the hashes of the missing code, sorted.
+	pub fn check_missing(self) -> Result<(), Vec<H256>> {
+		let mut missing = self.missing_code.keys().cloned().collect::<Vec<_>>();
+		missing.sort();
 		match missing.is_empty() {
 			true => Ok(()),
-			false => Err(Error::MissingCode(missing)),
+			false => Err(missing),
 		}
 	}
 
 	/// Get the state root of the rebuilder.
 	pub fn state_root(&self) -> H256 { self.state_root }
+
+	/// Get the number of accounts rebuilt from the chunks fed so far.
+	pub fn accounts_rebuilt(&self) -> usize { self.accounts_rebuilt }
 }
 
 #[derive(Default)]
@@ -568,6 +667,9 @@ const POW_VERIFY_RATE: f32 = 0.02;
 /// Does basic verification for all blocks, but `PoW` verification for some.
 /// Blocks must be fed in-order.
 ///
+/// Engine verification may be skipped altogether for snapshots from a trusted
+/// source, see `set_trusted`.
+///
 /// The first block in every chunk is disconnected from the last block in the
 /// chunk before it, as chunks may be submitted out-of-order.
 ///
@@ -577,6 +679,7 @@ pub struct BlockRebuilder {
 	rng: OsRng,
 	disconnected: Vec<(u64, H256)>,
 	best_number: u64,
+	trusted: bool,
 }
 
 impl BlockRebuilder {
@@ -587,9 +690,17 @@ impl BlockRebuilder {
 			rng: try!(OsRng::new()),
 			disconnected: Vec::new(),
 			best_number: best_number,
+			trusted: false,
 		})
 	}
 
+	/// Set whether fed blocks are trusted. Trusted blocks are inserted without
+	/// any engine verification, which is considerably faster but must only be
+	/// used for snapshots produced by a trusted source.
+	pub fn set_trusted(&mut self, trusted: bool) {
+		self.trusted = trusted;
+	}
+
 	/// Feed the rebuilder an uncompressed block chunk.
 	/// Returns the number of blocks fed or any errors.
 	pub fn feed(&mut self, chunk: &[u8], engine: &Engine) -> Result<u64, ::error::Error> {
@@ -614,10 +725,12 @@ impl BlockRebuilder {
 			let block = try!(abridged_block.to_block(parent_hash, cur_number));
 			let block_bytes = block.rlp_bytes(With);
 
-			if self.rng.gen::<f32>() <= POW_VERIFY_RATE {
-				try!(engine.verify_block_seal(&block.header))
-			} else {
-				try!(engine.verify_block_basic(&block.header, Some(&block_bytes)));
+			if !self.trusted {
+				if self.rng.gen::<f32>() <= POW_VERIFY_RATE {
+					try!(engine.verify_block_seal(&block.header))
+				} else {
+					try!(engine.verify_block_basic(&block.header, Some(&block_bytes)));
+				}
 			}
 
 			let is_best = cur_number == self.best_number;
@@ -639,18 +752,30 @@ impl BlockRebuilder {
 		Ok(item_count as u64 - 3)
 	}
 
-	/// Glue together any disconnected chunks. To be called at the end.
-	pub fn glue_chunks(self) {
+	/// Glue together any disconnected chunks and check that the restored chain
+	/// has no gaps up to the best block. To be called at the end.
+	pub fn glue_chunks(self) -> Result<(), ::error::Error> {
+		// only the chunk starting lowest may be left without a parent.
+		let lowest = self.disconnected.iter().map(|&(num, _)| num).min();
+
 		for (first_num, first_hash) in self.disconnected {
 			let parent_num = first_num - 1;
 
 			// check if the parent is even in the chain.
 			// since we don't restore every single block in the chain,
 			// the first block of the first chunks has nothing to connect to.
-			if let Some(parent_hash) = self.chain.block_hash(parent_num) {
+			match self.chain.block_hash(parent_num) {
 				// if so, add the child to it.
-				self.chain.add_child(parent_hash, first_hash);
+				Some(parent_hash) => self.chain.add_child(parent_hash, first_hash),
+				None if Some(first_num) == lowest => {}
+				None => return Err(Error::ChainGap(parent_num).into()),
 			}
 		}
+
+		if self.chain.block_hash(self.best_number).is_none() {
+			return Err(Error::ChainGap(self.best_number).into());
+		}
+
+		Ok(())
 	}
 }
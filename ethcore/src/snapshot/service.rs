@@ -16,27 +16,32 @@
 
 //! Snapshot network service implementation.
 
-use std::collections::HashSet;
-use std::io::ErrorKind;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, ErrorKind};
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 
-use super::{ManifestData, StateRebuilder, BlockRebuilder};
+use super::{ManifestData, StateRebuilder, BlockRebuilder, Progress, MAX_CHUNK_SIZE, verify_chunk};
+use super::Error as SnapshotError;
 use super::io::{SnapshotReader, LooseReader, SnapshotWriter, LooseWriter};
 
 use blockchain::BlockChain;
+use client::Client;
 use engines::Engine;
 use error::Error;
+use ids::BlockID;
 use service::ClientIoMessage;
 use spec::Spec;
 
 use io::IoChannel;
+use ethkey::{self, Secret, Signature};
 
 use util::{Bytes, H256, Mutex, RwLock, UtilError};
 use util::journaldb::Algorithm;
-use util::kvdb::{Database, DatabaseConfig};
+use util::kvdb::{Database, DatabaseConfig, KeyValueDB};
 use util::snappy;
 
 /// Statuses for restorations.
@@ -44,12 +49,84 @@ use util::snappy;
 pub enum RestorationStatus {
 	///	No restoration.
 	Inactive,
-	/// Ongoing restoration.
-	Ongoing,
+	/// Ongoing restoration, with the number of chunks processed so far
+	/// and the total number of chunks to restore.
+	Ongoing {
+		/// Number of state chunks completed.
+		state_chunks_done: usize,
+		/// Number of block chunks completed.
+		block_chunks_done: usize,
+		/// Total number of state chunks.
+		state_chunks: usize,
+		/// Total number of block chunks. Zero when restoring state only.
+		block_chunks: usize,
+	},
+	/// All chunks were fed and the restored databases and snapshot are being put in place.
+	Finalizing,
 	/// Failed restoration.
 	Failed,
 }
 
+impl RestorationStatus {
+	/// Whether a restoration is ongoing.
+	pub fn is_ongoing(&self) -> bool {
+		match *self {
+			RestorationStatus::Ongoing { .. } => true,
+			_ => false,
+		}
+	}
+}
+
+/// Which parts of a snapshot to restore.
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum RestorationMode {
+	/// Restore both state and block chunks.
+	Full,
+	/// Restore only the state chunks. The manifest's block hashes are ignored
+	/// and blocks are expected to be synced normally afterwards.
+	StateOnly,
+}
+
+/// Default number of bytes of raw chunk data kept in memory to serve repeated requests.
+pub const DEFAULT_CHUNK_CACHE_SIZE: usize = 16 * 1024 * 1024;
+
+/// Default number of queued chunks above which the service reports itself busy.
+pub const DEFAULT_CHUNK_HIGH_WATER_MARK: usize = 256;
+
+/// Default length, in seconds, of the window over which a peer's chunk requests are counted.
+pub const DEFAULT_PEER_REQUEST_WINDOW_SECS: u64 = 60;
+
+/// Information about a successfully completed restoration.
+#[derive(PartialEq, Clone, Debug)]
+pub struct RestorationSummary {
+	/// Number of state chunks restored.
+	pub state_chunks: usize,
+	/// Number of block chunks restored.
+	pub block_chunks: usize,
+	/// The state root the restored state was verified against.
+	pub final_state_root: H256,
+	/// Block number the restored snapshot was taken at.
+	pub block_number: u64,
+	/// Time taken from initialization to finalization.
+	pub duration: Duration,
+}
+
+/// Consistent view of the snapshot service's state, e.g. for an admin UI.
+#[derive(PartialEq, Clone, Debug)]
+pub struct SnapshotInfo {
+	/// Status of the restoration, carrying its progress while ongoing.
+	pub restoration: RestorationStatus,
+	/// Time the restoration has been running for, see `Service::elapsed`.
+	pub elapsed: Option<Duration>,
+	/// Estimated time until all chunks of the ongoing restoration are fed,
+	/// once at least one of them was.
+	pub eta: Option<Duration>,
+	/// Block number of the snapshot currently served.
+	pub block_number: Option<u64>,
+	/// Size on disk of the snapshot currently served, in bytes, as measured when it was put in place.
+	pub size: Option<u64>,
+}
+
 /// The interface for a snapshot network service.
 /// This handles:
 ///    - restoration of snapshots to temporary databases.
@@ -58,10 +135,26 @@ pub trait SnapshotService {
 	/// Query the most recent manifest data.
 	fn manifest(&self) -> Option<ManifestData>;
 
+	/// Query the manifest of the snapshot being restored, if any.
+	/// This differs from `manifest` until the restoration completes.
+	fn restoring_manifest(&self) -> Option<ManifestData>;
+
+	/// Query the block number of the most recent manifest.
+	fn current_block_number(&self) -> Option<u64>;
+
+	/// Query the state root of the most recent manifest.
+	fn current_state_root(&self) -> Option<H256>;
+
 	/// Get raw chunk for a given hash.
 	fn chunk(&self, hash: H256) -> Option<Bytes>;
 
+	/// Get the hashes of all chunks which can be served from the current snapshot:
+	/// state chunk hashes followed by block chunk hashes.
+	/// Empty if there is no current snapshot.
+	fn available_chunks(&self) -> Vec<H256>;
+
 	/// Ask the snapshot service for the restoration status.
+	/// The chunk counts of an ongoing restoration are always consistent with each other.
 	fn status(&self) -> RestorationStatus;
 
 	/// Ask the snapshot service for the number of chunks completed.
@@ -70,20 +163,29 @@ pub trait SnapshotService {
 	fn chunks_done(&self) -> (usize, usize);
 
 	/// Begin snapshot restoration.
-	/// If restoration in-progress, this will reset it.
+	/// While a restoration is ongoing, a request for the manifest it is restoring is
+	/// ignored so its progress is kept, and a request for a different manifest is
+	/// rejected; abort the ongoing restoration first to switch manifests.
 	/// From this point on, any previous snapshot may become unavailable.
 	fn begin_restore(&self, manifest: ManifestData);
 
 	/// Abort an in-progress restoration if there is one.
+	/// Ignored once the restoration has started to be finalized.
 	fn abort_restore(&self);
 
 	/// Feed a raw state chunk to the service to be processed asynchronously.
 	/// no-op if not currently restoring.
-	fn restore_state_chunk(&self, hash: H256, chunk: Bytes);
+	/// Returns `false` without queueing the chunk if the service is busy, in which
+	/// case the chunk should be fed again once fewer chunks are pending.
+	fn restore_state_chunk(&self, hash: H256, chunk: Bytes) -> bool;
 
 	/// Feed a raw block chunk to the service to be processed asynchronously.
 	/// no-op if currently restoring.
-	fn restore_block_chunk(&self, hash: H256, chunk: Bytes);
+	/// Returns `false` without queueing the chunk if the service is busy.
+	fn restore_block_chunk(&self, hash: H256, chunk: Bytes) -> bool;
+
+	/// Number of chunks queued for processing but not processed yet.
+	fn pending_chunks(&self) -> usize;
 }
 
 /// State restoration manager.
@@ -96,30 +198,38 @@ struct Restoration {
 	writer: LooseWriter,
 	snappy_buffer: Bytes,
 	final_state_root: H256,
+	mode: RestorationMode,
+	trust_blocks: bool,
+	started: Instant,
 }
 
 struct RestorationParams<'a> {
 	manifest: ManifestData, // manifest to base restoration on.
+	mode: RestorationMode, // which chunks to restore.
 	pruning: Algorithm, // pruning algorithm for the database.
-	db_path: PathBuf, // database path
+	db: Arc<KeyValueDB>, // database to restore into.
 	writer: LooseWriter, // writer for recovered snapshot.
 	genesis: &'a [u8], // genesis block of the chain.
+	trust_blocks: bool, // whether to skip engine verification of restored blocks.
 }
 
 impl Restoration {
 	// make a new restoration using the given parameters.
 	fn new(params: RestorationParams) -> Result<Self, Error> {
-		let manifest = params.manifest;
+		let mut manifest = params.manifest;
+
+		// block hashes are ignored entirely when only restoring state.
+		if params.mode == RestorationMode::StateOnly {
+			manifest.block_hashes.clear();
+		}
 
 		let state_chunks = manifest.state_hashes.iter().cloned().collect();
 		let block_chunks = manifest.block_hashes.iter().cloned().collect();
 
-		let cfg = DatabaseConfig::with_columns(::db::NUM_COLUMNS);
-		let raw_db = Arc::new(try!(Database::open(&cfg, &*params.db_path.to_string_lossy())
-			.map_err(UtilError::SimpleString)));
-
+		let raw_db = params.db;
 		let chain = BlockChain::new(Default::default(), params.genesis, raw_db.clone());
-		let blocks = try!(BlockRebuilder::new(chain, manifest.block_number));
+		let mut blocks = try!(BlockRebuilder::new(chain, manifest.block_number));
+		blocks.set_trusted(params.trust_blocks);
 
 		let root = manifest.state_root.clone();
 		Ok(Restoration {
@@ -131,13 +241,16 @@ impl Restoration {
 			writer: params.writer,
 			snappy_buffer: Vec::new(),
 			final_state_root: root,
+			mode: params.mode,
+			trust_blocks: params.trust_blocks,
+			started: Instant::now(),
 		})
 	}
 
 	// feeds a state chunk
 	fn feed_state(&mut self, hash: H256, chunk: &[u8]) -> Result<(), Error> {
 		if self.state_chunks_left.remove(&hash) {
-			let len = try!(snappy::decompress_into(chunk, &mut self.snappy_buffer));
+			let len = try!(decompress_chunk(chunk, &mut self.snappy_buffer));
 
 			try!(self.state.feed(&self.snappy_buffer[..len]));
 			try!(self.writer.write_state_chunk(hash, chunk));
@@ -146,10 +259,20 @@ impl Restoration {
 		Ok(())
 	}
 
+	// number of accounts rebuilt from the state chunks fed so far.
+	fn accounts_rebuilt(&self) -> usize {
+		self.state.accounts_rebuilt()
+	}
+
 	// feeds a block chunk
 	fn feed_blocks(&mut self, hash: H256, chunk: &[u8], engine: &Engine) -> Result<(), Error> {
+		// trusted blocks aren't verified by the engine, so make sure at least the chunk is what we asked for.
+		if self.trust_blocks && self.block_chunks_left.contains(&hash) && !verify_chunk(&self.manifest, hash, chunk) {
+			return Err(SnapshotError::ChunkHashMismatch(hash).into());
+		}
+
 		if self.block_chunks_left.remove(&hash) {
-			let len = try!(snappy::decompress_into(chunk, &mut self.snappy_buffer));
+			let len = try!(decompress_chunk(chunk, &mut self.snappy_buffer));
 
 			try!(self.blocks.feed(&self.snappy_buffer[..len], engine));
 			try!(self.writer.write_block_chunk(hash, chunk));
@@ -172,20 +295,104 @@ impl Restoration {
 		}
 
 		// check for missing code.
-		try!(self.state.check_missing());
+		try!(self.state.check_missing().map_err(SnapshotError::MissingCode));
 
 		// connect out-of-order chunks.
-		self.blocks.glue_chunks();
+		if self.mode == RestorationMode::Full {
+			try!(self.blocks.glue_chunks());
+		}
 
 		try!(self.writer.finish(self.manifest));
 
 		Ok(())
 	}
 
+	// whether this restores the given manifest in the given mode.
+	fn restores(&self, manifest: &ManifestData, mode: RestorationMode) -> bool {
+		let mut manifest = manifest.clone();
+		if mode == RestorationMode::StateOnly {
+			manifest.block_hashes.clear();
+		}
+		self.mode == mode && self.manifest == manifest
+	}
+
 	// is everything done?
 	fn is_done(&self) -> bool {
-		self.block_chunks_left.is_empty() && self.state_chunks_left.is_empty()
+		match self.mode {
+			RestorationMode::Full => self.block_chunks_left.is_empty() && self.state_chunks_left.is_empty(),
+			RestorationMode::StateOnly => self.state_chunks_left.is_empty(),
+		}
+	}
+}
+
+// decompress a chunk into the buffer, rejecting chunks declaring an uncompressed
+// size beyond `MAX_CHUNK_SIZE` before any memory is allocated for them.
+fn decompress_chunk(chunk: &[u8], buffer: &mut Bytes) -> Result<usize, Error> {
+	let len = try!(snappy::decompressed_len(chunk));
+	if len > MAX_CHUNK_SIZE {
+		return Err(SnapshotError::ChunkTooLarge(len).into());
 	}
+
+	Ok(try!(snappy::decompress_into(chunk, buffer)))
+}
+
+// error code of a rename across filesystems.
+#[cfg(not(windows))]
+const EXDEV: i32 = 18;
+// `ERROR_NOT_SAME_DEVICE`
+#[cfg(windows)]
+const EXDEV: i32 = 17;
+
+// move a directory, falling back to copying it when it can't be
+// renamed because the destination is on another filesystem.
+fn move_dir(from: &Path, to: &Path) -> io::Result<()> {
+	move_dir_with(from, to, |from, to| fs::rename(from, to))
+}
+
+fn move_dir_with<F>(from: &Path, to: &Path, rename: F) -> io::Result<()>
+	where F: Fn(&Path, &Path) -> io::Result<()>
+{
+	match rename(from, to) {
+		Err(ref e) if e.raw_os_error() == Some(EXDEV) => {
+			trace!(target: "snapshot", "{:?} and {:?} are on different devices, copying", from, to);
+			if let Err(e) = copy_dir(from, to) {
+				let _ = fs::remove_dir_all(to);
+				return Err(e);
+			}
+			fs::remove_dir_all(from)
+		}
+		other => other,
+	}
+}
+
+// recursively copy the contents of a directory.
+fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+	try!(fs::create_dir(to));
+	for entry in try!(fs::read_dir(from)) {
+		let entry = try!(entry);
+		let mut dest = to.to_owned();
+		dest.push(entry.file_name());
+
+		match try!(entry.file_type()).is_dir() {
+			true => try!(copy_dir(&entry.path(), &dest)),
+			false => { try!(fs::copy(entry.path(), &dest)); }
+		}
+	}
+	Ok(())
+}
+
+// total size of the files within a directory, recursively.
+fn dir_size(dir: &Path) -> io::Result<u64> {
+	let mut size = 0;
+	for entry in try!(fs::read_dir(dir)) {
+		let entry = try!(entry);
+		let metadata = try!(entry.metadata());
+		size += match metadata.is_dir() {
+			true => try!(dir_size(&entry.path())),
+			false => metadata.len(),
+		};
+	}
+	Ok(size)
 }
 
 /// Type alias for client io channel.
@@ -198,44 +405,127 @@ pub type Channel = IoChannel<ClientIoMessage>;
 /// is fed.
 pub struct Service {
 	restoration: Mutex<Option<Restoration>>,
+	finalizing: AtomicBool,
+	async_finalization: AtomicBool,
+	pending_finalization: Mutex<Option<Restoration>>,
+	taking_snapshot: Mutex<bool>,
+	progress: Progress,
 	client_db: PathBuf, // "<chain hash>/<pruning>/db"
 	db_path: PathBuf,  // "<chain hash>/"
 	io_channel: Channel,
 	pruning: Algorithm,
 	status: Mutex<RestorationStatus>,
-	reader: RwLock<Option<LooseReader>>,
+	reader: RwLock<Option<Arc<LooseReader>>>,
+	snapshot_size: Mutex<Option<u64>>,
 	engine: Arc<Engine>,
 	genesis_block: Bytes,
 	state_chunks: AtomicUsize,
 	block_chunks: AtomicUsize,
+	accounts_rebuilt: AtomicUsize,
+	last_summary: Mutex<Option<RestorationSummary>>,
+	restoration_started: Mutex<Option<Instant>>,
+	last_failure: Mutex<Option<String>>,
+	load_failure: Option<String>,
+	retain_backup: AtomicBool,
+	trust_blocks: AtomicBool,
+	last_backup: Mutex<Option<PathBuf>>,
+	chunk_cache_size: AtomicUsize,
+	verify_chunks: AtomicBool,
+	peer_limit: Mutex<Option<usize>>,
+	peer_request_window: Mutex<Duration>,
+	peer_requests: Mutex<HashMap<usize, (Instant, usize)>>,
+	restoration_db_factory: RestorationDbFactory,
+	pending_chunks: AtomicUsize,
+	chunk_high_water_mark: AtomicUsize,
+}
+
+/// Creates the database a restoration is written into, given the path
+/// it would live at on disk.
+pub type RestorationDbFactory = Box<Fn(&Path) -> Result<Arc<KeyValueDB>, Error> + Send + Sync>;
+
+/// Restoration database factory opening the database on disk with a cache of
+/// given size (in megabytes). `None` leaves the cache size to the database defaults.
+/// A smaller cache lets memory-constrained nodes restore, at the cost of speed.
+pub fn restoration_db_factory(cache_size: Option<usize>) -> RestorationDbFactory {
+	Box::new(move |path: &Path| {
+		let mut cfg = DatabaseConfig::with_columns(::db::NUM_COLUMNS);
+		cfg.cache_size = cache_size;
+		let db = try!(Database::open(&cfg, &*path.to_string_lossy()).map_err(UtilError::SimpleString));
+		Ok(Arc::new(db) as Arc<KeyValueDB>)
+	})
 }
 
 impl Service {
 	/// Create a new snapshot service.
 	pub fn new(spec: &Spec, pruning: Algorithm, client_db: PathBuf, io_channel: Channel) -> Result<Self, Error> {
+		Service::with_restoration_db_factory(spec, pruning, client_db, io_channel, restoration_db_factory(None))
+	}
+
+	/// Create a new snapshot service, restoring into databases created by the given factory.
+	/// Restored databases are swapped in for the client's one from the restoration path
+	/// on disk, and finalization fails if the factory didn't put the database there.
+	/// Other factories are thus only useful for testing, while `restoration_db_factory`
+	/// allows limiting the database's memory use.
+	pub fn with_restoration_db_factory(
+		spec: &Spec,
+		pruning: Algorithm,
+		client_db: PathBuf,
+		io_channel: Channel,
+		restoration_db_factory: RestorationDbFactory,
+	) -> Result<Self, Error> {
 		let db_path = try!(client_db.parent().and_then(Path::parent)
 			.ok_or_else(|| UtilError::SimpleString("Failed to find database root.".into()))).to_owned();
 
-		let reader = {
-			let mut snapshot_path = db_path.clone();
-			snapshot_path.push("snapshot");
-			snapshot_path.push("current");
-
-			LooseReader::new(snapshot_path).ok()
+		let mut snapshot_path = db_path.clone();
+		snapshot_path.push("snapshot");
+		snapshot_path.push("current");
+
+		// having no snapshot yet is normal, but one which can't be read isn't.
+		let (reader, load_failure) = match snapshot_path.exists() {
+			false => (None, None),
+			true => match LooseReader::new(snapshot_path.clone()) {
+				Ok(reader) => (Some(Arc::new(reader.with_cache(DEFAULT_CHUNK_CACHE_SIZE))), None),
+				Err(e) => {
+					warn!(target: "snapshot", "Unable to load snapshot at {:?}: {}", snapshot_path, e);
+					(None, Some(format!("{}", e)))
+				}
+			},
 		};
 
 		let service = Service {
 			restoration: Mutex::new(None),
+			finalizing: AtomicBool::new(false),
+			async_finalization: AtomicBool::new(false),
+			pending_finalization: Mutex::new(None),
+			taking_snapshot: Mutex::new(false),
+			progress: Default::default(),
 			client_db: client_db,
 			db_path: db_path,
 			io_channel: io_channel,
 			pruning: pruning,
 			status: Mutex::new(RestorationStatus::Inactive),
+			snapshot_size: Mutex::new(reader.as_ref().and_then(|_| dir_size(&snapshot_path).ok())),
 			reader: RwLock::new(reader),
 			engine: spec.engine.clone(),
 			genesis_block: spec.genesis_block(),
 			state_chunks: AtomicUsize::new(0),
 			block_chunks: AtomicUsize::new(0),
+			accounts_rebuilt: AtomicUsize::new(0),
+			last_summary: Mutex::new(None),
+			restoration_started: Mutex::new(None),
+			last_failure: Mutex::new(None),
+			load_failure: load_failure,
+			retain_backup: AtomicBool::new(false),
+			trust_blocks: AtomicBool::new(false),
+			last_backup: Mutex::new(None),
+			chunk_cache_size: AtomicUsize::new(DEFAULT_CHUNK_CACHE_SIZE),
+			verify_chunks: AtomicBool::new(false),
+			peer_limit: Mutex::new(None),
+			peer_request_window: Mutex::new(Duration::from_secs(DEFAULT_PEER_REQUEST_WINDOW_SECS)),
+			peer_requests: Mutex::new(HashMap::new()),
+			restoration_db_factory: restoration_db_factory,
+			pending_chunks: AtomicUsize::new(0),
+			chunk_high_water_mark: AtomicUsize::new(DEFAULT_CHUNK_HIGH_WATER_MARK),
 		};
 
 		// create the root snapshot dir if it doesn't exist.
@@ -290,6 +580,20 @@ impl Service {
 		dir
 	}
 
+	// temporary directory a new snapshot is written to.
+	fn temp_snapshot_dir(&self) -> PathBuf {
+		let mut dir = self.root_dir();
+		dir.push("in_progress");
+		dir
+	}
+
+	// path to retain a replaced client database at.
+	fn retained_backup_dir(&self) -> PathBuf {
+		let mut dir = self.root_dir();
+		dir.push(format!("backup_db_{}", ::time::get_time().sec));
+		dir
+	}
+
 	// replace one the client's database with our own.
 	fn replace_client_db(&self) -> Result<(), Error> {
 		let our_db = self.restoration_db();
@@ -301,7 +605,7 @@ impl Service {
 
 		let _ = fs::remove_dir_all(&backup_db);
 
-		let existed = match fs::rename(&self.client_db, &backup_db) {
+		let existed = match move_dir(&self.client_db, &backup_db) {
 			Ok(_) => true,
 			Err(e) => if let ErrorKind::NotFound = e.kind() {
 				false
@@ -310,18 +614,25 @@ impl Service {
 			}
 		};
 
-		match fs::rename(&our_db, &self.client_db) {
+		match move_dir(&our_db, &self.client_db) {
 			Ok(_) => {
-				// clean up the backup.
+				// clean up or retain the backup.
 				if existed {
-					try!(fs::remove_dir_all(&backup_db));
+					if self.retain_backup.load(Ordering::SeqCst) {
+						let retained = self.retained_backup_dir();
+						trace!(target: "snapshot", "retaining old client db at {:?}", retained);
+						try!(move_dir(&backup_db, &retained));
+						*self.last_backup.lock() = Some(retained);
+					} else {
+						try!(fs::remove_dir_all(&backup_db));
+					}
 				}
 				Ok(())
 			}
 			Err(e) => {
 				// restore the backup.
 				if existed {
-					try!(fs::rename(&backup_db, &self.client_db));
+					try!(move_dir(&backup_db, &self.client_db));
 				}
 				Err(e.into())
 			}
@@ -329,11 +640,41 @@ impl Service {
 	}
 
 	/// Initialize the restoration synchronously.
-	pub fn init_restore(&self, manifest: ManifestData) -> Result<(), Error> {
+	/// In `StateOnly` mode, the block chunks listed in the manifest are ignored.
+	/// An ongoing restoration is treated as by `SnapshotService::begin_restore`: restoring
+	/// the same manifest in the same mode again keeps its progress, while a different one
+	/// fails, see `force_init_restore`.
+	pub fn init_restore(&self, manifest: ManifestData, mode: RestorationMode) -> Result<(), Error> {
+		self.start_restoration(manifest, mode, false)
+	}
+
+	/// Initialize the restoration synchronously, tearing down any ongoing one.
+	pub fn force_init_restore(&self, manifest: ManifestData, mode: RestorationMode) -> Result<(), Error> {
+		self.start_restoration(manifest, mode, true)
+	}
+
+	// start a new restoration. unless forced, an ongoing one is kept: restoring its
+	// manifest again is a no-op, and a different manifest fails to start.
+	fn start_restoration(&self, manifest: ManifestData, mode: RestorationMode, force: bool) -> Result<(), Error> {
 		let rest_dir = self.restoration_dir();
 
 		let mut res = self.restoration.lock();
 
+		if self.finalizing.load(Ordering::SeqCst) {
+			return Err(UtilError::SimpleString("Previous restoration is being finalized.".into()).into());
+		}
+
+		match *res {
+			Some(ref rest) if !force => {
+				if rest.restores(&manifest, mode) {
+					trace!(target: "snapshot", "manifest is already being restored, keeping progress");
+					return Ok(());
+				}
+				return Err(UtilError::SimpleString("A restoration is already ongoing.".into()).into());
+			}
+			_ => {},
+		}
+
 		// tear down existing restoration.
 		*res = None;
 
@@ -350,35 +691,91 @@ impl Service {
 		// make new restoration.
 		let writer = try!(LooseWriter::new(self.temp_recovery_dir()));
 
+		let (state_chunks, block_chunks) = (manifest.state_hashes.len(), match mode {
+			RestorationMode::Full => manifest.block_hashes.len(),
+			RestorationMode::StateOnly => 0,
+		});
+
 		let params = RestorationParams {
 			manifest: manifest,
+			mode: mode,
 			pruning: self.pruning,
-			db_path: self.restoration_db(),
+			db: try!((self.restoration_db_factory)(&self.restoration_db())),
 			writer: writer,
 			genesis: &self.genesis_block,
+			trust_blocks: self.trust_blocks.load(Ordering::SeqCst),
 		};
 
 		*res = Some(try!(Restoration::new(params)));
 
-		*self.status.lock() = RestorationStatus::Ongoing;
+		self.state_chunks.store(0, Ordering::SeqCst);
+		self.block_chunks.store(0, Ordering::SeqCst);
+		self.accounts_rebuilt.store(0, Ordering::SeqCst);
+		*self.last_failure.lock() = None;
+		*self.restoration_started.lock() = Some(Instant::now());
+		*self.status.lock() = RestorationStatus::Ongoing {
+			state_chunks_done: 0,
+			block_chunks_done: 0,
+			state_chunks: state_chunks,
+			block_chunks: block_chunks,
+		};
 		Ok(())
 	}
 
-	// finalize the restoration. this accepts an already-locked
-	// restoration as an argument -- so acquiring it again _will_
-	// lead to deadlock.
-	fn finalize_restoration(&self, rest: &mut Option<Restoration>) -> Result<(), Error> {
+	/// Decode a manifest received as raw RLP and initialize a full restoration from it.
+	/// Malformed manifest bytes are reported as an error and leave any in-progress
+	/// restoration untouched.
+	pub fn begin_restore_from_bytes(&self, raw: &[u8]) -> Result<(), Error> {
+		let manifest = try!(ManifestData::from_rlp(raw));
+		self.init_restore(manifest, RestorationMode::Full)
+	}
+
+	// finalize the restoration. this is called with the restoration already
+	// taken out of the restoration lock and the `finalizing` flag set, so
+	// the lock is free while the databases and snapshot are replaced.
+	fn finalize_restoration(&self, rest: Restoration) -> Result<(), Error> {
 		trace!(target: "snapshot", "finalizing restoration");
 
+		// the client's database is replaced from disk, which a database kept elsewhere can't be.
+		if !self.restoration_db().exists() {
+			return Err(UtilError::SimpleString("Restored database is not on disk.".into()).into());
+		}
+
+		let summary = RestorationSummary {
+			state_chunks: self.state_chunks.load(Ordering::SeqCst),
+			block_chunks: self.block_chunks.load(Ordering::SeqCst),
+			final_state_root: rest.final_state_root,
+			block_number: rest.manifest.block_number,
+			duration: rest.started.elapsed(),
+		};
+
 		self.state_chunks.store(0, Ordering::SeqCst);
 		self.block_chunks.store(0, Ordering::SeqCst);
+		self.accounts_rebuilt.store(0, Ordering::SeqCst);
 
 		// destroy the restoration before replacing databases and snapshot.
-		try!(rest.take().map(Restoration::finalize).unwrap_or(Ok(())));
+		try!(rest.finalize());
 		try!(self.replace_client_db());
 
-		let mut reader = self.reader.write();
-		*reader = None; // destroy the old reader if it existed.
+		try!(self.replace_current_snapshot(&self.temp_recovery_dir()));
+
+		let _ = fs::remove_dir_all(self.restoration_dir());
+
+		*self.last_summary.lock() = Some(summary);
+		*self.status.lock() = RestorationStatus::Inactive;
+
+		Ok(())
+	}
+
+	// replace the current snapshot with the one found in the given directory.
+	// readers are swapped so that peers being seeded always see a complete snapshot:
+	// first serve the new snapshot from where it was written while the current one
+	// is replaced, then serve it from its final location.
+	fn replace_current_snapshot(&self, from: &Path) -> Result<(), Error> {
+		// the new snapshot is complete, so its size can be taken once up front.
+		let size = dir_size(from).ok();
+		let temp_reader = Arc::new(try!(self.open_reader(from.to_owned())));
+		self.swap_reader(temp_reader, size);
 
 		let snapshot_dir = self.snapshot_dir();
 
@@ -392,97 +789,655 @@ impl Service {
 
 		try!(fs::create_dir(&snapshot_dir));
 
-		trace!(target: "snapshot", "copying restored snapshot files over");
-		for maybe_file in try!(fs::read_dir(self.temp_recovery_dir())) {
+		trace!(target: "snapshot", "copying new snapshot files over");
+		for maybe_file in try!(fs::read_dir(from)) {
 			let path = try!(maybe_file).path();
 			if let Some(name) = path.file_name().map(|x| x.to_owned()) {
 				let mut new_path = snapshot_dir.clone();
 				new_path.push(name);
-				try!(fs::rename(path, new_path));
+				// the new snapshot is still being served from, so files can't be moved out of it.
+				if fs::hard_link(&path, &new_path).is_err() {
+					try!(fs::copy(&path, &new_path));
+				}
 			}
 		}
 
-		let _ = fs::remove_dir_all(self.restoration_dir());
+		self.swap_reader(Arc::new(try!(self.open_reader(snapshot_dir))), size);
+		Ok(())
+	}
+
+	/// Take a snapshot of the client's state and blocks at the given block number,
+	/// then serve it in place of the current snapshot.
+	/// This is done synchronously; use `request_snapshot` to take one in the background.
+	/// Fails if another snapshot is already being taken.
+	pub fn take_snapshot(&self, client: &Client, num: u64) -> Result<(), Error> {
+		{
+			let mut taking = self.taking_snapshot.lock();
+			if *taking {
+				return Err(UtilError::SimpleString("A snapshot is already being taken.".into()).into());
+			}
+
+			// reset before the snapshot becomes visible, so a cancellation can't be undone by it.
+			self.progress.reset();
+			*taking = true;
+		}
+
+		let res = self.take_snapshot_inner(client, num);
+		{
+			let mut taking = self.taking_snapshot.lock();
+			self.progress.clear_abort();
+			*taking = false;
+		}
+
+		if let Err(ref e) = res {
+			warn!(target: "snapshot", "failed to take snapshot at block {}: {}", num, e);
+		}
+		res
+	}
+
+	fn take_snapshot_inner(&self, client: &Client, num: u64) -> Result<(), Error> {
+		let temp_dir = self.temp_snapshot_dir();
+		let _ = fs::remove_dir_all(&temp_dir);
+
+		let res = self.write_snapshot(client, num, &temp_dir);
+
+		// the current snapshot has been replaced on success; on failure, the partial one is never served.
+		let _ = fs::remove_dir_all(&temp_dir);
+		res
+	}
+
+	// write a snapshot into the given temporary directory and promote it to the current one.
+	fn write_snapshot(&self, client: &Client, num: u64, temp_dir: &Path) -> Result<(), Error> {
+		let writer = try!(LooseWriter::new(temp_dir.to_owned()));
+		try!(client.take_snapshot(writer, BlockID::Number(num), &self.progress));
+
+		info!("Finished taking snapshot at block {}", num);
+
+		// a finalizing restoration would replace the current snapshot too.
+		let _rest = self.restoration.lock();
+		if self.finalizing.load(Ordering::SeqCst) {
+			return Err(UtilError::SimpleString("Restoration is being finalized.".into()).into());
+		}
+
+		if self.progress.aborted() {
+			return Err(SnapshotError::SnapshotAborted.into());
+		}
+
+		self.replace_current_snapshot(temp_dir)
+	}
+
+	/// Cancel the snapshot currently being taken, if any.
+	/// Its partial output is discarded and the current snapshot is left in place.
+	/// Returns whether there was a snapshot being taken.
+	pub fn cancel_snapshot(&self) -> bool {
+		let taking = self.taking_snapshot.lock();
+		if *taking {
+			self.progress.abort();
+		}
+		*taking
+	}
+
+	/// Take a snapshot at the given block number in the background.
+	pub fn request_snapshot(&self, num: u64) {
+		self.io_channel.send(ClientIoMessage::TakeSnapshot(num))
+			.expect("snapshot service and io service are kept alive by client service; qed");
+	}
+
+	/// Whether a snapshot is currently being taken.
+	pub fn taking_snapshot(&self) -> bool {
+		*self.taking_snapshot.lock()
+	}
+
+	/// Progress of the snapshot being taken, or of the last one taken.
+	pub fn snapshot_progress(&self) -> &Progress {
+		&self.progress
+	}
+
+	// get the reader currently used to serve chunks.
+	fn current_reader(&self) -> Option<Arc<LooseReader>> {
+		self.reader.read().clone()
+	}
+
+	// open a reader for the snapshot in the given directory, configured
+	// with the current cache size and verification settings.
+	fn open_reader(&self, dir: PathBuf) -> Result<LooseReader, Error> {
+		let reader = try!(LooseReader::new(dir)).with_cache(self.chunk_cache_size.load(Ordering::SeqCst));
+		Ok(match self.verify_chunks.load(Ordering::SeqCst) {
+			true => reader.with_verification(),
+			false => reader,
+		})
+	}
+
+	// reopen the current snapshot, if any, so that changed reader settings take effect.
+	fn reload_reader(&self) -> Result<(), Error> {
+		if self.current_reader().is_none() {
+			return Ok(());
+		}
+
+		let reader = try!(self.open_reader(self.snapshot_dir()));
+		let size = *self.snapshot_size.lock();
+		self.swap_reader(Arc::new(reader), size);
+		Ok(())
+	}
+
+	// atomically replace the reader used to serve chunks, along with the size of its snapshot.
+	// the previous one is dropped once the last in-flight request using it finishes.
+	fn swap_reader(&self, new_reader: Arc<LooseReader>, size: Option<u64>) {
+		let mut reader = self.reader.write();
+		*self.snapshot_size.lock() = size;
+		*reader = Some(new_reader);
+	}
+
+	/// Set whether the replaced client database should be kept after a successful
+	/// restoration rather than deleted. Retained databases are moved into the snapshot
+	/// directory under a timestamped name and may be restored with `rollback_to_backup`.
+	pub fn set_backup_retention(&self, retain: bool) {
+		self.retain_backup.store(retain, Ordering::SeqCst);
+	}
+
+	/// Set the number of bytes of chunk data kept in memory to serve repeated
+	/// chunk requests, `DEFAULT_CHUNK_CACHE_SIZE` by default. Zero disables caching.
+	/// The current snapshot is reopened, dropping any cached chunks.
+	pub fn set_chunk_cache_size(&self, max_bytes: usize) -> Result<(), Error> {
+		self.chunk_cache_size.store(max_bytes, Ordering::SeqCst);
+		self.reload_reader()
+	}
+
+	/// Set whether chunks served to peers are checked against their hash when read
+	/// from disk, so that corrupted ones are withheld rather than sent. Off by default.
+	/// The current snapshot is reopened for the setting to take effect.
+	pub fn set_verify_chunks(&self, verify: bool) -> Result<(), Error> {
+		self.verify_chunks.store(verify, Ordering::SeqCst);
+		self.reload_reader()
+	}
+
+	/// Set whether blocks of restorations started from now on are trusted.
+	/// Trusted blocks are restored without engine verification; chunks are still
+	/// checked against the manifest's hashes. This should only be enabled for
+	/// snapshots produced by a trusted source. Defaults to full verification.
+	pub fn set_trust_blocks(&self, trust: bool) {
+		self.trust_blocks.store(trust, Ordering::SeqCst);
+	}
+
+	/// Reverse the last database swap, putting the retained backup back in place of
+	/// the restored client database. Fails if no backup was retained.
+	pub fn rollback_to_backup(&self) -> Result<(), Error> {
+		// hold the restoration lock so a concurrent restoration can't swap underneath us.
+		let _rest = self.restoration.lock();
+		if self.finalizing.load(Ordering::SeqCst) {
+			return Err(UtilError::SimpleString("Restoration is being finalized.".into()).into());
+		}
+
+		let mut last_backup = self.last_backup.lock();
+
+		let backup = match last_backup.take() {
+			Some(backup) => backup,
+			None => return Err(UtilError::SimpleString("No retained database to roll back to.".into()).into()),
+		};
+
+		trace!(target: "snapshot", "rolling back {:?} to {:?}", self.client_db, backup);
+
+		let mut restored_db = self.root_dir();
+		restored_db.push("rolled_back_db");
+
+		let _ = fs::remove_dir_all(&restored_db);
+
+		let existed = match move_dir(&self.client_db, &restored_db) {
+			Ok(_) => true,
+			Err(e) => if let ErrorKind::NotFound = e.kind() {
+				false
+			} else {
+				*last_backup = Some(backup);
+				return Err(e.into());
+			}
+		};
+
+		match move_dir(&backup, &self.client_db) {
+			Ok(_) => {
+				if existed {
+					try!(fs::remove_dir_all(&restored_db));
+				}
+				Ok(())
+			}
+			Err(e) => {
+				// put the restored database back.
+				if existed {
+					try!(move_dir(&restored_db, &self.client_db));
+				}
+				*last_backup = Some(backup);
+				Err(e.into())
+			}
+		}
+	}
+
+	/// Query the most recent manifest data along with a signature over its hash made
+	/// with given (node) key, so peers can verify which node it comes from.
+	/// `None` if there's no snapshot or signing fails.
+	pub fn signed_manifest(&self, signer: &Secret) -> Option<(ManifestData, Signature)> {
+		let manifest = match self.current_reader() {
+			Some(reader) => reader.manifest().clone(),
+			None => return None,
+		};
+
+		match ethkey::sign(signer, &manifest.hash()) {
+			Ok(signature) => Some((manifest, signature)),
+			Err(e) => {
+				warn!(target: "snapshot", "Unable to sign snapshot manifest: {}", e);
+				None
+			}
+		}
+	}
+
+	/// Query the most recent manifest data without cloning it.
+	/// Cheaper than `manifest` when answering many queries, e.g. from peers.
+	pub fn shared_manifest(&self) -> Option<Arc<ManifestData>> {
+		self.current_reader().map(|r| r.shared_manifest())
+	}
+
+	/// Get the pruning algorithm restoration databases are created with.
+	pub fn pruning_algorithm(&self) -> Algorithm {
+		self.pruning
+	}
+
+	/// Get the state root the active restoration is expected to produce,
+	/// or `None` if no restoration is in progress.
+	pub fn expected_state_root(&self) -> Option<H256> {
+		self.restoration.lock().as_ref().map(|r| r.final_state_root)
+	}
+
+	/// Get the hashes of the state and block chunks the active restoration still needs,
+	/// in that order. Both are empty if no restoration is in progress.
+	pub fn needed_chunks(&self) -> (Vec<H256>, Vec<H256>) {
+		match *self.restoration.lock() {
+			Some(ref rest) => (
+				rest.state_chunks_left.iter().cloned().collect(),
+				rest.block_chunks_left.iter().cloned().collect(),
+			),
+			None => (Vec::new(), Vec::new()),
+		}
+	}
+
+	/// Get the manifest of the snapshot with the highest block number at or below
+	/// `block` among all snapshots kept in the snapshot directory.
+	pub fn manifest_at(&self, block: u64) -> Option<ManifestData> {
+		let readers = match LooseReader::find_all(&self.root_dir()) {
+			Ok(readers) => readers,
+			Err(e) => {
+				warn!(target: "snapshot", "failed to list snapshots: {}", e);
+				return None;
+			}
+		};
+
+		readers.into_iter()
+			.map(|r| r.manifest().clone())
+			.filter(|m| m.block_number <= block)
+			.max_by_key(|m| m.block_number)
+	}
+
+	/// Limit the number of chunks served to any single peer through `chunk_for_peer`
+	/// within each request window. `None` removes the limit.
+	pub fn set_peer_request_limit(&self, limit: Option<usize>) {
+		*self.peer_limit.lock() = limit;
+	}
+
+	/// Set the length of the window over which a peer's requests are counted,
+	/// `DEFAULT_PEER_REQUEST_WINDOW_SECS` by default. A peer's count starts over
+	/// once its window has passed.
+	pub fn set_peer_request_window(&self, window: Duration) {
+		*self.peer_request_window.lock() = window;
+	}
+
+	/// Forget the requests made by a peer, e.g. after it disconnects.
+	pub fn reset_peer_requests(&self, peer: usize) {
+		self.peer_requests.lock().remove(&peer);
+	}
+
+	/// Get raw chunk for a given hash on behalf of a peer, identified by an opaque id.
+	/// Returns `None` once the peer has made more requests than the configured limit
+	/// within the current window.
+	pub fn chunk_for_peer(&self, peer: usize, hash: H256) -> Option<Bytes> {
+		if let Some(limit) = *self.peer_limit.lock() {
+			let window = *self.peer_request_window.lock();
+			let now = Instant::now();
+			let mut requests = self.peer_requests.lock();
+
+			// forget peers whose window has passed, so idle ones don't pile up.
+			let expired: Vec<usize> = requests.iter()
+				.filter(|&(_, &(start, _))| now.duration_since(start) >= window)
+				.map(|(peer, _)| *peer)
+				.collect();
+			for expired in expired {
+				requests.remove(&expired);
+			}
+
+			let entry = requests.entry(peer).or_insert((now, 0));
+			if entry.1 >= limit {
+				trace!(target: "snapshot", "peer {} exceeded chunk request limit", peer);
+				return None;
+			}
+			entry.1 += 1;
+		}
+
+		self.chunk(hash)
+	}
 
-		*reader = Some(try!(LooseReader::new(snapshot_dir)));
+	/// Set the number of queued chunks at which `restore_state_chunk` and
+	/// `restore_block_chunk` start rejecting chunks.
+	pub fn set_chunk_high_water_mark(&self, mark: usize) {
+		self.chunk_high_water_mark.store(mark, Ordering::SeqCst);
+	}
+
+	/// Whether too many chunks are queued to accept more.
+	pub fn is_busy(&self) -> bool {
+		self.pending_chunks.load(Ordering::SeqCst) >= self.chunk_high_water_mark.load(Ordering::SeqCst)
+	}
 
+	/// Mark a chunk queued by `restore_state_chunk` or `restore_block_chunk` as processed.
+	/// Called by the IO handler once it has fed the chunk.
+	/// Extra calls are ignored rather than wrapping the count around.
+	pub fn queued_chunk_done(&self) {
+		let mut pending = self.pending_chunks.load(Ordering::SeqCst);
+		while pending != 0 {
+			let prev = self.pending_chunks.compare_and_swap(pending, pending - 1, Ordering::SeqCst);
+			if prev == pending {
+				break;
+			}
+			pending = prev;
+		}
+	}
+
+	// queue a chunk message, unless too many chunks are pending.
+	fn queue_chunk(&self, message: ClientIoMessage) -> bool {
+		if self.is_busy() {
+			trace!(target: "snapshot", "rejecting chunk: {} chunks pending", self.pending_chunks());
+			return false;
+		}
+
+		self.pending_chunks.fetch_add(1, Ordering::SeqCst);
+		self.io_channel.send(message)
+			.expect("snapshot service and io service are kept alive by client service; qed");
+		true
+	}
+
+	/// Abort any restoration, clearing a `Failed` status and removing whatever
+	/// a failed restoration left behind, so that a new one may be started.
+	/// Fails only if a restoration is being finalized or its directory can't be removed.
+	pub fn reset(&self) -> Result<(), Error> {
+		let mut restoration = self.restoration.lock();
+		if self.finalizing.load(Ordering::SeqCst) {
+			return Err(UtilError::SimpleString("Restoration is being finalized.".into()).into());
+		}
+
+		*restoration = None;
+		self.state_chunks.store(0, Ordering::SeqCst);
+		self.block_chunks.store(0, Ordering::SeqCst);
+		self.accounts_rebuilt.store(0, Ordering::SeqCst);
+		*self.last_failure.lock() = None;
 		*self.status.lock() = RestorationStatus::Inactive;
 
+		if let Err(e) = fs::remove_dir_all(self.restoration_dir()) {
+			if e.kind() != ErrorKind::NotFound {
+				return Err(e.into());
+			}
+		}
+
 		Ok(())
 	}
 
-	/// Feed a chunk of either kind. no-op if no restoration or status is wrong.
+	/// Get the number of accounts rebuilt by the ongoing restoration so far.
+	pub fn accounts_rebuilt(&self) -> usize {
+		self.accounts_rebuilt.load(Ordering::SeqCst)
+	}
+
+	/// Get a summary of the last restoration which completed successfully, if any.
+	pub fn last_restoration_summary(&self) -> Option<RestorationSummary> {
+		self.last_summary.lock().clone()
+	}
+
+	/// Get how long the current restoration has been running.
+	/// Once a restoration completes, this is frozen at the duration from its summary.
+	/// `None` if no restoration is ongoing or has completed.
+	pub fn elapsed(&self) -> Option<Duration> {
+		let status = *self.status.lock();
+		self.elapsed_at(status)
+	}
+
+	// how long the restoration has been running for, given its status.
+	fn elapsed_at(&self, status: RestorationStatus) -> Option<Duration> {
+		match status {
+			RestorationStatus::Ongoing { .. } | RestorationStatus::Finalizing =>
+				self.restoration_started.lock().map(|started| started.elapsed()),
+			RestorationStatus::Inactive => self.last_summary.lock().as_ref().map(|summary| summary.duration),
+			RestorationStatus::Failed => None,
+		}
+	}
+
+	/// Get the state of the restoration and of the snapshot served at once.
+	/// The status lock is held throughout, so neither a restoration finishing nor
+	/// the served snapshot being replaced can be observed half-way.
+	pub fn snapshot_info(&self) -> SnapshotInfo {
+		let status = self.status.lock();
+		let elapsed = self.elapsed_at(*status);
+
+		let eta = match (*status, elapsed) {
+			(RestorationStatus::Ongoing { state_chunks_done, block_chunks_done, state_chunks, block_chunks }, Some(elapsed)) => {
+				let done = (state_chunks_done + block_chunks_done) as u64;
+				let total = (state_chunks + block_chunks) as u64;
+				match done {
+					0 => None,
+					done => {
+						let elapsed_ms = elapsed.as_secs() * 1000 + (elapsed.subsec_nanos() / 1_000_000) as u64;
+						Some(Duration::from_millis(elapsed_ms * total.saturating_sub(done) / done))
+					}
+				}
+			}
+			_ => None,
+		};
+
+		let reader = self.reader.read();
+		let (block_number, size) = match *reader {
+			Some(ref reader) => (Some(reader.manifest().block_number), *self.snapshot_size.lock()),
+			None => (None, None),
+		};
+
+		SnapshotInfo {
+			restoration: *status,
+			elapsed: elapsed,
+			eta: eta,
+			block_number: block_number,
+			size: size,
+		}
+	}
+
+	/// Get the reason the last restoration failed, if its status is `Failed`.
+	/// For snapshots with missing contract code, this lists the missing code hashes.
+	pub fn last_restoration_failure(&self) -> Option<String> {
+		self.last_failure.lock().clone()
+	}
+
+	/// Get the reason the snapshot found at startup couldn't be loaded, if there was one.
+	/// `None` both when it was loaded and when there was no snapshot at all,
+	/// so callers may refuse to run with a corrupt snapshot.
+	pub fn snapshot_load_failure(&self) -> Option<String> {
+		self.load_failure.clone()
+	}
+
+	/// Feed a chunk of either kind. no-op if no restoration, status is wrong,
+	/// or the restoration is being finalized.
 	fn feed_chunk(&self, hash: H256, chunk: &[u8], is_state: bool) -> Result<(), Error> {
+		if self.finalizing.load(Ordering::SeqCst) {
+			trace!(target: "snapshot", "ignoring chunk {:?} fed while finalizing", hash);
+			return Ok(());
+		}
+
 		// TODO: be able to process block chunks and state chunks at same time?
-		let mut restoration = self.restoration.lock();
+		let done = {
+			let mut restoration = self.restoration.lock();
+
+			match self.status() {
+				RestorationStatus::Inactive | RestorationStatus::Finalizing | RestorationStatus::Failed => return Ok(()),
+				RestorationStatus::Ongoing { .. } => {
+					let is_done = {
+						let rest = match *restoration {
+							Some(ref mut r) => r,
+							None => return Ok(()),
+						};
 
-		match self.status() {
-			RestorationStatus::Inactive | RestorationStatus::Failed => Ok(()),
-			RestorationStatus::Ongoing => {
-				let res = {
-					let rest = match *restoration {
-						Some(ref mut r) => r,
-						None => return Ok(()),
+						try!(match is_state {
+							true => rest.feed_state(hash, chunk),
+							false => rest.feed_blocks(hash, chunk, &*self.engine),
+						});
+
+						// updated under the restoration lock, so always consistent with the chunks fed.
+						self.accounts_rebuilt.store(rest.accounts_rebuilt(), Ordering::SeqCst);
+						rest.is_done()
 					};
 
+					// the status counts are updated together with the counters, so they always agree.
+					let mut status = self.status.lock();
 					match is_state {
-						true => rest.feed_state(hash, chunk),
-						false => rest.feed_blocks(hash, chunk, &*self.engine),
-					}.map(|_| rest.is_done())
-				};
-
-				match res {
-					Ok(is_done) => {
+						true => self.state_chunks.fetch_add(1, Ordering::SeqCst),
+						false => self.block_chunks.fetch_add(1, Ordering::SeqCst),
+					};
+					if let RestorationStatus::Ongoing { ref mut state_chunks_done, ref mut block_chunks_done, .. } = *status {
 						match is_state {
-							true => self.state_chunks.fetch_add(1, Ordering::SeqCst),
-							false => self.block_chunks.fetch_add(1, Ordering::SeqCst),
-						};
-
-						match is_done {
-							true => self.finalize_restoration(&mut *restoration),
-							false => Ok(())
+							true => *state_chunks_done += 1,
+							false => *block_chunks_done += 1,
 						}
 					}
-					other => other.map(drop),
+					drop(status);
+
+					// take the finished restoration out while still holding the lock,
+					// so exactly one feed gets to finalize it.
+					match is_done {
+						true => {
+							self.finalizing.store(true, Ordering::SeqCst);
+							*self.status.lock() = RestorationStatus::Finalizing;
+							restoration.take()
+						}
+						false => None,
+					}
 				}
 			}
+		};
+
+		let rest = match done {
+			Some(rest) => rest,
+			None => return Ok(()),
+		};
+
+		if self.async_finalization.load(Ordering::SeqCst) {
+			*self.pending_finalization.lock() = Some(rest);
+			if self.io_channel.send(ClientIoMessage::FinalizeRestoration).is_ok() {
+				return Ok(());
+			}
+
+			// no IO service to finalize on, so do it here.
+			match self.pending_finalization.lock().take() {
+				Some(rest) => self.finish_finalization(rest),
+				None => Ok(()),
+			}
+		} else {
+			self.finish_finalization(rest)
+		}
+	}
+
+	// finalize the restoration and allow a new one to begin.
+	fn finish_finalization(&self, rest: Restoration) -> Result<(), Error> {
+		let res = self.finalize_restoration(rest);
+		self.finalizing.store(false, Ordering::SeqCst);
+		res
+	}
+
+	/// Whether to finalize restorations in the background (disabled by default).
+	/// When enabled, feeding the last chunk only queues the finalization on the IO service
+	/// and returns; `status()` reports `Finalizing` until `finalize_pending_restoration`
+	/// has run.
+	pub fn set_async_finalization(&self, enabled: bool) {
+		self.async_finalization.store(enabled, Ordering::SeqCst);
+	}
+
+	/// Finalize a restoration whose last chunk was fed with background finalization enabled.
+	/// No-op if there is none.
+	pub fn finalize_pending_restoration(&self) {
+		let rest = match self.pending_finalization.lock().take() {
+			Some(rest) => rest,
+			None => return,
+		};
+
+		if let Err(e) = self.finish_finalization(rest) {
+			self.fail_restoration("finalization", e);
 		}
 	}
 
 	/// Feed a state chunk to be processed synchronously.
 	pub fn feed_state_chunk(&self, hash: H256, chunk: &[u8]) {
-		match self.feed_chunk(hash, chunk, true) {
-			Ok(()) => (),
-			Err(e) => {
-				warn!("Encountered error during state restoration: {}", e);
-				*self.restoration.lock() = None;
-				*self.status.lock() = RestorationStatus::Failed;
-				let _ = fs::remove_dir_all(self.restoration_dir());
-			}
+		if let Err(e) = self.feed_chunk(hash, chunk, true) {
+			self.fail_restoration("state restoration", e);
 		}
 	}
 
 	/// Feed a block chunk to be processed synchronously.
 	pub fn feed_block_chunk(&self, hash: H256, chunk: &[u8]) {
-		match self.feed_chunk(hash, chunk, false) {
-			Ok(()) => (),
-			Err(e) => {
-				warn!("Encountered error during block restoration: {}", e);
-				*self.restoration.lock() = None;
-				*self.status.lock() = RestorationStatus::Failed;
-				let _ = fs::remove_dir_all(self.restoration_dir());
-			}
+		if let Err(e) = self.feed_chunk(hash, chunk, false) {
+			self.fail_restoration("block restoration", e);
 		}
 	}
+
+	// tear down the restoration after an error, remembering why it failed.
+	fn fail_restoration(&self, stage: &str, e: Error) {
+		warn!("Encountered error during {}: {}", stage, e);
+		*self.restoration.lock() = None;
+		*self.last_failure.lock() = Some(format!("{}", e));
+		*self.status.lock() = RestorationStatus::Failed;
+		let _ = fs::remove_dir_all(self.restoration_dir());
+	}
 }
 
 impl SnapshotService for Service {
 	fn manifest(&self) -> Option<ManifestData> {
-		self.reader.read().as_ref().map(|r| r.manifest().clone())
+		self.current_reader().map(|r| r.manifest().clone())
+	}
+
+	fn restoring_manifest(&self) -> Option<ManifestData> {
+		self.restoration.lock().as_ref().map(|r| r.manifest.clone())
+	}
+
+	fn current_block_number(&self) -> Option<u64> {
+		self.reader.read().as_ref().map(|r| r.manifest().block_number)
+	}
+
+	fn current_state_root(&self) -> Option<H256> {
+		self.reader.read().as_ref().map(|r| r.manifest().state_root)
 	}
 
 	fn chunk(&self, hash: H256) -> Option<Bytes> {
-		self.reader.read().as_ref().and_then(|r| r.chunk(hash).ok())
+		// the lock is only held to grab the reader, not while reading from disk.
+		let reader = match self.current_reader() {
+			Some(reader) => reader,
+			None => return None,
+		};
+
+		match reader.chunk(hash) {
+			Ok(chunk) => Some(chunk),
+			// the snapshot may have been replaced and its files removed mid-read;
+			// the reader swapped in serves the same chunks.
+			Err(_) => self.current_reader()
+				.and_then(|current| match &*current as *const LooseReader == &*reader as *const LooseReader {
+					true => None,
+					false => current.chunk(hash).ok(),
+				}),
+		}
+	}
+
+	fn available_chunks(&self) -> Vec<H256> {
+		match self.current_reader() {
+			Some(r) => {
+				let manifest = r.manifest();
+				manifest.state_hashes.iter().chain(manifest.block_hashes.iter()).cloned().collect()
+			}
+			None => Vec::new(),
+		}
 	}
 
 	fn status(&self) -> RestorationStatus {
@@ -499,7 +1454,18 @@ impl SnapshotService for Service {
 	}
 
 	fn abort_restore(&self) {
-		*self.restoration.lock() = None;
+		let mut restoration = self.restoration.lock();
+		if self.finalizing.load(Ordering::SeqCst) {
+			// a finalization which hasn't started yet can still be cancelled;
+			// one already under way is moving the restoration directory.
+			if self.pending_finalization.lock().take().is_none() {
+				trace!(target: "snapshot", "ignoring request to abort a restoration being finalized");
+				return;
+			}
+			self.finalizing.store(false, Ordering::SeqCst);
+		}
+
+		*restoration = None;
 		*self.status.lock() = RestorationStatus::Inactive;
 		if let Err(e) = fs::remove_dir_all(&self.restoration_dir()) {
 			match e.kind() {
@@ -509,13 +1475,192 @@ impl SnapshotService for Service {
 		}
 	}
 
-	fn restore_state_chunk(&self, hash: H256, chunk: Bytes) {
-		self.io_channel.send(ClientIoMessage::FeedStateChunk(hash, chunk))
-			.expect("snapshot service and io service are kept alive by client service; qed");
+	fn restore_state_chunk(&self, hash: H256, chunk: Bytes) -> bool {
+		self.queue_chunk(ClientIoMessage::FeedStateChunk(hash, chunk))
 	}
 
-	fn restore_block_chunk(&self, hash: H256, chunk: Bytes) {
-		self.io_channel.send(ClientIoMessage::FeedBlockChunk(hash, chunk))
-			.expect("snapshot service and io service are kept alive by client service; qed");
+	fn restore_block_chunk(&self, hash: H256, chunk: Bytes) -> bool {
+		self.queue_chunk(ClientIoMessage::FeedBlockChunk(hash, chunk))
+	}
+
+	fn pending_chunks(&self) -> usize {
+		self.pending_chunks.load(Ordering::SeqCst)
+	}
+}
+
+/// Snapshot service which only serves an existing snapshot, e.g. for seeding.
+///
+/// Unlike `Service` it needs neither a client database nor an IO channel.
+/// It never restores: restoration requests are ignored with a warning
+/// and chunks fed to it are rejected.
+pub struct ReadOnlyService {
+	reader: LooseReader,
+}
+
+impl ReadOnlyService {
+	/// Create a service serving the snapshot in the given directory.
+	/// Fails if the directory doesn't contain a valid snapshot.
+	pub fn new(snapshot_dir: PathBuf) -> Result<Self, Error> {
+		Ok(ReadOnlyService {
+			reader: try!(LooseReader::new(snapshot_dir)),
+		})
+	}
+}
+
+impl SnapshotService for ReadOnlyService {
+	fn manifest(&self) -> Option<ManifestData> {
+		Some(self.reader.manifest().clone())
+	}
+
+	fn restoring_manifest(&self) -> Option<ManifestData> {
+		None
+	}
+
+	fn current_block_number(&self) -> Option<u64> {
+		Some(self.reader.manifest().block_number)
+	}
+
+	fn current_state_root(&self) -> Option<H256> {
+		Some(self.reader.manifest().state_root)
+	}
+
+	fn chunk(&self, hash: H256) -> Option<Bytes> {
+		self.reader.chunk(hash).ok()
+	}
+
+	fn available_chunks(&self) -> Vec<H256> {
+		let manifest = self.reader.manifest();
+		manifest.state_hashes.iter().chain(manifest.block_hashes.iter()).cloned().collect()
+	}
+
+	fn status(&self) -> RestorationStatus {
+		RestorationStatus::Inactive
+	}
+
+	fn chunks_done(&self) -> (usize, usize) {
+		(0, 0)
+	}
+
+	fn begin_restore(&self, _manifest: ManifestData) {
+		warn!(target: "snapshot", "Ignoring restoration request: snapshot service is read-only.");
+	}
+
+	fn abort_restore(&self) {}
+
+	fn restore_state_chunk(&self, _hash: H256, _chunk: Bytes) -> bool {
+		false
+	}
+
+	fn restore_block_chunk(&self, _hash: H256, _chunk: Bytes) -> bool {
+		false
+	}
+
+	fn pending_chunks(&self) -> usize {
+		0
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::fs::{self, File};
+	use std::io::{self, Read, Write};
+	use std::sync::Arc;
+	use devtools::RandomTempPath;
+	use error::Error;
+	use snapshot::{Error as SnapshotError, RestorationMode};
+	use snapshot::io::{LooseReader, LooseWriter, SnapshotReader};
+	use snapshot::tests::helpers::state_snapshot;
+	use spec::Spec;
+	use util::journaldb::Algorithm;
+	use util::kvdb::in_memory;
+	use util::snappy;
+	use super::{decompress_chunk, move_dir_with, Restoration, RestorationParams, EXDEV};
+
+	#[test]
+	fn restores_into_in_memory_db() {
+		let root = RandomTempPath::create_dir();
+		let mut fixture = root.as_path().to_owned();
+		fixture.push("fixture");
+		let mut recovery = root.as_path().to_owned();
+		recovery.push("recovery");
+
+		let manifest = state_snapshot(fixture.clone(), 50);
+		let reader = LooseReader::new(fixture).unwrap();
+		let genesis = Spec::new_null().genesis_block();
+
+		let mut rest = Restoration::new(RestorationParams {
+			manifest: manifest.clone(),
+			mode: RestorationMode::StateOnly,
+			pruning: Algorithm::Archive,
+			db: Arc::new(in_memory(::db::NUM_COLUMNS.unwrap_or(0))),
+			writer: LooseWriter::new(recovery).unwrap(),
+			genesis: &genesis,
+			trust_blocks: false,
+		}).unwrap();
+
+		for hash in &manifest.state_hashes {
+			rest.feed_state(*hash, &reader.chunk(*hash).unwrap()).unwrap();
+		}
+
+		assert!(rest.is_done());
+		assert_eq!(rest.state.state_root(), manifest.state_root);
+		rest.finalize().unwrap();
+	}
+
+	#[test]
+	fn decompress_chunk_rejects_huge_chunks() {
+		let mut buffer = Vec::new();
+
+		let chunk = snappy::compress(b"some chunk data");
+		assert_eq!(decompress_chunk(&chunk, &mut buffer).unwrap(), 15);
+		assert_eq!(&buffer[..15], b"some chunk data");
+
+		// a header declaring 1GiB of uncompressed data, with nothing behind it.
+		let bomb = [0x80, 0x80, 0x80, 0x80, 0x04];
+		match decompress_chunk(&bomb, &mut buffer) {
+			Err(Error::Snapshot(SnapshotError::ChunkTooLarge(len))) => assert_eq!(len, 1 << 30),
+			other => panic!("unexpected result: {:?}", other),
+		}
+		assert!(buffer.len() < 1 << 30);
+	}
+
+	#[test]
+	fn move_dir_copies_across_devices() {
+		let root = RandomTempPath::create_dir();
+		let mut from = root.as_path().to_owned();
+		from.push("from");
+		let mut to = root.as_path().to_owned();
+		to.push("to");
+
+		let mut nested = from.clone();
+		nested.push("nested");
+		fs::create_dir_all(&nested).unwrap();
+		nested.push("file");
+		File::create(&nested).unwrap().write_all(b"data").unwrap();
+
+		move_dir_with(&from, &to, |_, _| Err(io::Error::from_raw_os_error(EXDEV))).unwrap();
+
+		let mut moved = to.clone();
+		moved.push("nested");
+		moved.push("file");
+		let mut content = Vec::new();
+		File::open(&moved).unwrap().read_to_end(&mut content).unwrap();
+		assert_eq!(content, b"data");
+		assert!(!from.exists());
+	}
+
+	#[test]
+	fn move_dir_reports_other_errors() {
+		let root = RandomTempPath::create_dir();
+		let mut from = root.as_path().to_owned();
+		from.push("from");
+		fs::create_dir(&from).unwrap();
+		let mut to = root.as_path().to_owned();
+		to.push("to");
+
+		let res = move_dir_with(&from, &to, |_, _| Err(io::Error::new(io::ErrorKind::Other, "failure")));
+		assert!(res.is_err());
+		assert!(from.exists());
+		assert!(!to.exists());
 	}
 }
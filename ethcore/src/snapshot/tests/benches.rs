@@ -0,0 +1,65 @@
+// Copyright 2015, 2016 Ethcore (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Benchmarks for serving snapshot chunks.
+//! should be started with:
+//! ```bash
+//! multirust run nightly cargo bench --features benches
+//! ```
+
+extern crate test;
+
+use self::test::Bencher;
+
+use devtools::RandomTempPath;
+use snapshot::{RestorationMode, SnapshotService};
+use snapshot::io::{LooseReader, SnapshotReader};
+use super::service::{make_fixture, restore_fixture};
+
+#[bench]
+fn same_chunk_from_disk(b: &mut Bencher) {
+	let root = RandomTempPath::create_dir();
+	let (fixture, manifest) = make_fixture(&root);
+	let reader = LooseReader::new(fixture).unwrap();
+	let hash = manifest.state_hashes[0];
+
+	b.iter(|| reader.chunk(hash).unwrap());
+}
+
+#[bench]
+fn manifest_from_service(b: &mut Bencher) {
+	let root = RandomTempPath::create_dir();
+	let (service, _) = restore_fixture(&root, RestorationMode::Full);
+
+	b.iter(|| service.manifest().unwrap());
+}
+
+#[bench]
+fn shared_manifest_from_service(b: &mut Bencher) {
+	let root = RandomTempPath::create_dir();
+	let (service, _) = restore_fixture(&root, RestorationMode::Full);
+
+	b.iter(|| service.shared_manifest().unwrap());
+}
+
+#[bench]
+fn same_chunk_from_service(b: &mut Bencher) {
+	let root = RandomTempPath::create_dir();
+	let (service, _) = restore_fixture(&root, RestorationMode::Full);
+
+	let hash = service.manifest().unwrap().state_hashes[0];
+	b.iter(|| service.chunk(hash).unwrap());
+}
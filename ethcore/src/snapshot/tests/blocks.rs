@@ -20,15 +20,53 @@ use devtools::RandomTempPath;
 
 use blockchain::generator::{ChainGenerator, ChainIterator, BlockFinalizer};
 use blockchain::BlockChain;
+use builtin::Builtin;
+use engines::{Engine, NullEngine};
+use env_info::EnvInfo;
+use error::{BlockError, Error};
+use evm::Schedule;
+use header::Header;
 use snapshot::{chunk_blocks, BlockRebuilder, Progress};
 use snapshot::io::{PackedReader, PackedWriter, SnapshotReader, SnapshotWriter};
+use spec::CommonParams;
 
-use util::{Mutex, snappy};
+use util::{Address, Mutex, snappy};
 use util::kvdb::{Database, DatabaseConfig};
 
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
+// engine which rejects every block it is asked to verify.
+struct RejectingEngine(NullEngine);
+
+impl Engine for RejectingEngine {
+	fn name(&self) -> &str { "RejectingEngine" }
+	fn params(&self) -> &CommonParams { self.0.params() }
+	fn builtins(&self) -> &BTreeMap<Address, Builtin> { self.0.builtins() }
+	fn schedule(&self, env_info: &EnvInfo) -> Schedule { self.0.schedule(env_info) }
+
+	fn verify_block_basic(&self, _header: &Header, _block: Option<&[u8]>) -> Result<(), Error> {
+		Err(BlockError::InvalidSeal.into())
+	}
+}
+
+fn null_engine() -> NullEngine {
+	NullEngine::new(Default::default(), Default::default())
+}
+
 fn chunk_and_restore(amount: u64) {
+	chunk_and_restore_skipping(amount, None).unwrap();
+}
+
+// chunk a chain of the given length and restore it, leaving out the block chunk
+// at the given manifest index if any.
+fn chunk_and_restore_skipping(amount: u64, skip: Option<usize>) -> Result<(), Error> {
+	chunk_and_restore_with(amount, skip, &null_engine(), false)
+}
+
+// chunk a chain of the given length and restore it, verifying blocks with the given
+// engine unless they are trusted.
+fn chunk_and_restore_with(amount: u64, skip: Option<usize>, engine: &Engine, trusted: bool) -> Result<(), Error> {
 	let mut canon_chain = ChainGenerator::default();
 	let mut finalizer = BlockFinalizer::default();
 	let genesis = canon_chain.generate(&mut finalizer).unwrap();
@@ -70,19 +108,24 @@ fn chunk_and_restore(amount: u64) {
 	let new_db = Arc::new(Database::open(&db_cfg, new_path.as_str()).unwrap());
 	let new_chain = BlockChain::new(Default::default(), &genesis, new_db.clone());
 	let mut rebuilder = BlockRebuilder::new(new_chain, amount).unwrap();
+	rebuilder.set_trusted(trusted);
 	let reader = PackedReader::new(&snapshot_path).unwrap().unwrap();
-	let engine = ::engines::NullEngine::new(Default::default(), Default::default());
-	for chunk_hash in &reader.manifest().block_hashes {
+	let block_hashes = &reader.manifest().block_hashes;
+	// a skipped chunk must have an older one after it.
+	if let Some(skip) = skip { assert!(block_hashes.len() > skip + 1) }
+	for (idx, chunk_hash) in block_hashes.iter().enumerate() {
+		if Some(idx) == skip { continue }
 		let compressed = reader.chunk(*chunk_hash).unwrap();
 		let chunk = snappy::decompress(&compressed).unwrap();
-		rebuilder.feed(&chunk, &engine).unwrap();
+		try!(rebuilder.feed(&chunk, engine));
 	}
 
-	rebuilder.glue_chunks();
+	try!(rebuilder.glue_chunks());
 
 	// and test it.
 	let new_chain = BlockChain::new(Default::default(), &genesis, new_db);
 	assert_eq!(new_chain.best_block_hash(), best_hash);
+	Ok(())
 }
 
 #[test]
@@ -90,3 +133,35 @@ fn chunk_and_restore_500() { chunk_and_restore(500) }
 
 #[test]
 fn chunk_and_restore_40k() { chunk_and_restore(40000) }
+
+#[test]
+fn gap_in_restored_chain_is_rejected() {
+	use snapshot::Error as SnapshotError;
+
+	// leave out the chunk containing the head of the chain.
+	match chunk_and_restore_skipping(40000, Some(0)) {
+		Err(Error::Snapshot(SnapshotError::ChainGap(40000))) => {}
+		other => panic!("expected chain gap error, got {:?}", other),
+	}
+
+	// leave out a chunk in the middle, so the chunk above it has no parent.
+	match chunk_and_restore_skipping(40000, Some(1)) {
+		Err(Error::Snapshot(SnapshotError::ChainGap(num))) => assert!(num < 40000),
+		other => panic!("expected chain gap error, got {:?}", other),
+	}
+}
+
+#[test]
+fn trusted_blocks_skip_verification() {
+	let engine = RejectingEngine(null_engine());
+	chunk_and_restore_with(500, None, &engine, true).unwrap();
+}
+
+#[test]
+fn untrusted_blocks_are_verified() {
+	let engine = RejectingEngine(null_engine());
+	match chunk_and_restore_with(500, None, &engine, false) {
+		Err(Error::Block(BlockError::InvalidSeal)) => {}
+		other => panic!("expected invalid seal error, got {:?}", other),
+	}
+}
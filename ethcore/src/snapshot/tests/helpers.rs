@@ -18,15 +18,24 @@
 //! which can be queried before and after a full snapshot/restore cycle.
 
 use account_db::AccountDBMut;
-use rand::Rng;
+use rand::{self, Rng};
 use snapshot::account::Account;
+use snapshot::io::{LooseWriter, SnapshotReader, SnapshotWriter};
+use snapshot::{chunk_state, ManifestData, Progress};
 
+use util::{Bytes, Mutex};
 use util::hash::{FixedHash, H256};
 use util::hashdb::HashDB;
+use util::memorydb::MemoryDB;
 use util::trie::{Alphabet, StandardMap, SecTrieDBMut, TrieMut, ValueMode};
 use util::trie::{TrieDB, TrieDBMut, Trie};
 use util::rlp::SHA3_NULL_RLP;
 
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+
 // the proportion of accounts we will alter each tick.
 const ACCOUNT_CHURN: f32 = 0.01;
 
@@ -122,3 +131,90 @@ pub fn compare_dbs(one: &HashDB, two: &HashDB) {
 		assert_eq!(one.get(&key).unwrap(), two.get(&key).unwrap());
 	}
 }
+
+/// Produce a random state over the given number of ticks and write a
+/// state-only loose snapshot of it into the given directory.
+/// Returns the manifest of the written snapshot.
+pub fn state_snapshot(dir: PathBuf, ticks: usize) -> ManifestData {
+	let mut producer = StateProducer::new();
+	let mut rng = rand::thread_rng();
+	let mut db = MemoryDB::new();
+
+	for _ in 0..ticks {
+		producer.tick(&mut rng, &mut db);
+	}
+
+	let state_root = producer.state_root();
+	let writer = Mutex::new(LooseWriter::new(dir).unwrap());
+	let state_hashes = chunk_state(&db, &state_root, &writer, &Progress::default()).unwrap();
+
+	let manifest = ManifestData {
+		state_hashes: state_hashes,
+		block_hashes: Vec::new(),
+		state_root: state_root,
+		block_number: 0,
+		block_hash: H256::default(),
+	};
+
+	writer.into_inner().finish(manifest.clone()).unwrap();
+	manifest
+}
+
+/// Slot which a `MemoryWriter` deposits its snapshot into once finished.
+pub type MemorySnapshot = Arc<Mutex<Option<MemoryReader>>>;
+
+/// Writes snapshot chunks into memory. Like the loose writer, the snapshot
+/// only becomes readable once the manifest has been written by `finish`.
+pub struct MemoryWriter {
+	chunks: HashMap<H256, Bytes>,
+	target: MemorySnapshot,
+}
+
+impl MemoryWriter {
+	/// Create a new `MemoryWriter` which will deposit the finished snapshot into `target`.
+	pub fn new(target: MemorySnapshot) -> Self {
+		MemoryWriter {
+			chunks: HashMap::new(),
+			target: target,
+		}
+	}
+}
+
+impl SnapshotWriter for MemoryWriter {
+	fn write_state_chunk(&mut self, hash: H256, chunk: &[u8]) -> io::Result<()> {
+		self.chunks.insert(hash, chunk.to_vec());
+		Ok(())
+	}
+
+	fn write_block_chunk(&mut self, hash: H256, chunk: &[u8]) -> io::Result<()> {
+		self.chunks.insert(hash, chunk.to_vec());
+		Ok(())
+	}
+
+	fn finish(self, manifest: ManifestData) -> io::Result<()> {
+		*self.target.lock() = Some(MemoryReader {
+			chunks: self.chunks,
+			manifest: manifest,
+		});
+
+		Ok(())
+	}
+}
+
+/// Reads a snapshot written by a `MemoryWriter`.
+#[derive(Clone)]
+pub struct MemoryReader {
+	chunks: HashMap<H256, Bytes>,
+	manifest: ManifestData,
+}
+
+impl SnapshotReader for MemoryReader {
+	fn manifest(&self) -> &ManifestData {
+		&self.manifest
+	}
+
+	fn chunk(&self, hash: H256) -> io::Result<Bytes> {
+		self.chunks.get(&hash).cloned()
+			.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("no chunk with hash {}", hash)))
+	}
+}
@@ -0,0 +1,131 @@
+// Copyright 2015, 2016 Ethcore (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Tests for snapshot i/o.
+
+use devtools::RandomTempPath;
+use snapshot::ManifestData;
+use snapshot::io::{LooseReader, LooseWriter, SnapshotReader, SnapshotWriter};
+use util::hash::{FixedHash, H256};
+use util::Hashable;
+
+use std::fs::File;
+use std::io::Write;
+
+// write a loose snapshot holding the given state chunks, returning their hashes.
+fn write_snapshot(root: &RandomTempPath, chunks: &[&[u8]]) -> Vec<H256> {
+	let hashes: Vec<_> = chunks.iter().map(|_| H256::random()).collect();
+	let mut writer = LooseWriter::new(root.as_path().to_owned()).unwrap();
+	for (hash, chunk) in hashes.iter().zip(chunks) {
+		writer.write_state_chunk(*hash, chunk).unwrap();
+	}
+
+	writer.finish(ManifestData {
+		state_hashes: hashes.clone(),
+		block_hashes: Vec::new(),
+		state_root: H256::random(),
+		block_number: 1000,
+		block_hash: H256::random(),
+	}).unwrap();
+	hashes
+}
+
+#[test]
+fn loose_reader_serves_cached_chunks() {
+	let root = RandomTempPath::create_dir();
+	let hashes = write_snapshot(&root, &[b"first chunk", b"second chunk"]);
+	let reader = LooseReader::new(root.as_path().to_owned()).unwrap().with_cache(1024);
+
+	assert_eq!(reader.chunk(hashes[0]).unwrap(), b"first chunk");
+	assert_eq!(reader.chunk(hashes[0]).unwrap(), b"first chunk");
+	assert_eq!(reader.disk_reads(), 1);
+
+	assert_eq!(reader.chunk(hashes[1]).unwrap(), b"second chunk");
+	assert_eq!(reader.disk_reads(), 2);
+}
+
+#[test]
+fn loose_reader_cache_is_size_bounded() {
+	let root = RandomTempPath::create_dir();
+	let hashes = write_snapshot(&root, &[&[1; 100], &[2; 100], &[3; 300]]);
+	// room for two small chunks only.
+	let reader = LooseReader::new(root.as_path().to_owned()).unwrap().with_cache(250);
+
+	reader.chunk(hashes[0]).unwrap();
+	reader.chunk(hashes[1]).unwrap();
+	reader.chunk(hashes[0]).unwrap();
+	assert_eq!(reader.disk_reads(), 2);
+
+	// too big to be cached at all.
+	reader.chunk(hashes[2]).unwrap();
+	reader.chunk(hashes[2]).unwrap();
+	assert_eq!(reader.disk_reads(), 4);
+
+	let root = RandomTempPath::create_dir();
+	let hashes = write_snapshot(&root, &[&[1; 100], &[2; 100], &[3; 100]]);
+	let reader = LooseReader::new(root.as_path().to_owned()).unwrap().with_cache(250);
+	reader.chunk(hashes[0]).unwrap();
+	reader.chunk(hashes[1]).unwrap();
+	reader.chunk(hashes[0]).unwrap();
+	// `0` was used more recently, so `1` is evicted.
+	reader.chunk(hashes[2]).unwrap();
+	assert_eq!(reader.disk_reads(), 3);
+
+	reader.chunk(hashes[0]).unwrap();
+	assert_eq!(reader.disk_reads(), 3);
+	reader.chunk(hashes[1]).unwrap();
+	assert_eq!(reader.disk_reads(), 4);
+}
+
+#[test]
+fn loose_reader_without_cache_reads_disk() {
+	let root = RandomTempPath::create_dir();
+	let hashes = write_snapshot(&root, &[b"chunk"]);
+	let reader = LooseReader::new(root.as_path().to_owned()).unwrap();
+
+	reader.chunk(hashes[0]).unwrap();
+	reader.chunk(hashes[0]).unwrap();
+	assert_eq!(reader.disk_reads(), 2);
+}
+
+#[test]
+fn loose_reader_verification_rejects_corrupted_chunks() {
+	let root = RandomTempPath::create_dir();
+	let chunk = b"chunk to be corrupted";
+	let hash = chunk.sha3();
+	let mut writer = LooseWriter::new(root.as_path().to_owned()).unwrap();
+	writer.write_state_chunk(hash, chunk).unwrap();
+	writer.finish(ManifestData {
+		state_hashes: vec![hash],
+		block_hashes: Vec::new(),
+		state_root: H256::random(),
+		block_number: 1000,
+		block_hash: H256::random(),
+	}).unwrap();
+
+	let reader = LooseReader::new(root.as_path().to_owned()).unwrap().with_verification();
+	assert_eq!(reader.chunk(hash).unwrap(), &chunk[..]);
+
+	let mut path = root.as_path().to_owned();
+	path.push(hash.hex());
+	File::create(&path).unwrap().write_all(b"chunk to be corrupteD").unwrap();
+
+	assert!(reader.chunk(hash).is_err());
+
+	// not verified by default.
+	let reader = LooseReader::new(root.as_path().to_owned()).unwrap();
+	assert_eq!(reader.chunk(hash).unwrap(), b"chunk to be corrupteD");
+}
@@ -0,0 +1,93 @@
+// Copyright 2015, 2016 Ethcore (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Tests for manifest diffing and chunk verification.
+
+use snapshot::{verify_chunk, ManifestData, ManifestDiff};
+use util::Hashable;
+use util::hash::{FixedHash, H256};
+
+use std::collections::HashSet;
+
+fn manifest(state_hashes: Vec<H256>, block_hashes: Vec<H256>) -> ManifestData {
+	ManifestData {
+		state_hashes: state_hashes,
+		block_hashes: block_hashes,
+		state_root: H256::random(),
+		block_number: 1000,
+		block_hash: H256::random(),
+	}
+}
+
+fn set(hashes: &[H256]) -> HashSet<H256> {
+	hashes.iter().cloned().collect()
+}
+
+#[test]
+fn diff_overlapping_manifests() {
+	let hashes: Vec<_> = (0..8).map(|_| H256::random()).collect();
+
+	let old = manifest(hashes[0..3].to_vec(), hashes[5..7].to_vec());
+	let new = manifest(hashes[1..5].to_vec(), hashes[6..8].to_vec());
+
+	let diff = old.diff(&new);
+	assert_eq!(diff, ManifestDiff {
+		added_state: set(&hashes[3..5]),
+		removed_state: set(&hashes[0..1]),
+		added_blocks: set(&hashes[7..8]),
+		removed_blocks: set(&hashes[5..6]),
+	});
+
+	// the reverse diff swaps additions and removals.
+	let reverse = new.diff(&old);
+	assert_eq!(reverse.added_state, diff.removed_state);
+	assert_eq!(reverse.removed_blocks, diff.added_blocks);
+}
+
+#[test]
+fn diff_identical_manifests() {
+	let hashes: Vec<_> = (0..4).map(|_| H256::random()).collect();
+	let old = manifest(hashes[0..2].to_vec(), hashes[2..4].to_vec());
+
+	assert!(old.diff(&old.clone()).is_empty());
+	assert!(!old.diff(&manifest(hashes[0..2].to_vec(), Vec::new())).is_empty());
+}
+
+#[test]
+fn verify_valid_chunk() {
+	let state_chunk = b"state chunk".to_vec();
+	let block_chunk = b"block chunk".to_vec();
+	let manifest = manifest(vec![state_chunk.sha3()], vec![block_chunk.sha3()]);
+
+	assert!(verify_chunk(&manifest, state_chunk.sha3(), &state_chunk));
+	assert!(verify_chunk(&manifest, block_chunk.sha3(), &block_chunk));
+}
+
+#[test]
+fn verify_chunk_with_wrong_hash() {
+	let chunk = b"state chunk".to_vec();
+	let manifest = manifest(vec![chunk.sha3()], Vec::new());
+
+	assert!(!verify_chunk(&manifest, chunk.sha3(), b"tampered chunk"));
+}
+
+#[test]
+fn verify_chunk_not_in_manifest() {
+	let chunk = b"stray chunk".to_vec();
+	let manifest = manifest(vec![H256::random()], vec![H256::random()]);
+
+	assert!(!verify_chunk(&manifest, chunk.sha3(), &chunk));
+}
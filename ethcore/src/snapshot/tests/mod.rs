@@ -17,6 +17,12 @@
 //! Snapshot tests.
 
 mod blocks;
+mod io;
+mod manifest;
 mod state;
+mod service;
+
+#[cfg(feature="benches")]
+mod benches;
 
 pub mod helpers;
\ No newline at end of file
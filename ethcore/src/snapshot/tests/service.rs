@@ -0,0 +1,999 @@
+// Copyright 2015, 2016 Ethcore (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! Tests for the snapshot service.
+
+use client::BlockChainClient;
+use devtools::RandomTempPath;
+use error::Error;
+use io::IoChannel;
+use spec::Spec;
+
+use snapshot::{ManifestData, ReadOnlyService, RestorationMode, RestorationStatus, Service, SnapshotInfo, SnapshotService};
+use snapshot::io::{LooseReader, LooseWriter, SnapshotReader, SnapshotWriter};
+use snapshot::service::restoration_db_factory;
+use super::helpers::state_snapshot;
+use tests::helpers::generate_dummy_client_with_data;
+
+use util::hash::{FixedHash, H256};
+use util::journaldb::Algorithm;
+use util::kvdb::{self, KeyValueDB};
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+// create a snapshot service whose data lives under the given directory.
+pub fn make_service(root: &RandomTempPath) -> Service {
+	let spec = Spec::new_null();
+	let mut client_db = root.as_path().to_owned();
+	client_db.push("pruning");
+	client_db.push("db");
+
+	Service::new(&spec, Algorithm::Archive, client_db, IoChannel::disconnected()).unwrap()
+}
+
+// write a state-only snapshot into a fixture directory beneath the root.
+pub fn make_fixture(root: &RandomTempPath) -> (PathBuf, ManifestData) {
+	let mut dir = root.as_path().to_owned();
+	dir.push("fixture");
+
+	let manifest = state_snapshot(dir.clone(), 50);
+	(dir, manifest)
+}
+
+// feed the service every state chunk of the snapshot read by the reader.
+pub fn feed_fixture(service: &Service, reader: &LooseReader) {
+	for hash in &reader.manifest().state_hashes {
+		service.feed_state_chunk(*hash, &reader.chunk(*hash).unwrap());
+	}
+}
+
+// restore the given manifest from the chunks read by the reader.
+// chunks listed in the manifest but not in the reader's snapshot are never fed,
+// keeping the restoration ongoing.
+pub fn restore_from(service: &Service, reader: &LooseReader, manifest: &ManifestData, mode: RestorationMode) {
+	service.init_restore(manifest.clone(), mode).unwrap();
+	feed_fixture(service, reader);
+}
+
+// restore the fixture into a new service, returning it along with the fixture's manifest.
+pub fn restore_fixture(root: &RandomTempPath, mode: RestorationMode) -> (Service, ManifestData) {
+	let (fixture, manifest) = make_fixture(root);
+	let reader = LooseReader::new(fixture).unwrap();
+
+	let service = make_service(root);
+	restore_from(&service, &reader, &manifest, mode);
+	assert_eq!(service.status(), RestorationStatus::Inactive);
+	(service, manifest)
+}
+
+// create a snapshot service serving a copy of the fixture as its current snapshot.
+pub fn serve_fixture(root: &RandomTempPath) -> (Service, ManifestData) {
+	let (fixture, manifest) = make_fixture(root);
+
+	let current = root.as_path().join("snapshot").join("current");
+	fs::create_dir_all(&current).unwrap();
+	for entry in fs::read_dir(&fixture).unwrap() {
+		let path = entry.unwrap().path();
+		fs::copy(&path, current.join(path.file_name().unwrap())).unwrap();
+	}
+
+	(make_service(root), manifest)
+}
+
+#[test]
+fn state_only_restoration_ignores_blocks() {
+	let root = RandomTempPath::create_dir();
+	let (fixture, mut manifest) = make_fixture(&root);
+	let reader = LooseReader::new(fixture).unwrap();
+
+	// block chunks which will never be fed.
+	manifest.block_hashes = vec![H256::random(), H256::random()];
+
+	let service = make_service(&root);
+	restore_from(&service, &reader, &manifest, RestorationMode::StateOnly);
+	assert_eq!(service.status(), RestorationStatus::Inactive);
+
+	let restored = service.manifest().unwrap();
+	assert_eq!(restored.state_root, manifest.state_root);
+	assert!(restored.block_hashes.is_empty());
+}
+
+#[test]
+fn available_chunks_match_manifest() {
+	let root = RandomTempPath::create_dir();
+	assert!(make_service(&root).available_chunks().is_empty());
+
+	let (service, manifest) = restore_fixture(&root, RestorationMode::Full);
+	let expected: Vec<_> = manifest.state_hashes.iter().chain(manifest.block_hashes.iter()).cloned().collect();
+	assert_eq!(service.available_chunks(), expected);
+}
+
+#[test]
+fn summary_matches_completed_restoration() {
+	let root = RandomTempPath::create_dir();
+	assert!(make_service(&root).last_restoration_summary().is_none());
+
+	let (service, manifest) = restore_fixture(&root, RestorationMode::Full);
+	let summary = service.last_restoration_summary().unwrap();
+	assert_eq!(summary.state_chunks, manifest.state_hashes.len());
+	assert_eq!(summary.block_chunks, 0);
+	assert_eq!(summary.final_state_root, manifest.state_root);
+	assert_eq!(summary.block_number, manifest.block_number);
+}
+
+#[test]
+fn retained_backup_can_be_rolled_back() {
+	let root = RandomTempPath::create_dir();
+	let (fixture, manifest) = make_fixture(&root);
+	let reader = LooseReader::new(fixture).unwrap();
+
+	let service = make_service(&root);
+	service.set_backup_retention(true);
+	assert!(service.rollback_to_backup().is_err());
+
+	// mark the pre-existing client database so it can be recognized later.
+	let mut client_db = root.as_path().to_owned();
+	client_db.push("pruning");
+	client_db.push("db");
+	fs::create_dir_all(&client_db).unwrap();
+
+	let mut marker = client_db.clone();
+	marker.push("marker");
+	File::create(&marker).unwrap();
+
+	restore_from(&service, &reader, &manifest, RestorationMode::Full);
+	assert_eq!(service.status(), RestorationStatus::Inactive);
+	assert!(!marker.exists());
+
+	let mut snapshot_root = root.as_path().to_owned();
+	snapshot_root.push("snapshot");
+	let backups = fs::read_dir(&snapshot_root).unwrap()
+		.map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+		.filter(|name| name.starts_with("backup_db_"))
+		.count();
+	assert_eq!(backups, 1);
+
+	service.rollback_to_backup().unwrap();
+	assert!(marker.exists());
+	assert!(service.rollback_to_backup().is_err());
+}
+
+#[test]
+fn expected_state_root_while_restoring() {
+	let root = RandomTempPath::create_dir();
+	let (_, mut manifest) = make_fixture(&root);
+
+	// an extra chunk which is never fed keeps the restoration ongoing.
+	manifest.state_hashes.push(H256::random());
+
+	let service = make_service(&root);
+	assert_eq!(service.expected_state_root(), None);
+
+	service.init_restore(manifest.clone(), RestorationMode::Full).unwrap();
+	assert_eq!(service.expected_state_root(), Some(manifest.state_root));
+
+	service.abort_restore();
+	assert_eq!(service.expected_state_root(), None);
+}
+
+#[test]
+fn chunks_always_available_across_finalize() {
+	let root = RandomTempPath::create_dir();
+	let (fixture, manifest) = make_fixture(&root);
+	let reader = LooseReader::new(fixture).unwrap();
+
+	let restore = |service: &Service| {
+		restore_from(service, &reader, &manifest, RestorationMode::Full);
+		assert_eq!(service.status(), RestorationStatus::Inactive);
+	};
+
+	let service = Arc::new(make_service(&root));
+	restore(&*service);
+
+	// restoring the same snapshot again swaps in a reader serving identical chunks,
+	// so every request made meanwhile must succeed.
+	let done = Arc::new(AtomicBool::new(false));
+	let seeders: Vec<_> = (0..4).map(|_| {
+		let service = service.clone();
+		let done = done.clone();
+		let hashes = manifest.state_hashes.clone();
+		thread::spawn(move || {
+			let mut requests = 0;
+			while !done.load(Ordering::SeqCst) || requests == 0 {
+				for hash in &hashes {
+					assert!(service.chunk(*hash).is_some());
+					assert!(service.manifest().is_some());
+					requests += 1;
+				}
+			}
+		})
+	}).collect();
+
+	restore(&*service);
+	done.store(true, Ordering::SeqCst);
+
+	for seeder in seeders {
+		seeder.join().unwrap();
+	}
+}
+
+#[test]
+fn restore_from_manifest_bytes() {
+	let root = RandomTempPath::create_dir();
+	let (_, manifest) = make_fixture(&root);
+	let raw = manifest.clone().into_rlp();
+
+	let service = make_service(&root);
+	assert!(service.begin_restore_from_bytes(&raw[..raw.len() - 1]).is_err());
+	assert!(service.begin_restore_from_bytes(&[]).is_err());
+	assert_eq!(service.status(), RestorationStatus::Inactive);
+
+	service.begin_restore_from_bytes(&raw).unwrap();
+	assert!(service.status().is_ongoing());
+	assert_eq!(service.expected_state_root(), Some(manifest.state_root));
+}
+
+#[test]
+fn manifest_at_picks_closest_snapshot_below() {
+	let root = RandomTempPath::create_dir();
+	let service = make_service(&root);
+
+	// keep two snapshots besides each other in the snapshot directory.
+	for &(name, number) in &[("at_100", 100), ("at_200", 200)] {
+		let mut dir = root.as_path().to_owned();
+		dir.push("snapshot");
+		dir.push(name);
+
+		let mut manifest = state_snapshot(dir.clone(), 10);
+		manifest.block_number = number;
+		LooseWriter::new(dir).unwrap().finish(manifest).unwrap();
+	}
+
+	assert_eq!(service.manifest_at(50), None);
+	assert_eq!(service.manifest_at(100).unwrap().block_number, 100);
+	assert_eq!(service.manifest_at(150).unwrap().block_number, 100);
+	assert_eq!(service.manifest_at(250).unwrap().block_number, 200);
+}
+
+#[test]
+fn peer_request_limit() {
+	let root = RandomTempPath::create_dir();
+	let (service, manifest) = restore_fixture(&root, RestorationMode::Full);
+	let hash = manifest.state_hashes[0];
+	let chunk = service.chunk(hash);
+
+	// no limit by default.
+	for _ in 0..10 {
+		assert!(service.chunk_for_peer(1, hash).is_some());
+	}
+
+	service.set_peer_request_limit(Some(3));
+	for _ in 0..3 {
+		assert_eq!(service.chunk_for_peer(2, hash), chunk);
+	}
+	assert!(service.chunk_for_peer(2, hash).is_none());
+
+	// other peers are unaffected, and the count can be reset.
+	assert!(service.chunk_for_peer(3, hash).is_some());
+	service.reset_peer_requests(2);
+	assert!(service.chunk_for_peer(2, hash).is_some());
+
+	// the count starts over once the window has passed.
+	service.set_peer_request_window(Duration::from_millis(500));
+	service.reset_peer_requests(2);
+	for _ in 0..3 {
+		assert!(service.chunk_for_peer(2, hash).is_some());
+	}
+	assert!(service.chunk_for_peer(2, hash).is_none());
+	thread::sleep(Duration::from_millis(600));
+	assert!(service.chunk_for_peer(2, hash).is_some());
+
+	// the limit doesn't apply to requests made without a peer.
+	assert!(service.chunk(hash).is_some());
+}
+
+#[test]
+fn accounts_rebuilt_rises_as_chunks_are_fed() {
+	let root = RandomTempPath::create_dir();
+	let (fixture, mut manifest) = make_fixture(&root);
+	let reader = LooseReader::new(fixture).unwrap();
+	let hashes = manifest.state_hashes.clone();
+
+	// an extra chunk which is never fed keeps the restoration ongoing.
+	manifest.state_hashes.push(H256::random());
+
+	let service = make_service(&root);
+	service.init_restore(manifest, RestorationMode::Full).unwrap();
+	assert_eq!(service.accounts_rebuilt(), 0);
+
+	let mut last = 0;
+	for hash in &hashes {
+		service.feed_state_chunk(*hash, &reader.chunk(*hash).unwrap());
+		assert!(service.accounts_rebuilt() > last);
+		last = service.accounts_rebuilt();
+	}
+
+	assert!(service.status().is_ongoing());
+}
+
+#[test]
+fn current_manifest_fields() {
+	let root = RandomTempPath::create_dir();
+	{
+		let service = make_service(&root);
+		assert_eq!(service.current_block_number(), None);
+		assert_eq!(service.current_state_root(), None);
+	}
+
+	let (service, _) = restore_fixture(&root, RestorationMode::Full);
+	let current = service.manifest().unwrap();
+	assert_eq!(service.current_block_number(), Some(current.block_number));
+	assert_eq!(service.current_state_root(), Some(current.state_root));
+}
+
+#[test]
+fn feeds_during_finalization_are_ignored() {
+	let root = RandomTempPath::create_dir();
+	let (fixture, manifest) = make_fixture(&root);
+	let reader = Arc::new(LooseReader::new(fixture).unwrap());
+
+	let service = Arc::new(make_service(&root));
+	service.init_restore(manifest.clone(), RestorationMode::Full).unwrap();
+
+	// every feeder delivers the whole snapshot, so all but one of the final
+	// chunks arrive while the restoration is being (or has been) finalized.
+	let feeders: Vec<_> = (0..4).map(|_| {
+		let service = service.clone();
+		let reader = reader.clone();
+		let hashes = manifest.state_hashes.clone();
+		thread::spawn(move || {
+			for _ in 0..5 {
+				for hash in &hashes {
+					service.feed_state_chunk(*hash, &reader.chunk(*hash).unwrap());
+				}
+			}
+		})
+	}).collect();
+
+	for feeder in feeders {
+		feeder.join().unwrap();
+	}
+
+	assert_eq!(service.status(), RestorationStatus::Inactive);
+	assert_eq!(service.manifest().unwrap().state_root, manifest.state_root);
+	assert_eq!(service.last_restoration_summary().unwrap().final_state_root, manifest.state_root);
+
+	// a new restoration can begin once finalization is over.
+	service.init_restore(manifest, RestorationMode::Full).unwrap();
+	assert!(service.status().is_ongoing());
+}
+
+#[test]
+fn take_snapshot_then_restore() {
+	let root = RandomTempPath::create_dir();
+	let client = generate_dummy_client_with_data(10, 0, &[]);
+	let client = client.reference();
+
+	let service = make_service(&root);
+	assert!(!service.taking_snapshot());
+	service.take_snapshot(&**client, 10).unwrap();
+	assert!(service.snapshot_progress().done());
+
+	let manifest = service.manifest().unwrap();
+	assert_eq!(manifest.block_number, 10);
+	assert_eq!(manifest.block_hash, client.chain_info().best_block_hash);
+	assert!(!manifest.block_hashes.is_empty());
+
+	// restore from the chunks the service now serves.
+	let chunks: Vec<_> = manifest.state_hashes.iter().chain(manifest.block_hashes.iter())
+		.map(|hash| (*hash, service.chunk(*hash).unwrap()))
+		.collect();
+
+	service.init_restore(manifest.clone(), RestorationMode::Full).unwrap();
+	for &(hash, ref chunk) in &chunks[..manifest.state_hashes.len()] {
+		service.feed_state_chunk(hash, chunk);
+	}
+	for &(hash, ref chunk) in &chunks[manifest.state_hashes.len()..] {
+		service.feed_block_chunk(hash, chunk);
+	}
+
+	assert_eq!(service.status(), RestorationStatus::Inactive);
+	assert_eq!(service.last_restoration_summary().unwrap().final_state_root, manifest.state_root);
+}
+
+#[test]
+fn reset_clears_failed_restoration() {
+	let root = RandomTempPath::create_dir();
+	let (_, manifest) = make_fixture(&root);
+
+	let service = make_service(&root);
+	assert!(service.reset().is_ok());
+
+	service.init_restore(manifest.clone(), RestorationMode::Full).unwrap();
+	service.feed_state_chunk(manifest.state_hashes[0], b"not a snappy chunk");
+	assert_eq!(service.status(), RestorationStatus::Failed);
+
+	service.reset().unwrap();
+	assert_eq!(service.status(), RestorationStatus::Inactive);
+	assert_eq!(service.chunks_done(), (0, 0));
+
+	let mut restoration_dir = root.as_path().to_owned();
+	restoration_dir.push("snapshot");
+	restoration_dir.push("restoration");
+	assert!(!restoration_dir.exists());
+
+	// a new restoration may begin afterwards.
+	service.init_restore(manifest, RestorationMode::Full).unwrap();
+	assert!(service.status().is_ongoing());
+}
+
+#[test]
+fn missing_code_is_reported() {
+	use snapshot::StateRebuilder;
+	use util::{Hashable, RlpStream, Stream, U256, snappy};
+	use util::kvdb::{Database, DatabaseConfig, KeyValueDB};
+	use util::rlp::{Compressible, RlpType, UntrustedRlp};
+
+	let root = RandomTempPath::create_dir();
+	let code_hash = H256::random();
+
+	// a chunk with a single account whose code is only referenced by hash.
+	let chunk = {
+		let mut account = RlpStream::new_list(5);
+		account.append(&U256::zero())
+			.append(&U256::from(1))
+			.append(&2u8)
+			.append(&code_hash)
+			.append_raw(&RlpStream::new_list(0).out(), 1);
+		let fat_rlp = account.out();
+		let compressed = UntrustedRlp::new(&fat_rlp).compress(RlpType::Snapshot).to_vec();
+
+		let mut stream = RlpStream::new_list(1);
+		stream.begin_list(2).append(&H256::random()).append_raw(&compressed, 1);
+		stream.out()
+	};
+
+	// rebuild it once on the side to learn the state root.
+	let state_root = {
+		let mut db_path = root.as_path().to_owned();
+		db_path.push("scratch");
+		let cfg = DatabaseConfig::with_columns(::db::NUM_COLUMNS);
+		let db: Arc<KeyValueDB> = Arc::new(Database::open(&cfg, &db_path.to_string_lossy()).unwrap());
+		let mut rebuilder = StateRebuilder::new(db, Algorithm::Archive);
+		rebuilder.feed(&chunk).unwrap();
+		let state_root = rebuilder.state_root();
+		assert_eq!(rebuilder.check_missing(), Err(vec![code_hash]));
+		state_root
+	};
+
+	let compressed = snappy::compress(&chunk);
+	let chunk_hash = compressed.sha3();
+	let manifest = ManifestData {
+		state_hashes: vec![chunk_hash],
+		block_hashes: Vec::new(),
+		state_root: state_root,
+		block_number: 0,
+		block_hash: H256::default(),
+	};
+
+	let service = make_service(&root);
+	assert!(service.last_restoration_failure().is_none());
+
+	service.init_restore(manifest, RestorationMode::StateOnly).unwrap();
+	service.feed_state_chunk(chunk_hash, &compressed);
+	assert_eq!(service.status(), RestorationStatus::Failed);
+
+	let reason = service.last_restoration_failure().unwrap();
+	assert!(reason.contains(&format!("{:?}", code_hash)), "missing code hash not reported: {}", reason);
+
+	service.reset().unwrap();
+	assert!(service.last_restoration_failure().is_none());
+}
+
+#[test]
+fn restoring_manifest_while_restoring() {
+	let root = RandomTempPath::create_dir();
+	let (fixture, manifest) = make_fixture(&root);
+	let reader = LooseReader::new(fixture).unwrap();
+
+	let service = make_service(&root);
+	assert_eq!(service.restoring_manifest(), None);
+
+	service.init_restore(manifest.clone(), RestorationMode::Full).unwrap();
+	assert_eq!(service.restoring_manifest(), Some(manifest.clone()));
+	assert_eq!(service.manifest(), None);
+
+	feed_fixture(&service, &reader);
+	assert_eq!(service.status(), RestorationStatus::Inactive);
+	assert_eq!(service.restoring_manifest(), None);
+	assert_eq!(service.manifest(), Some(manifest));
+}
+
+#[test]
+fn second_restoration_requires_force() {
+	let root = RandomTempPath::create_dir();
+	let (_, manifest) = make_fixture(&root);
+	let mut other = manifest.clone();
+	other.state_root = H256::random();
+
+	let service = make_service(&root);
+	service.init_restore(manifest.clone(), RestorationMode::Full).unwrap();
+
+	assert!(service.init_restore(other.clone(), RestorationMode::Full).is_err());
+	assert!(service.status().is_ongoing());
+	assert_eq!(service.restoring_manifest(), Some(manifest));
+
+	service.force_init_restore(other.clone(), RestorationMode::Full).unwrap();
+	assert!(service.status().is_ongoing());
+	assert_eq!(service.restoring_manifest(), Some(other));
+}
+
+#[test]
+fn ongoing_status_carries_chunk_counts() {
+	let root = RandomTempPath::create_dir();
+	let (fixture, mut manifest) = make_fixture(&root);
+	let reader = LooseReader::new(fixture).unwrap();
+
+	// a block chunk which is never fed keeps the restoration going.
+	manifest.block_hashes = vec![H256::random()];
+
+	let service = make_service(&root);
+	service.init_restore(manifest.clone(), RestorationMode::Full).unwrap();
+
+	for (i, hash) in manifest.state_hashes.iter().enumerate() {
+		match service.status() {
+			RestorationStatus::Ongoing { state_chunks_done, block_chunks_done, state_chunks, block_chunks } => {
+				assert_eq!((state_chunks_done, block_chunks_done), service.chunks_done());
+				assert_eq!(state_chunks_done, i);
+				assert_eq!(state_chunks, manifest.state_hashes.len());
+				assert_eq!(block_chunks, 1);
+			}
+			status => panic!("unexpected status {:?}", status),
+		}
+
+		service.feed_state_chunk(*hash, &reader.chunk(*hash).unwrap());
+	}
+
+	assert_eq!(service.status(), RestorationStatus::Ongoing {
+		state_chunks_done: manifest.state_hashes.len(),
+		block_chunks_done: 0,
+		state_chunks: manifest.state_hashes.len(),
+		block_chunks: 1,
+	});
+	assert_eq!(service.chunks_done(), (manifest.state_hashes.len(), 0));
+}
+
+#[test]
+fn reports_busy_above_high_water_mark() {
+	let root = RandomTempPath::create_dir();
+	let service = make_service(&root);
+	service.set_chunk_high_water_mark(2);
+
+	// the io channel is disconnected, so queued chunks are never processed.
+	assert!(service.restore_state_chunk(H256::random(), vec![1]));
+	assert!(service.restore_block_chunk(H256::random(), vec![2]));
+	assert_eq!(service.pending_chunks(), 2);
+	assert!(service.is_busy());
+
+	assert!(!service.restore_state_chunk(H256::random(), vec![3]));
+	assert!(!service.restore_block_chunk(H256::random(), vec![4]));
+	assert_eq!(service.pending_chunks(), 2);
+
+	service.queued_chunk_done();
+	assert!(!service.is_busy());
+	assert!(service.restore_state_chunk(H256::random(), vec![5]));
+	assert_eq!(service.pending_chunks(), 2);
+
+	// spurious completions can't wrap the count around.
+	for _ in 0..3 {
+		service.queued_chunk_done();
+	}
+	assert_eq!(service.pending_chunks(), 0);
+	assert!(!service.is_busy());
+}
+
+#[test]
+fn exposes_pruning_algorithm() {
+	let root = RandomTempPath::create_dir();
+	assert_eq!(make_service(&root).pruning_algorithm(), Algorithm::Archive);
+
+	let spec = Spec::new_null();
+	let mut client_db = root.as_path().to_owned();
+	client_db.push("fast");
+	client_db.push("db");
+	let service = Service::new(&spec, Algorithm::OverlayRecent, client_db, IoChannel::disconnected()).unwrap();
+	assert_eq!(service.pruning_algorithm(), Algorithm::OverlayRecent);
+}
+
+#[test]
+fn shared_manifest_is_not_cloned() {
+	let root = RandomTempPath::create_dir();
+	assert!(make_service(&root).shared_manifest().is_none());
+
+	let (service, manifest) = restore_fixture(&root, RestorationMode::Full);
+	let first = service.shared_manifest().unwrap();
+	let second = service.shared_manifest().unwrap();
+	assert_eq!(*first, manifest);
+	assert_eq!(service.manifest(), Some(manifest));
+	assert_eq!(&*first as *const _, &*second as *const _);
+}
+
+#[test]
+fn read_only_service_serves_but_never_restores() {
+	let root = RandomTempPath::create_dir();
+	let (fixture, manifest) = make_fixture(&root);
+	let reader = LooseReader::new(fixture.clone()).unwrap();
+
+	let service = ReadOnlyService::new(fixture).unwrap();
+	assert_eq!(service.manifest(), Some(manifest.clone()));
+	assert_eq!(service.available_chunks(), manifest.state_hashes);
+	for hash in &manifest.state_hashes {
+		assert_eq!(service.chunk(*hash), Some(reader.chunk(*hash).unwrap()));
+	}
+	assert_eq!(service.chunk(H256::random()), None);
+
+	service.begin_restore(manifest.clone());
+	assert_eq!(service.status(), RestorationStatus::Inactive);
+	assert_eq!(service.restoring_manifest(), None);
+	assert!(!service.restore_state_chunk(manifest.state_hashes[0], reader.chunk(manifest.state_hashes[0]).unwrap()));
+	assert_eq!(service.pending_chunks(), 0);
+}
+
+#[test]
+fn async_finalization_reports_finalizing() {
+	let root = RandomTempPath::create_dir();
+	let (fixture, manifest) = make_fixture(&root);
+	let reader = LooseReader::new(fixture).unwrap();
+
+	// the disconnected IO channel never delivers the finalization message.
+	let service = make_service(&root);
+	service.set_async_finalization(true);
+	restore_from(&service, &reader, &manifest, RestorationMode::Full);
+	assert_eq!(service.status(), RestorationStatus::Finalizing);
+	assert!(service.init_restore(manifest.clone(), RestorationMode::Full).is_err());
+
+	service.finalize_pending_restoration();
+	assert_eq!(service.status(), RestorationStatus::Inactive);
+	assert_eq!(service.manifest().unwrap().state_root, manifest.state_root);
+	assert_eq!(service.last_restoration_summary().unwrap().final_state_root, manifest.state_root);
+
+	// nothing left to finalize.
+	service.finalize_pending_restoration();
+	assert_eq!(service.status(), RestorationStatus::Inactive);
+}
+
+#[test]
+fn abort_cancels_pending_finalization() {
+	let root = RandomTempPath::create_dir();
+	let (fixture, manifest) = make_fixture(&root);
+	let reader = LooseReader::new(fixture).unwrap();
+
+	let service = make_service(&root);
+	service.set_async_finalization(true);
+	restore_from(&service, &reader, &manifest, RestorationMode::Full);
+	assert_eq!(service.status(), RestorationStatus::Finalizing);
+
+	service.abort_restore();
+	assert_eq!(service.status(), RestorationStatus::Inactive);
+
+	// the cancelled restoration is never finalized.
+	service.finalize_pending_restoration();
+	assert!(service.manifest().is_none());
+	assert!(service.init_restore(manifest, RestorationMode::Full).is_ok());
+}
+
+#[test]
+fn elapsed_rises_during_restoration() {
+	let root = RandomTempPath::create_dir();
+	let (fixture, manifest) = make_fixture(&root);
+	let reader = LooseReader::new(fixture).unwrap();
+
+	let service = make_service(&root);
+	assert!(service.elapsed().is_none());
+
+	service.init_restore(manifest.clone(), RestorationMode::StateOnly).unwrap();
+	let first = service.elapsed().unwrap();
+	thread::sleep(Duration::from_millis(10));
+	let second = service.elapsed().unwrap();
+	assert!(second > first);
+
+	feed_fixture(&service, &reader);
+	assert_eq!(service.status(), RestorationStatus::Inactive);
+
+	// frozen at the final value.
+	let last = service.elapsed().unwrap();
+	assert!(last >= second);
+	assert_eq!(last, service.last_restoration_summary().unwrap().duration);
+	thread::sleep(Duration::from_millis(10));
+	assert_eq!(service.elapsed(), Some(last));
+}
+
+#[test]
+fn snapshot_info_is_consistent() {
+	let root = RandomTempPath::create_dir();
+	let (fixture, manifest) = make_fixture(&root);
+	let reader = LooseReader::new(fixture.clone()).unwrap();
+
+	let service = make_service(&root);
+	assert_eq!(service.snapshot_info(), SnapshotInfo {
+		restoration: RestorationStatus::Inactive,
+		elapsed: None,
+		eta: None,
+		block_number: None,
+		size: None,
+	});
+
+	// restore a snapshot to serve.
+	restore_from(&service, &reader, &manifest, RestorationMode::StateOnly);
+
+	// begin another restoration, with block chunks which will never be fed.
+	let mut next = manifest.clone();
+	next.block_hashes = vec![H256::random(), H256::random()];
+	restore_from(&service, &reader, &next, RestorationMode::Full);
+
+	let info = service.snapshot_info();
+	assert_eq!(info.restoration, RestorationStatus::Ongoing {
+		state_chunks_done: next.state_hashes.len(),
+		block_chunks_done: 0,
+		state_chunks: next.state_hashes.len(),
+		block_chunks: 2,
+	});
+	assert!(info.restoration.is_ongoing());
+	assert!(info.elapsed.is_some());
+	assert!(info.eta.is_some());
+	assert_eq!(info.block_number, Some(manifest.block_number));
+
+	let fixture_size = fs::read_dir(&fixture).unwrap()
+		.map(|entry| entry.unwrap().metadata().unwrap().len())
+		.fold(0, |total, len| total + len);
+	assert_eq!(info.size, Some(fixture_size));
+}
+
+#[test]
+fn restoration_completes_with_small_db_cache() {
+	let root = RandomTempPath::create_dir();
+	let (fixture, manifest) = make_fixture(&root);
+	let reader = LooseReader::new(fixture).unwrap();
+
+	let spec = Spec::new_null();
+	let mut client_db = root.as_path().to_owned();
+	client_db.push("pruning");
+	client_db.push("db");
+	let service = Service::with_restoration_db_factory(
+		&spec,
+		Algorithm::Archive,
+		client_db,
+		IoChannel::disconnected(),
+		restoration_db_factory(Some(1)),
+	).unwrap();
+
+	restore_from(&service, &reader, &manifest, RestorationMode::StateOnly);
+	assert_eq!(service.status(), RestorationStatus::Inactive);
+	assert_eq!(service.last_restoration_summary().unwrap().final_state_root, manifest.state_root);
+}
+
+// restoration database factory keeping the database in memory.
+fn in_memory_db(_path: &Path) -> Result<Arc<KeyValueDB>, Error> {
+	Ok(Arc::new(kvdb::in_memory(::db::NUM_COLUMNS.unwrap_or(0))) as Arc<KeyValueDB>)
+}
+
+#[test]
+fn in_memory_restoration_is_not_finalized() {
+	let root = RandomTempPath::create_dir();
+	let (fixture, manifest) = make_fixture(&root);
+	let reader = LooseReader::new(fixture).unwrap();
+
+	let spec = Spec::new_null();
+	let mut client_db = root.as_path().to_owned();
+	client_db.push("pruning");
+	client_db.push("db");
+	let service = Service::with_restoration_db_factory(
+		&spec,
+		Algorithm::Archive,
+		client_db.clone(),
+		IoChannel::disconnected(),
+		Box::new(in_memory_db),
+	).unwrap();
+
+	// the chunks are all restored, but the database can't be swapped in for the client's one.
+	restore_from(&service, &reader, &manifest, RestorationMode::StateOnly);
+	assert_eq!(service.status(), RestorationStatus::Failed);
+	assert!(service.last_restoration_failure().unwrap().contains("not on disk"));
+	assert!(!client_db.exists());
+	assert!(service.manifest().is_none());
+}
+
+#[test]
+fn corrupt_snapshot_is_distinguished_from_missing() {
+	let root = RandomTempPath::create_dir();
+
+	let service = make_service(&root);
+	assert!(service.manifest().is_none());
+	assert!(service.snapshot_load_failure().is_none());
+
+	let mut current = root.as_path().to_owned();
+	current.push("snapshot");
+	current.push("current");
+	fs::create_dir_all(&current).unwrap();
+	File::create(current.join("MANIFEST")).unwrap().write_all(b"not a manifest").unwrap();
+
+	let service = make_service(&root);
+	assert!(service.manifest().is_none());
+	assert!(service.snapshot_load_failure().is_some());
+}
+
+#[test]
+fn signed_manifest_verifies() {
+	use ethkey::{Generator, Random};
+
+	let root = RandomTempPath::create_dir();
+	let node = Random.generate().unwrap();
+	let other = Random.generate().unwrap();
+	assert!(make_service(&root).signed_manifest(node.secret()).is_none());
+
+	let (service, manifest) = restore_fixture(&root, RestorationMode::StateOnly);
+	let (signed, signature) = service.signed_manifest(node.secret()).unwrap();
+	assert_eq!(signed, manifest);
+	assert!(signed.verify_signature(node.public(), &signature));
+	assert!(!signed.verify_signature(other.public(), &signature));
+
+	let mut tampered = signed.clone();
+	tampered.block_number += 1;
+	assert!(!tampered.verify_signature(node.public(), &signature));
+}
+
+#[test]
+fn duplicate_restoration_request_keeps_progress() {
+	let root = RandomTempPath::create_dir();
+	let (fixture, mut manifest) = make_fixture(&root);
+	let reader = LooseReader::new(fixture).unwrap();
+
+	// block chunks which will never be fed.
+	manifest.block_hashes = vec![H256::random(), H256::random()];
+
+	let service = make_service(&root);
+	restore_from(&service, &reader, &manifest, RestorationMode::Full);
+	let progress = service.status();
+
+	// the same manifest again.
+	service.begin_restore(manifest.clone());
+	service.init_restore(manifest.clone(), RestorationMode::Full).unwrap();
+	assert_eq!(service.status(), progress);
+	assert_eq!(service.chunks_done(), (manifest.state_hashes.len(), 0));
+
+	// a different one is rejected until the ongoing one is aborted.
+	let mut other = manifest.clone();
+	other.state_root = H256::random();
+	assert!(service.init_restore(other.clone(), RestorationMode::Full).is_err());
+	assert_eq!(service.restoring_manifest(), Some(manifest));
+
+	service.abort_restore();
+	service.init_restore(other.clone(), RestorationMode::Full).unwrap();
+	assert_eq!(service.restoring_manifest(), Some(other));
+	assert_eq!(service.chunks_done(), (0, 0));
+}
+
+#[test]
+fn cancelled_snapshot_keeps_previous_one() {
+	let root = RandomTempPath::create_dir();
+	let client = generate_dummy_client_with_data(200, 0, &[]);
+	let client = client.reference().clone();
+
+	let service = Arc::new(make_service(&root));
+	service.take_snapshot(&*client, 10).unwrap();
+	let previous = service.manifest().unwrap();
+	assert!(!service.cancel_snapshot());
+
+	let taker = {
+		let service = service.clone();
+		let client = client.clone();
+		thread::spawn(move || service.take_snapshot(&*client, 200))
+	};
+
+	while !service.taking_snapshot() {
+		thread::yield_now();
+	}
+	assert!(service.cancel_snapshot());
+	assert!(taker.join().unwrap().is_err());
+
+	// no partial snapshot is left behind, and the previous one is still served.
+	let mut in_progress = root.as_path().to_owned();
+	in_progress.push("snapshot");
+	in_progress.push("in_progress");
+	assert!(!in_progress.exists());
+	assert_eq!(service.manifest().unwrap(), previous);
+	assert!(previous.state_hashes.iter().all(|hash| service.chunk(*hash).is_some()));
+}
+
+#[test]
+fn needed_chunks_shrink_as_chunks_are_fed() {
+	let root = RandomTempPath::create_dir();
+	let (fixture, mut manifest) = make_fixture(&root);
+	let reader = LooseReader::new(fixture).unwrap();
+	let hashes = manifest.state_hashes.clone();
+
+	// an extra chunk which is never fed keeps the restoration ongoing.
+	let missing = H256::random();
+	manifest.state_hashes.push(missing);
+	manifest.block_hashes.push(H256::random());
+
+	let service = make_service(&root);
+	assert_eq!(service.needed_chunks(), (vec![], vec![]));
+	service.init_restore(manifest.clone(), RestorationMode::Full).unwrap();
+
+	let (state, blocks) = service.needed_chunks();
+	assert_eq!(state.len(), hashes.len() + 1);
+	assert_eq!(blocks, manifest.block_hashes);
+
+	for (i, hash) in hashes.iter().enumerate() {
+		service.feed_state_chunk(*hash, &reader.chunk(*hash).unwrap());
+		let (state, _) = service.needed_chunks();
+		assert_eq!(state.len(), hashes.len() - i);
+		assert!(!state.contains(hash));
+	}
+
+	assert_eq!(service.needed_chunks(), (vec![missing], manifest.block_hashes.clone()));
+
+	service.abort_restore();
+	assert_eq!(service.needed_chunks(), (vec![], vec![]));
+}
+
+#[test]
+fn chunk_cache_size_is_configurable() {
+	let root = RandomTempPath::create_dir();
+	let (service, manifest) = serve_fixture(&root);
+	let hash = manifest.state_hashes[0];
+	let path = root.as_path().join("snapshot").join("current").join(hash.hex());
+
+	// served from memory once read, even if the file goes away.
+	let chunk = service.chunk(hash).unwrap();
+	fs::remove_file(&path).unwrap();
+	assert_eq!(service.chunk(hash), Some(chunk.clone()));
+
+	// without a cache every request goes to disk.
+	service.set_chunk_cache_size(0).unwrap();
+	assert!(service.chunk(hash).is_none());
+
+	File::create(&path).unwrap().write_all(&chunk).unwrap();
+	assert_eq!(service.chunk(hash), Some(chunk));
+	fs::remove_file(&path).unwrap();
+	assert!(service.chunk(hash).is_none());
+}
+
+#[test]
+fn verify_chunks_withholds_corrupted_chunks() {
+	let root = RandomTempPath::create_dir();
+	let (service, manifest) = serve_fixture(&root);
+	let hash = manifest.state_hashes[0];
+	let path = root.as_path().join("snapshot").join("current").join(hash.hex());
+
+	service.set_chunk_cache_size(0).unwrap();
+	let chunk = service.chunk(hash).unwrap();
+	File::create(&path).unwrap().write_all(b"corrupted").unwrap();
+	assert_eq!(service.chunk(hash), Some(b"corrupted".to_vec()));
+
+	service.set_verify_chunks(true).unwrap();
+	assert!(service.chunk(hash).is_none());
+
+	File::create(&path).unwrap().write_all(&chunk).unwrap();
+	assert_eq!(service.chunk(hash), Some(chunk));
+}
@@ -18,12 +18,12 @@
 
 use snapshot::{chunk_state, Progress, StateRebuilder};
 use snapshot::io::{PackedReader, PackedWriter, SnapshotReader, SnapshotWriter};
-use super::helpers::{compare_dbs, StateProducer};
+use super::helpers::{compare_dbs, MemoryWriter, StateProducer};
 
 use rand;
-use util::hash::H256;
+use util::hash::{FixedHash, H256};
 use util::journaldb::{self, Algorithm};
-use util::kvdb::{Database, DatabaseConfig};
+use util::kvdb::{self, Database, DatabaseConfig, KeyValueDB};
 use util::memorydb::MemoryDB;
 use util::Mutex;
 use devtools::RandomTempPath;
@@ -82,3 +82,56 @@ fn snap_and_restore() {
 
 	compare_dbs(&old_db, new_db.as_hashdb());
 }
+
+#[test]
+fn snap_and_restore_in_memory() {
+	let mut producer = StateProducer::new();
+	let mut rng = rand::thread_rng();
+	let mut old_db = MemoryDB::new();
+
+	for _ in 0..150 {
+		producer.tick(&mut rng, &mut old_db);
+	}
+
+	let state_root = producer.state_root();
+	let snapshot = Arc::new(Mutex::new(None));
+	let writer = Mutex::new(MemoryWriter::new(snapshot.clone()));
+
+	let state_hashes = chunk_state(&old_db, &state_root, &writer, &Progress::default()).unwrap();
+
+	// nothing is readable until the manifest is written.
+	assert!(snapshot.lock().is_none());
+
+	writer.into_inner().finish(::snapshot::ManifestData {
+		state_hashes: state_hashes.clone(),
+		block_hashes: Vec::new(),
+		state_root: state_root,
+		block_number: 0,
+		block_hash: H256::default(),
+	}).unwrap();
+
+	let reader = snapshot.lock().take().unwrap();
+	assert_eq!(reader.manifest().state_hashes, state_hashes);
+	assert!(reader.chunk(H256::random()).is_err());
+
+	let db = {
+		let new_db: Arc<KeyValueDB> = Arc::new(kvdb::in_memory(::db::NUM_COLUMNS.unwrap_or(0)));
+		let mut rebuilder = StateRebuilder::new(new_db.clone(), Algorithm::Archive);
+
+		for chunk_hash in &reader.manifest().state_hashes {
+			let raw = reader.chunk(*chunk_hash).unwrap();
+			let chunk = ::util::snappy::decompress(&raw).unwrap();
+
+			rebuilder.feed(&chunk).unwrap();
+		}
+
+		assert_eq!(rebuilder.state_root(), state_root);
+		rebuilder.check_missing().unwrap();
+
+		new_db
+	};
+
+	let new_db = journaldb::new(db, Algorithm::Archive, ::db::COL_STATE);
+
+	compare_dbs(&old_db, new_db.as_hashdb());
+}
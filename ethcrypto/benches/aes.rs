@@ -0,0 +1,52 @@
+// Copyright 2015, 2016 Ethcore (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! benchmarking for AES-CTR backends
+//! should be started with:
+//! ```bash
+//! multirust run nightly cargo bench --features aesni
+//! ```
+//! Without the `aesni` feature (or on CPUs without AES-NI) both benchmarks
+//! use the safe backend.
+
+#![feature(test)]
+
+extern crate test;
+extern crate ethcrypto;
+
+use test::Bencher;
+use ethcrypto::aes::{self, Backend};
+
+const LEN: usize = 64 * 1024;
+
+fn bench_backend(b: &mut Bencher, backend: Backend) {
+	let key = [1u8; 16];
+	let iv = [2u8; 16];
+	let plain = vec![3u8; LEN];
+	let mut dest = vec![0u8; LEN];
+	b.bytes = LEN as u64;
+	b.iter(|| aes::encrypt_with_backend(backend, &key, &iv, &plain, &mut dest));
+}
+
+#[bench]
+fn bench_aes_ctr_safe_64k(b: &mut Bencher) {
+	bench_backend(b, Backend::Safe);
+}
+
+#[bench]
+fn bench_aes_ctr_aesni_64k(b: &mut Bencher) {
+	bench_backend(b, Backend::AesNi);
+}
@@ -0,0 +1,55 @@
+// Copyright 2015, 2016 Ethcore (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! benchmarking for batch scrypt key derivation
+//! should be started with:
+//! ```bash
+//! multirust run nightly cargo bench
+//! ```
+
+#![feature(test)]
+
+extern crate test;
+extern crate ethcrypto;
+
+use test::Bencher;
+use ethcrypto::{derive_key_scrypt, derive_keys_scrypt_batch};
+
+const N: u32 = 16384;
+const P: u32 = 1;
+const R: u32 = 8;
+
+fn inputs() -> Vec<(String, [u8; 32])> {
+	(0..8u8).map(|i| (format!("password{}", i), [i; 32])).collect()
+}
+
+#[bench]
+fn bench_scrypt_serial_8(b: &mut Bencher) {
+	let inputs = inputs();
+	b.iter(|| {
+		for &(ref password, ref salt) in &inputs {
+			derive_key_scrypt(password, salt, N, P, R).unwrap();
+		}
+	});
+}
+
+#[bench]
+fn bench_scrypt_batch_8(b: &mut Bencher) {
+	let inputs = inputs();
+	b.iter(|| {
+		derive_keys_scrypt_batch(&inputs, N, P, R);
+	});
+}
@@ -0,0 +1,236 @@
+// Copyright 2015, 2016 Ethcore (UK) Ltd.
+// This file is part of Parity.
+
+// Parity is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Parity is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU General Public License for more details.
+
+// You should have received a copy of the GNU General Public License
+// along with Parity.  If not, see <http://www.gnu.org/licenses/>.
+
+//! C API for ECIES encryption. Enabled with the `capi` feature.
+//!
+//! All memory is owned by the caller: input buffers are only read during the call,
+//! and output is written to a caller-allocated buffer. Output length follows a query
+//! convention: `out_len` must point to the capacity of `out`; on return it holds the
+//! number of bytes required. Passing a null `out` only queries the required length.
+//! Empty inputs may be passed as a null pointer with zero length.
+
+use std::{ptr, slice};
+use std::os::raw::c_int;
+use bigint::hash::FixedHash;
+use ethkey::{Public, Secret};
+use {ecies, Error};
+
+/// Call succeeded.
+pub const ETHCRYPTO_OK: c_int = 0;
+/// A required pointer was null.
+pub const ETHCRYPTO_ERR_NULL_POINTER: c_int = 1;
+/// A key had the wrong length.
+pub const ETHCRYPTO_ERR_INVALID_KEY_LENGTH: c_int = 2;
+/// Output buffer is too small; the required length was written to `out_len`.
+pub const ETHCRYPTO_ERR_BUFFER_TOO_SMALL: c_int = 3;
+/// Maps to `Error::Secp`.
+pub const ETHCRYPTO_ERR_SECP: c_int = 4;
+/// Maps to `Error::InvalidMessage`.
+pub const ETHCRYPTO_ERR_INVALID_MESSAGE: c_int = 5;
+/// Maps to `Error::Random`.
+pub const ETHCRYPTO_ERR_RANDOM: c_int = 6;
+/// Maps to `Error::InvalidSaltLength`.
+pub const ETHCRYPTO_ERR_INVALID_SALT_LENGTH: c_int = 7;
+/// Maps to `Error::SelfTest`.
+pub const ETHCRYPTO_ERR_SELF_TEST: c_int = 8;
+/// Maps to `Error::MessageTooLarge`.
+pub const ETHCRYPTO_ERR_MESSAGE_TOO_LARGE: c_int = 9;
+
+/// Bytes added to the plain text by `ecies::encrypt`.
+const ECIES_OVERHEAD: usize = 1 + 64 + 16 + 32;
+
+fn error_code(err: Error) -> c_int {
+	match err {
+		Error::Secp(_) => ETHCRYPTO_ERR_SECP,
+		Error::InvalidMessage => ETHCRYPTO_ERR_INVALID_MESSAGE,
+		Error::InvalidKeyLength => ETHCRYPTO_ERR_INVALID_KEY_LENGTH,
+		Error::InvalidSaltLength => ETHCRYPTO_ERR_INVALID_SALT_LENGTH,
+		Error::Random => ETHCRYPTO_ERR_RANDOM,
+		Error::SelfTest(_) => ETHCRYPTO_ERR_SELF_TEST,
+		Error::MessageTooLarge => ETHCRYPTO_ERR_MESSAGE_TOO_LARGE,
+	}
+}
+
+// view a caller-provided buffer as a slice. null is accepted only for empty buffers.
+unsafe fn input<'a>(data: *const u8, len: usize) -> Option<&'a [u8]> {
+	if len == 0 {
+		Some(&[])
+	} else if data.is_null() {
+		None
+	} else {
+		Some(slice::from_raw_parts(data, len))
+	}
+}
+
+// check the caller's buffer can hold `required` bytes, following the length query convention.
+// returns `None` if the call may proceed.
+unsafe fn check_output(required: usize, out: *mut u8, out_len: *mut usize) -> Option<c_int> {
+	let capacity = *out_len;
+	*out_len = required;
+
+	if out.is_null() {
+		Some(ETHCRYPTO_OK)
+	} else if capacity < required {
+		Some(ETHCRYPTO_ERR_BUFFER_TOO_SMALL)
+	} else {
+		None
+	}
+}
+
+// copy the result into the caller's buffer, checked to be large enough.
+unsafe fn output(result: &[u8], out: *mut u8, out_len: *mut usize) -> c_int {
+	ptr::copy_nonoverlapping(result.as_ptr(), out, result.len());
+	*out_len = result.len();
+	ETHCRYPTO_OK
+}
+
+/// Encrypt `plain` for the 64-byte uncompressed `public` key, see `ecies::encrypt`.
+/// The required output length is `plain_len + 113`.
+#[no_mangle]
+pub unsafe extern "C" fn ethcrypto_ecies_encrypt(
+	public: *const u8,
+	public_len: usize,
+	shared_mac: *const u8,
+	shared_mac_len: usize,
+	plain: *const u8,
+	plain_len: usize,
+	out: *mut u8,
+	out_len: *mut usize,
+) -> c_int {
+	if public.is_null() || out_len.is_null() {
+		return ETHCRYPTO_ERR_NULL_POINTER;
+	}
+
+	if public_len != 64 {
+		return ETHCRYPTO_ERR_INVALID_KEY_LENGTH;
+	}
+
+	let (shared_mac, plain) = match (input(shared_mac, shared_mac_len), input(plain, plain_len)) {
+		(Some(shared_mac), Some(plain)) => (shared_mac, plain),
+		_ => return ETHCRYPTO_ERR_NULL_POINTER,
+	};
+
+	if let Some(code) = check_output(plain.len() + ECIES_OVERHEAD, out, out_len) {
+		return code;
+	}
+
+	let public = Public::from_slice(slice::from_raw_parts(public, public_len));
+	match ecies::encrypt(&public, shared_mac, plain) {
+		Ok(encrypted) => output(&encrypted, out, out_len),
+		Err(e) => error_code(e),
+	}
+}
+
+/// Decrypt a message produced by `ethcrypto_ecies_encrypt` with the 32-byte `secret` key,
+/// see `ecies::decrypt`. The required output length is `encrypted_len - 113`.
+#[no_mangle]
+pub unsafe extern "C" fn ethcrypto_ecies_decrypt(
+	secret: *const u8,
+	secret_len: usize,
+	shared_mac: *const u8,
+	shared_mac_len: usize,
+	encrypted: *const u8,
+	encrypted_len: usize,
+	out: *mut u8,
+	out_len: *mut usize,
+) -> c_int {
+	if secret.is_null() || out_len.is_null() {
+		return ETHCRYPTO_ERR_NULL_POINTER;
+	}
+
+	if secret_len != 32 {
+		return ETHCRYPTO_ERR_INVALID_KEY_LENGTH;
+	}
+
+	let (shared_mac, encrypted) = match (input(shared_mac, shared_mac_len), input(encrypted, encrypted_len)) {
+		(Some(shared_mac), Some(encrypted)) => (shared_mac, encrypted),
+		_ => return ETHCRYPTO_ERR_NULL_POINTER,
+	};
+
+	if encrypted.len() < ECIES_OVERHEAD {
+		return ETHCRYPTO_ERR_INVALID_MESSAGE;
+	}
+
+	if let Some(code) = check_output(encrypted.len() - ECIES_OVERHEAD, out, out_len) {
+		return code;
+	}
+
+	let secret = Secret::from_slice(slice::from_raw_parts(secret, secret_len));
+	match ecies::decrypt(&secret, shared_mac, encrypted) {
+		Ok(plain) => output(&plain, out, out_len),
+		Err(e) => error_code(e),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use std::ptr;
+	use ethkey::{Random, Generator};
+	use super::*;
+
+	#[test]
+	fn capi_round_trip() {
+		let kp = Random.generate().unwrap();
+		let message = b"So many books, so little time";
+		let shared = b"shared";
+
+		unsafe {
+			// query the length first.
+			let mut len = 0;
+			assert_eq!(ethcrypto_ecies_encrypt(kp.public().as_ptr(), 64, shared.as_ptr(), shared.len(), message.as_ptr(), message.len(), ptr::null_mut(), &mut len), ETHCRYPTO_OK);
+			assert_eq!(len, message.len() + 113);
+
+			let mut encrypted = vec![0u8; len];
+			assert_eq!(ethcrypto_ecies_encrypt(kp.public().as_ptr(), 64, shared.as_ptr(), shared.len(), message.as_ptr(), message.len(), encrypted.as_mut_ptr(), &mut len), ETHCRYPTO_OK);
+			assert_eq!(len, encrypted.len());
+
+			let mut len = 0;
+			assert_eq!(ethcrypto_ecies_decrypt(kp.secret().as_ptr(), 32, shared.as_ptr(), shared.len(), encrypted.as_ptr(), encrypted.len(), ptr::null_mut(), &mut len), ETHCRYPTO_OK);
+			let mut decrypted = vec![0u8; len];
+			assert_eq!(ethcrypto_ecies_decrypt(kp.secret().as_ptr(), 32, shared.as_ptr(), shared.len(), encrypted.as_ptr(), encrypted.len(), decrypted.as_mut_ptr(), &mut len), ETHCRYPTO_OK);
+			assert_eq!(&decrypted[..len], &message[..]);
+		}
+	}
+
+	#[test]
+	fn capi_reports_errors() {
+		let kp = Random.generate().unwrap();
+		let message = b"So many books, so little time";
+
+		unsafe {
+			let mut len = 0;
+			assert_eq!(ethcrypto_ecies_encrypt(ptr::null(), 64, ptr::null(), 0, message.as_ptr(), message.len(), ptr::null_mut(), &mut len), ETHCRYPTO_ERR_NULL_POINTER);
+			assert_eq!(ethcrypto_ecies_encrypt(kp.public().as_ptr(), 32, ptr::null(), 0, message.as_ptr(), message.len(), ptr::null_mut(), &mut len), ETHCRYPTO_ERR_INVALID_KEY_LENGTH);
+			assert_eq!(ethcrypto_ecies_encrypt(kp.public().as_ptr(), 64, ptr::null(), 0, ptr::null(), message.len(), ptr::null_mut(), &mut len), ETHCRYPTO_ERR_NULL_POINTER);
+
+			// buffer too small reports the required length.
+			let mut small = [0u8; 16];
+			let mut len = small.len();
+			assert_eq!(ethcrypto_ecies_encrypt(kp.public().as_ptr(), 64, ptr::null(), 0, message.as_ptr(), message.len(), small.as_mut_ptr(), &mut len), ETHCRYPTO_ERR_BUFFER_TOO_SMALL);
+			assert_eq!(len, message.len() + 113);
+
+			let mut encrypted = vec![0u8; len];
+			assert_eq!(ethcrypto_ecies_encrypt(kp.public().as_ptr(), 64, ptr::null(), 0, message.as_ptr(), message.len(), encrypted.as_mut_ptr(), &mut len), ETHCRYPTO_OK);
+
+			// tampered messages and truncated input are rejected.
+			encrypted[1 + 64 + 16] ^= 1;
+			let mut out = vec![0u8; message.len()];
+			let mut len = out.len();
+			assert_eq!(ethcrypto_ecies_decrypt(kp.secret().as_ptr(), 32, ptr::null(), 0, encrypted.as_ptr(), encrypted.len(), out.as_mut_ptr(), &mut len), ETHCRYPTO_ERR_INVALID_MESSAGE);
+			assert_eq!(ethcrypto_ecies_decrypt(kp.secret().as_ptr(), 32, ptr::null(), 0, encrypted.as_ptr(), 10, out.as_mut_ptr(), &mut len), ETHCRYPTO_ERR_INVALID_MESSAGE);
+		}
+	}
+}
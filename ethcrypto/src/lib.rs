@@ -16,29 +16,55 @@
 
 //! Crypto utils used ethstore and network.
 
-extern crate bigint;
 extern crate tiny_keccak;
 extern crate crypto as rcrypto;
+extern crate num_cpus;
+extern crate crossbeam;
+#[cfg(feature = "ecc")]
+extern crate bigint;
+#[cfg(feature = "ecc")]
+extern crate rand;
+#[cfg(feature = "ecc")]
 extern crate secp256k1;
+#[cfg(feature = "ecc")]
 extern crate ethkey;
 
+use std::cmp;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use tiny_keccak::Keccak;
 use rcrypto::pbkdf2::pbkdf2;
 use rcrypto::scrypt::{scrypt, ScryptParams};
-use rcrypto::sha2::Sha256;
+use rcrypto::sha2::{Sha256, Sha512};
 use rcrypto::hmac::Hmac;
+#[cfg(feature = "ecc")]
 use secp256k1::Error as SecpError;
 
 pub const KEY_LENGTH: usize = 32;
 pub const KEY_ITERATIONS: usize = 10240;
 pub const KEY_LENGTH_AES: usize = KEY_LENGTH / 2;
+/// Shortest salt accepted by the key derivation functions.
+pub const MIN_SALT_LENGTH: usize = 16;
+/// Upper bound on the memory used by scrypt derivations running at once in a batch.
+pub const SCRYPT_BATCH_MEMORY_LIMIT: usize = 512 * 1024 * 1024;
 
 #[derive(PartialEq, Debug)]
 pub enum Error {
+	#[cfg(feature = "ecc")]
 	Secp(SecpError),
 	InvalidMessage,
+	InvalidKeyLength,
+	InvalidSaltLength,
+	Random,
+	/// Message is larger than the maximum accepted size.
+	MessageTooLarge,
+	/// A known-answer test of the named primitive failed.
+	SelfTest(&'static str),
 }
 
+#[cfg(feature = "ecc")]
 impl From<SecpError> for Error {
 	fn from(e: SecpError) -> Self {
 		Error::Secp(e)
@@ -59,50 +85,309 @@ impl Keccak256<[u8; 32]> for [u8] {
 	}
 }
 
-pub fn derive_key_iterations(password: &str, salt: &[u8; 32], c: u32) -> (Vec<u8>, Vec<u8>) {
-	let mut h_mac = Hmac::new(Sha256::new(), password.as_bytes());
+/// Derive an account address from its public key: the last 20 bytes of
+/// the Keccak-256 hash of the 64-byte public key.
+#[cfg(feature = "ecc")]
+pub fn public_to_address(public: &ethkey::Public) -> [u8; 20] {
+	let hash: [u8; 32] = public[..].keccak256();
+	let mut address = [0u8; 20];
+	address.copy_from_slice(&hash[12..]);
+	address
+}
+
+/// Compute the keccak256 hash of a file's contents, reading it in fixed-size blocks
+/// rather than loading it into memory all at once.
+pub fn keccak256_file(path: &Path) -> io::Result<[u8; 32]> {
+	let mut file = try!(File::open(path));
+	let mut keccak = Keccak::new_keccak256();
+	let mut buffer = [0u8; 8192];
+
+	loop {
+		match file.read(&mut buffer) {
+			Ok(0) => break,
+			Ok(n) => keccak.update(&buffer[..n]),
+			Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+			Err(e) => return Err(e),
+		}
+	}
+
+	let mut result = [0u8; 32];
+	keccak.finalize(&mut result);
+	Ok(result)
+}
+
+/// Pseudo-random function used by PBKDF2.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Prf {
+	HmacSha256,
+	HmacSha512,
+}
+
+impl Default for Prf {
+	fn default() -> Self {
+		Prf::HmacSha256
+	}
+}
+
+/// Derive a key with PBKDF2-HMAC-SHA256.
+/// Fails if the salt is shorter than `MIN_SALT_LENGTH`.
+pub fn derive_key_iterations(password: &str, salt: &[u8], c: u32) -> Result<(Vec<u8>, Vec<u8>), Error> {
+	derive_key_iterations_with_prf(password, salt, c, Prf::HmacSha256)
+}
+
+/// Derive a key with PBKDF2 using given pseudo-random function.
+/// Only the first `KEY_LENGTH` bytes are derived, whatever the PRF's output size.
+/// Fails if the salt is shorter than `MIN_SALT_LENGTH`.
+pub fn derive_key_iterations_with_prf(password: &str, salt: &[u8], c: u32, prf: Prf) -> Result<(Vec<u8>, Vec<u8>), Error> {
+	try!(check_salt(salt));
 	let mut derived_key = vec![0u8; KEY_LENGTH];
-	pbkdf2(&mut h_mac, salt, c, &mut derived_key);
+	match prf {
+		Prf::HmacSha256 => pbkdf2(&mut Hmac::new(Sha256::new(), password.as_bytes()), salt, c, &mut derived_key),
+		Prf::HmacSha512 => pbkdf2(&mut Hmac::new(Sha512::new(), password.as_bytes()), salt, c, &mut derived_key),
+	}
 	let derived_right_bits = &derived_key[0..KEY_LENGTH_AES];
 	let derived_left_bits = &derived_key[KEY_LENGTH_AES..KEY_LENGTH];
-	(derived_right_bits.to_vec(), derived_left_bits.to_vec())
+	Ok((derived_right_bits.to_vec(), derived_left_bits.to_vec()))
 }
 
-pub fn derive_key_scrypt(password: &str, salt: &[u8; 32], n: u32, p: u32, r: u32) -> (Vec<u8>, Vec<u8>) {
+/// Derive a key with scrypt.
+/// Fails if the salt is shorter than `MIN_SALT_LENGTH`.
+pub fn derive_key_scrypt(password: &str, salt: &[u8], n: u32, p: u32, r: u32) -> Result<(Vec<u8>, Vec<u8>), Error> {
+	try!(check_salt(salt));
 	let mut derived_key = vec![0u8; KEY_LENGTH];
 	let scrypt_params = ScryptParams::new(n.trailing_zeros() as u8, r, p);
 	scrypt(password.as_bytes(), salt, &scrypt_params, &mut derived_key);
 	let derived_right_bits = &derived_key[0..KEY_LENGTH_AES];
 	let derived_left_bits = &derived_key[KEY_LENGTH_AES..KEY_LENGTH];
-	(derived_right_bits.to_vec(), derived_left_bits.to_vec())
+	Ok((derived_right_bits.to_vec(), derived_left_bits.to_vec()))
+}
+
+fn check_salt(salt: &[u8]) -> Result<(), Error> {
+	match salt.len() < MIN_SALT_LENGTH {
+		true => Err(Error::InvalidSaltLength),
+		false => Ok(()),
+	}
 }
 
-pub fn derive_mac(derived_left_bits: &[u8], cipher_text: &[u8]) -> Vec<u8> {
-	let mut mac = vec![0u8; KEY_LENGTH_AES + cipher_text.len()];
+/// Concatenate the derived key bits and the cipher text, as hashed to get the keystore MAC.
+/// Fails if the key bits aren't `KEY_LENGTH_AES` bytes long.
+pub fn derive_mac(derived_left_bits: &[u8], cipher_text: &[u8]) -> Result<Vec<u8>, Error> {
+	derive_mac_with_aad(derived_left_bits, cipher_text, &[])
+}
+
+/// Like `derive_mac`, but also binds associated data (e.g. a version tag) into the MAC input.
+///
+/// The returned buffer is laid out as `derived_left_bits (16 bytes) || cipher_text || aad`,
+/// with no length prefixes or separators; the MAC is its keccak256 hash.
+/// With empty `aad` the result is identical to `derive_mac`.
+pub fn derive_mac_with_aad(derived_left_bits: &[u8], cipher_text: &[u8], aad: &[u8]) -> Result<Vec<u8>, Error> {
+	if derived_left_bits.len() != KEY_LENGTH_AES {
+		return Err(Error::InvalidKeyLength);
+	}
+
+	let mut mac = vec![0u8; KEY_LENGTH_AES + cipher_text.len() + aad.len()];
 	mac[0..KEY_LENGTH_AES].copy_from_slice(derived_left_bits);
 	mac[KEY_LENGTH_AES..cipher_text.len() + KEY_LENGTH_AES].copy_from_slice(cipher_text);
-	mac
+	mac[KEY_LENGTH_AES + cipher_text.len()..].copy_from_slice(aad);
+	Ok(mac)
+}
+
+/// Approximate number of bytes of memory used by a single scrypt derivation.
+pub fn scrypt_memory(n: u32, p: u32, r: u32) -> usize {
+	128 * r as usize * (n as usize + p as usize)
 }
 
+/// Derive keys for many `(password, salt)` pairs at once, running the derivations
+/// in parallel. Concurrency is limited by the number of CPUs and by
+/// `SCRYPT_BATCH_MEMORY_LIMIT`, so expensive parameters fall back to fewer threads.
+/// Results are in the order of inputs.
+pub fn derive_keys_scrypt_batch(inputs: &[(String, [u8; 32])], n: u32, p: u32, r: u32) -> Vec<(Vec<u8>, Vec<u8>)> {
+	let per_derivation = cmp::max(scrypt_memory(n, p, r), 1);
+	let threads = cmp::min(num_cpus::get(), SCRYPT_BATCH_MEMORY_LIMIT / per_derivation);
+	let threads = cmp::max(cmp::min(threads, inputs.len()), 1);
+
+	parallel_map(inputs, threads, |&(ref password, ref salt)| derive_key_scrypt_32(password, salt, n, p, r))
+}
+
+// apply `f` to every item, spreading the work over at most `threads` threads
+// which borrow the items. Results are in the order of items.
+fn parallel_map<T, R, F>(items: &[T], threads: usize, f: F) -> Vec<R>
+	where T: Sync, R: Send, F: Fn(&T) -> R + Sync
+{
+	let threads = cmp::max(cmp::min(threads, items.len()), 1);
+	if threads == 1 {
+		return items.iter().map(f).collect();
+	}
+
+	let next = AtomicUsize::new(0);
+	let mut results: Vec<Option<R>> = (0..items.len()).map(|_| None).collect();
+	crossbeam::scope(|scope| {
+		let workers: Vec<_> = (0..threads).map(|_| scope.spawn(|| {
+			let mut done = Vec::new();
+			loop {
+				let i = next.fetch_add(1, Ordering::SeqCst);
+				if i >= items.len() {
+					return done;
+				}
+
+				done.push((i, f(&items[i])));
+			}
+		})).collect();
+
+		for worker in workers {
+			for (i, result) in worker.join() {
+				results[i] = Some(result);
+			}
+		}
+	});
+
+	results.into_iter().map(|r| r.expect("every item is taken by a worker; qed")).collect()
+}
+
+// derive with a salt of fixed length, which can't be too short.
+fn derive_key_scrypt_32(password: &str, salt: &[u8; 32], n: u32, p: u32, r: u32) -> (Vec<u8>, Vec<u8>) {
+	derive_key_scrypt(password, salt, n, p, r).expect("32-byte salt is longer than MIN_SALT_LENGTH; qed")
+}
+
+#[cfg(feature = "capi")]
+pub mod capi;
+
 /// AES encryption
 pub mod aes {
-	use rcrypto::blockmodes::{CtrMode, CbcDecryptor, PkcsPadding};
+	use super::Error;
+	use rcrypto::blockmodes::{CtrMode, CbcDecryptor, NoPadding, PkcsPadding};
 	use rcrypto::aessafe::{AesSafe128Encryptor, AesSafe128Decryptor};
-	use rcrypto::symmetriccipher::{Encryptor, Decryptor, SymmetricCipherError};
+	use rcrypto::symmetriccipher::{Decryptor, SymmetricCipherError, SynchronousStreamCipher};
 	use rcrypto::buffer::{RefReadBuffer, RefWriteBuffer, WriteBuffer};
 
+	/// Size of the blocks `decrypt_to_sink` passes to the sink.
+	pub const SINK_BLOCK_SIZE: usize = 4096;
+
+	/// Implementation of the AES block cipher used in CTR mode.
+	/// All backends produce identical output.
+	#[derive(Debug, PartialEq, Clone, Copy)]
+	pub enum Backend {
+		/// Constant-time software implementation, available everywhere.
+		Safe,
+		/// AES-NI instructions. Only available with the `aesni` feature, on x86 CPUs supporting them.
+		AesNi,
+	}
+
+	impl Backend {
+		/// Whether the backend can be used on this machine.
+		pub fn is_available(&self) -> bool {
+			match *self {
+				Backend::Safe => true,
+				Backend::AesNi => aesni_supported(),
+			}
+		}
+	}
+
+	/// Fastest backend available on this machine, used by `encrypt`, `decrypt` and `decrypt_to_sink`.
+	pub fn best_backend() -> Backend {
+		match Backend::AesNi.is_available() {
+			true => Backend::AesNi,
+			false => Backend::Safe,
+		}
+	}
+
+	// whether the CPU supports AES-NI, detected on first use only.
+	#[cfg(all(feature = "aesni", any(target_arch = "x86", target_arch = "x86_64")))]
+	fn aesni_supported() -> bool {
+		use std::sync::{Once, ONCE_INIT};
+		use std::sync::atomic::{AtomicBool, ATOMIC_BOOL_INIT, Ordering};
+		use rcrypto::util::supports_aesni;
+
+		static DETECT: Once = ONCE_INIT;
+		static SUPPORTED: AtomicBool = ATOMIC_BOOL_INIT;
+
+		DETECT.call_once(|| SUPPORTED.store(supports_aesni(), Ordering::SeqCst));
+		SUPPORTED.load(Ordering::SeqCst)
+	}
+
+	#[cfg(not(all(feature = "aesni", any(target_arch = "x86", target_arch = "x86_64"))))]
+	fn aesni_supported() -> bool {
+		false
+	}
+
+	#[cfg(all(feature = "aesni", any(target_arch = "x86", target_arch = "x86_64")))]
+	fn aesni_ctr(k: &[u8], iv: &[u8]) -> Option<Box<SynchronousStreamCipher>> {
+		use rcrypto::aes::KeySize;
+		use rcrypto::aesni::AesNiEncryptor;
+
+		match aesni_supported() {
+			true => Some(Box::new(CtrMode::new(AesNiEncryptor::new(KeySize::KeySize128, k), iv.to_vec()))),
+			false => None,
+		}
+	}
+
+	#[cfg(not(all(feature = "aesni", any(target_arch = "x86", target_arch = "x86_64"))))]
+	fn aesni_ctr(_k: &[u8], _iv: &[u8]) -> Option<Box<SynchronousStreamCipher>> {
+		None
+	}
+
+	// CTR mode cipher using given backend, falling back to the safe one if it's not available.
+	fn ctr(backend: Backend, k: &[u8], iv: &[u8]) -> Box<SynchronousStreamCipher> {
+		let accelerated = match backend {
+			Backend::AesNi => aesni_ctr(k, iv),
+			Backend::Safe => None,
+		};
+		accelerated.unwrap_or_else(|| Box::new(CtrMode::new(AesSafe128Encryptor::new(k), iv.to_vec())))
+	}
+
 	/// Encrypt a message
 	pub fn encrypt(k: &[u8], iv: &[u8], plain: &[u8], dest: &mut [u8]) {
-		let mut encryptor = CtrMode::new(AesSafe128Encryptor::new(k), iv.to_vec());
-		encryptor.encrypt(&mut RefReadBuffer::new(plain), &mut RefWriteBuffer::new(dest), true).expect("Invalid length or padding");
+		encrypt_with_backend(best_backend(), k, iv, plain, dest)
+	}
+
+	/// Encrypt a message using given backend, or the safe one if it's not available.
+	pub fn encrypt_with_backend(backend: Backend, k: &[u8], iv: &[u8], plain: &[u8], dest: &mut [u8]) {
+		ctr(backend, k, iv).process(plain, &mut dest[..plain.len()]);
 	}
 
 	/// Decrypt a message
 	pub fn decrypt(k: &[u8], iv: &[u8], encrypted: &[u8], dest: &mut [u8]) {
-		let mut encryptor = CtrMode::new(AesSafe128Encryptor::new(k), iv.to_vec());
-		encryptor.decrypt(&mut RefReadBuffer::new(encrypted), &mut RefWriteBuffer::new(dest), true).expect("Invalid length or padding");
+		decrypt_with_backend(best_backend(), k, iv, encrypted, dest)
 	}
 
+	/// Decrypt a message using given backend, or the safe one if it's not available.
+	pub fn decrypt_with_backend(backend: Backend, k: &[u8], iv: &[u8], encrypted: &[u8], dest: &mut [u8]) {
+		ctr(backend, k, iv).process(encrypted, &mut dest[..encrypted.len()]);
+	}
+
+	/// Derive the IV of the `counter`-th message encrypted in CTR mode under one key.
+	///
+	/// The counter is added to the upper 64 bits of `base_iv` (as a big-endian number),
+	/// so every message gets 2^64 blocks of key stream that never overlap with those of
+	/// another message. Counter 0 yields `base_iv` itself.
+	///
+	/// The counter must be strictly increasing for every message encrypted under the
+	/// same key and base IV; reusing a counter reuses the key stream.
+	pub fn ctr_iv_for_counter(base_iv: &[u8; 16], counter: u64) -> [u8; 16] {
+		let mut upper = 0u64;
+		for byte in &base_iv[0..8] {
+			upper = (upper << 8) | *byte as u64;
+		}
+		let upper = upper.wrapping_add(counter);
+
+		let mut iv = *base_iv;
+		for (i, byte) in iv[0..8].iter_mut().enumerate() {
+			*byte = (upper >> (56 - 8 * i)) as u8;
+		}
+		iv
+	}
+
+	/// Decrypt a message block by block, passing each decrypted block to `sink`.
+	/// Only a single block of plain text is held in memory at a time.
+	pub fn decrypt_to_sink<F: FnMut(&[u8])>(k: &[u8], iv: &[u8], encrypted: &[u8], mut sink: F) {
+		let mut decryptor = ctr(best_backend(), k, iv);
+		let mut block = [0u8; SINK_BLOCK_SIZE];
+		for chunk in encrypted.chunks(SINK_BLOCK_SIZE) {
+			let plain = &mut block[..chunk.len()];
+			decryptor.process(chunk, plain);
+			sink(plain);
+		}
+	}
 
 	/// Decrypt a message using cbc mode
 	pub fn decrypt_cbc(k: &[u8], iv: &[u8], encrypted: &[u8], dest: &mut [u8]) -> Result<usize, SymmetricCipherError> {
@@ -112,15 +397,89 @@ pub mod aes {
 		try!(encryptor.decrypt(&mut RefReadBuffer::new(encrypted), &mut buffer, true));
 		Ok(len - buffer.remaining())
 	}
+
+	/// Decrypt a message using cbc mode, checking the PKCS#7 padding in constant time.
+	///
+	/// Unlike `decrypt_cbc`, a bad padding is not distinguishable from any other failure:
+	/// all of them surface as `Error::InvalidMessage`, the same error returned on a MAC
+	/// mismatch. Callers should verify the MAC of `encrypted` before calling this.
+	/// On failure `dest` is zeroed.
+	pub fn decrypt_cbc_verified(k: &[u8], iv: &[u8], encrypted: &[u8], dest: &mut [u8]) -> Result<usize, Error> {
+		const BLOCK: usize = 16;
+
+		let len = encrypted.len();
+		if len == 0 || len % BLOCK != 0 || dest.len() < len {
+			return Err(Error::InvalidMessage);
+		}
+
+		let mut decryptor = CbcDecryptor::new(AesSafe128Decryptor::new(k), NoPadding, iv.to_vec());
+		if decryptor.decrypt(&mut RefReadBuffer::new(encrypted), &mut RefWriteBuffer::new(&mut dest[..len]), true).is_err() {
+			return Err(Error::InvalidMessage);
+		}
+
+		// every byte of the last block is inspected regardless of the padding value.
+		let (pad, bad) = {
+			let last = &dest[len - BLOCK..len];
+			let pad = last[BLOCK - 1];
+			let mut bad = ct_lt(pad, 1) | ct_lt(BLOCK as u8, pad);
+			for i in 0..BLOCK {
+				bad |= ct_lt(i as u8, pad) & (last[BLOCK - 1 - i] ^ pad);
+			}
+			(pad, bad)
+		};
+
+		if bad == 0 {
+			Ok(len - pad as usize)
+		} else {
+			for byte in dest.iter_mut() {
+				*byte = 0;
+			}
+			Err(Error::InvalidMessage)
+		}
+	}
+
+	// 0xff if `a < b`, 0 otherwise, without branching.
+	fn ct_lt(a: u8, b: u8) -> u8 {
+		let borrow = ((a as u32).wrapping_sub(b as u32) >> 31) as u8;
+		0u8.wrapping_sub(borrow)
+	}
 }
 
 /// ECDH functions
+#[cfg(feature = "ecc")]
 #[cfg_attr(feature="dev", allow(similar_names))]
 pub mod ecdh {
 	use secp256k1::{ecdh, key};
+	use bigint::hash::FixedHash;
 	use ethkey::{Secret, Public, SECP256K1};
 	use Error;
 
+	/// Construct a public key from 64 raw bytes, checking that they form a point on the curve.
+	/// Fails with `Error::InvalidMessage` otherwise, since keys are parsed from received messages.
+	pub fn public_from_slice(data: &[u8]) -> Result<Public, Error> {
+		if data.len() != 64 {
+			return Err(Error::InvalidMessage);
+		}
+
+		let mut pdata = [4u8; 65];
+		pdata[1..65].copy_from_slice(data);
+		try!(key::PublicKey::from_slice(&SECP256K1, &pdata).map_err(|_| Error::InvalidMessage));
+		Ok(Public::from_slice(data))
+	}
+
+	/// Construct a public key from its 33-byte compressed form, checking that it's a point on the curve.
+	/// Fails with `Error::InvalidMessage` otherwise.
+	pub fn public_from_compressed(data: &[u8; 33]) -> Result<Public, Error> {
+		if data[0] != 2 && data[0] != 3 {
+			return Err(Error::InvalidMessage);
+		}
+
+		let context = &SECP256K1;
+		let publ = try!(key::PublicKey::from_slice(context, data).map_err(|_| Error::InvalidMessage));
+		let serialized = publ.serialize_vec(context, false);
+		Ok(Public::from_slice(&serialized[1..65]))
+	}
+
 	/// Agree on a shared secret
 	pub fn agree(secret: &Secret, public: &Public) -> Result<Secret, Error> {
 		let context = &SECP256K1;
@@ -142,63 +501,200 @@ pub mod ecdh {
 }
 
 /// ECIES function
+#[cfg(feature = "ecc")]
 #[cfg_attr(feature="dev", allow(similar_names))]
 pub mod ecies {
 	use rcrypto::digest::Digest;
 	use rcrypto::sha2::Sha256;
 	use rcrypto::hmac::Hmac;
 	use rcrypto::mac::Mac;
+	use rcrypto::util::fixed_time_eq;
 	use bigint::hash::{FixedHash, H128};
-	use ethkey::{Random, Generator, Public, Secret};
+	use ethkey::{KeyPair, Public, Secret};
+	use rand::{Rng, OsRng};
+	use num_cpus;
 	use {Error, ecdh, aes, Keccak256};
 
-	/// Encrypt a message with a public key
-	pub fn encrypt(public: &Public, shared_mac: &[u8], plain: &[u8]) -> Result<Vec<u8>, Error> {
-		let r = Random.generate().unwrap();
-		let z = try!(ecdh::agree(r.secret(), public));
-		let mut key = [0u8; 32];
-		let mut mkey = [0u8; 32];
-		kdf(&z, &[0u8; 0], &mut key);
-		let mut hasher = Sha256::new();
-		let mkey_material = &key[16..32];
-		hasher.input(mkey_material);
-		hasher.result(&mut mkey);
-		let ekey = &key[0..16];
+	/// How many times to draw a secret from the RNG before giving up.
+	const KEYPAIR_ATTEMPTS: usize = 16;
 
-		let mut msg = vec![0u8; (1 + 64 + 16 + plain.len() + 32)];
-		msg[0] = 0x04u8;
-		{
-			let msgd = &mut msg[1..];
-			msgd[0..64].copy_from_slice(r.public());
-			let iv = H128::random();
-			msgd[64..80].copy_from_slice(&iv);
-			{
-				let cipher = &mut msgd[(64 + 16)..(64 + 16 + plain.len())];
-				aes::encrypt(ekey, &iv, plain, cipher);
+	/// Leading byte of messages carrying a key-id hint.
+	/// Plain messages start with the public key format byte (2-4), so the two can't be confused.
+	pub const KEY_ID_VERSION: u8 = 0x01;
+
+	/// Length of the key-id prefix: version byte and big-endian `u32` id.
+	const KEY_ID_PREFIX_LEN: usize = 5;
+
+	/// Length of the HMAC tag appended to encrypted messages.
+	#[derive(Debug, PartialEq, Clone, Copy)]
+	pub enum TagLength {
+		/// Full 32-byte SHA-256 HMAC.
+		Full,
+		/// HMAC truncated to 16 bytes.
+		Truncated,
+	}
+
+	impl TagLength {
+		/// Number of tag bytes in the message.
+		pub fn bytes(&self) -> usize {
+			match *self {
+				TagLength::Full => 32,
+				TagLength::Truncated => 16,
 			}
-			let mut hmac = Hmac::new(Sha256::new(), &mkey);
-			{
-				let cipher_iv = &msgd[64..(64 + 16 + plain.len())];
-				hmac.input(cipher_iv);
+		}
+	}
+
+	impl Default for TagLength {
+		fn default() -> Self {
+			TagLength::Full
+		}
+	}
+
+	/// View of an encrypted message:
+	/// `prefix || ephemeral public key (64) || IV (16) || ciphertext || tag`.
+	#[derive(Debug, PartialEq, Clone)]
+	pub struct EciesMessage<'a> {
+		/// Public key format byte, 2 to 4. Messages encrypted here always use 4.
+		pub prefix: u8,
+		/// Ephemeral public key the shared secret is agreed with.
+		pub ephemeral: Public,
+		/// AES-CTR initialization vector.
+		pub iv: H128,
+		/// Encrypted plain text.
+		pub ciphertext: &'a [u8],
+		/// HMAC-SHA256 tag over the IV, ciphertext and shared MAC data, possibly truncated.
+		pub tag: &'a [u8],
+	}
+
+	impl<'a> EciesMessage<'a> {
+		/// Number of bytes in a message besides the ciphertext.
+		pub fn overhead(tag_len: TagLength) -> usize {
+			1 + 64 + 16 + tag_len.bytes()
+		}
+
+		/// Split an encrypted message into its parts, expecting a tag of given length.
+		/// Fails if the message is too short, the prefix is unknown or the ephemeral
+		/// key isn't a valid point.
+		pub fn from_bytes(bytes: &'a [u8], tag_len: TagLength) -> Result<Self, Error> {
+			let overhead = Self::overhead(tag_len);
+			if bytes.len() < overhead || bytes[0] < 2 || bytes[0] > 4 {
+				return Err(Error::InvalidMessage);
 			}
-			hmac.input(shared_mac);
-			hmac.raw_result(&mut msgd[(64 + 16 + plain.len())..]);
+
+			let clen = bytes.len() - overhead;
+			Ok(EciesMessage {
+				prefix: bytes[0],
+				ephemeral: try!(ecdh::public_from_slice(&bytes[1..65])),
+				iv: H128::from_slice(&bytes[65..81]),
+				ciphertext: &bytes[81..(81 + clen)],
+				tag: &bytes[(81 + clen)..],
+			})
 		}
+
+		/// Encode the message.
+		pub fn to_bytes(&self) -> Vec<u8> {
+			let mut out = Vec::new();
+			self.write_to(&mut out);
+			out
+		}
+
+		/// Encode the message into the given buffer, replacing its contents.
+		pub fn write_to(&self, out: &mut Vec<u8>) {
+			out.clear();
+			out.reserve(1 + 64 + 16 + self.ciphertext.len() + self.tag.len());
+			out.push(self.prefix);
+			out.extend_from_slice(&*self.ephemeral);
+			out.extend_from_slice(&*self.iv);
+			out.extend_from_slice(self.ciphertext);
+			out.extend_from_slice(self.tag);
+		}
+	}
+
+	/// Encrypt a message with a public key
+	pub fn encrypt(public: &Public, shared_mac: &[u8], plain: &[u8]) -> Result<Vec<u8>, Error> {
+		encrypt_with_tag_len(public, shared_mac, plain, TagLength::Full)
+	}
+
+	/// Encrypt a message with a public key given in its 33-byte compressed form.
+	/// Fails with `Error::InvalidMessage` if the key isn't a point on the curve.
+	pub fn encrypt_to_compressed(compressed: &[u8; 33], shared_mac: &[u8], plain: &[u8]) -> Result<Vec<u8>, Error> {
+		let public = try!(ecdh::public_from_compressed(compressed));
+		encrypt(&public, shared_mac, plain)
+	}
+
+	/// Encrypt a message with a public key, appending a tag of given length.
+	pub fn encrypt_with_tag_len(public: &Public, shared_mac: &[u8], plain: &[u8], tag_len: TagLength) -> Result<Vec<u8>, Error> {
+		let mut rng = try!(OsRng::new().map_err(|_| Error::Random));
+		encrypt_inner(&mut rng, public, shared_mac, plain, tag_len)
+	}
+
+	/// Encrypt a message with a public key, drawing the ephemeral key and IV from the given RNG.
+	pub fn encrypt_with_rng<R: Rng>(rng: &mut R, public: &Public, shared_mac: &[u8], plain: &[u8]) -> Result<Vec<u8>, Error> {
+		encrypt_inner(rng, public, shared_mac, plain, TagLength::Full)
+	}
+
+	/// Encrypt a message with a public key into the given buffer, replacing its contents.
+	/// The ciphertext is written straight into the buffer, so reusing it across calls
+	/// avoids allocating for every message.
+	/// The buffer is left untouched on error.
+	pub fn encrypt_into(public: &Public, shared_mac: &[u8], plain: &[u8], out: &mut Vec<u8>) -> Result<(), Error> {
+		let mut rng = try!(OsRng::new().map_err(|_| Error::Random));
+		encrypt_into_with_rng(&mut rng, public, shared_mac, plain, out)
+	}
+
+	/// Like `encrypt_into`, drawing the ephemeral key and IV from the given RNG.
+	pub fn encrypt_into_with_rng<R: Rng>(rng: &mut R, public: &Public, shared_mac: &[u8], plain: &[u8], out: &mut Vec<u8>) -> Result<(), Error> {
+		encrypt_to(rng, public, shared_mac, plain, TagLength::Full, out)
+	}
+
+	fn encrypt_inner<R: Rng>(rng: &mut R, public: &Public, shared_mac: &[u8], plain: &[u8], tag_len: TagLength) -> Result<Vec<u8>, Error> {
+		let mut msg = Vec::new();
+		try!(encrypt_to(rng, public, shared_mac, plain, tag_len, &mut msg));
 		Ok(msg)
 	}
 
+	fn encrypt_to<R: Rng>(rng: &mut R, public: &Public, shared_mac: &[u8], plain: &[u8], tag_len: TagLength, msg: &mut Vec<u8>) -> Result<(), Error> {
+		let r = try!(random_keypair(rng));
+		let z = try!(ecdh::agree(r.secret(), public));
+		let (ekey, mkey) = derive_enc_mac_keys(&z);
+
+		let iv = random_iv(rng);
+		let clen = plain.len();
+
+		// lay the message out in place and encrypt straight into its ciphertext slice.
+		msg.clear();
+		msg.resize(EciesMessage::overhead(tag_len) + clen, 0);
+		msg[0] = 0x04u8;
+		r.public().copy_to(&mut msg[1..65]);
+		iv.copy_to(&mut msg[65..81]);
+		aes::encrypt(&ekey, &iv, plain, &mut msg[81..(81 + clen)]);
+		let mac = compute_tag(&mkey, &iv, &msg[81..(81 + clen)], shared_mac);
+		msg[(81 + clen)..].copy_from_slice(&mac[..tag_len.bytes()]);
+		Ok(())
+	}
+
+	// HMAC-SHA256 over the IV, ciphertext and shared MAC data, fed straight from the given slices.
+	fn compute_tag(mkey: &[u8; 32], iv: &H128, cipher: &[u8], shared_mac: &[u8]) -> [u8; 32] {
+		let mut hmac = Hmac::new(Sha256::new(), mkey);
+		hmac.input(iv);
+		hmac.input(cipher);
+		hmac.input(shared_mac);
+		let mut mac = [0u8; 32];
+		hmac.raw_result(&mut mac);
+		mac
+	}
+
 	/// Encrypt a message with a public key
 	pub fn encrypt_single_message(public: &Public, plain: &[u8]) -> Result<Vec<u8>, Error> {
-		let r = Random.generate().unwrap();
+		let mut rng = try!(OsRng::new().map_err(|_| Error::Random));
+		encrypt_single_message_with_rng(&mut rng, public, plain)
+	}
+
+	/// Encrypt a message with a public key, drawing the ephemeral key from the given RNG.
+	pub fn encrypt_single_message_with_rng<R: Rng>(rng: &mut R, public: &Public, plain: &[u8]) -> Result<Vec<u8>, Error> {
+		let r = try!(random_keypair(rng));
 		let z = try!(ecdh::agree(r.secret(), public));
-		let mut key = [0u8; 32];
-		let mut mkey = [0u8; 32];
-		kdf(&z, &[0u8; 0], &mut key);
-		let mut hasher = Sha256::new();
-		let mkey_material = &key[16..32];
-		hasher.input(mkey_material);
-		hasher.result(&mut mkey);
-		let ekey = &key[0..16];
+		let (ekey, _) = derive_enc_mac_keys(&z);
 
 		let mut msgd = vec![0u8; (64 + plain.len())];
 		{
@@ -206,7 +702,7 @@ pub mod ecies {
 			let iv = H128::from_slice(&z.keccak256()[0..16]);
 			{
 				let cipher = &mut msgd[64..(64 + plain.len())];
-				aes::encrypt(ekey, &iv, plain, cipher);
+				aes::encrypt(&ekey, &iv, plain, cipher);
 			}
 		}
 		Ok(msgd)
@@ -214,44 +710,133 @@ pub mod ecies {
 
 	/// Decrypt a message with a secret key
 	pub fn decrypt(secret: &Secret, shared_mac: &[u8], encrypted: &[u8]) -> Result<Vec<u8>, Error> {
-		let meta_len = 1 + 64 + 16 + 32;
-		if encrypted.len() < meta_len  || encrypted[0] < 2 || encrypted[0] > 4 {
-			return Err(Error::InvalidMessage); //invalid message: publickey
+		decrypt_with_tag_len(secret, shared_mac, encrypted, TagLength::Full)
+	}
+
+	/// Decrypt a message with a secret key, also returning the length of the valid plain text.
+	/// The plain text is the first that many bytes of the returned buffer. With the CTR cipher
+	/// used here they're always the whole buffer; padded ciphers would leave a shorter length.
+	pub fn decrypt_with_len(secret: &Secret, shared_mac: &[u8], encrypted: &[u8]) -> Result<(Vec<u8>, usize), Error> {
+		let plain = try!(decrypt(secret, shared_mac, encrypted));
+		let len = plain.len();
+		Ok((plain, len))
+	}
+
+	/// Decrypt a message with a secret key, expecting a tag of given length.
+	pub fn decrypt_with_tag_len(secret: &Secret, shared_mac: &[u8], encrypted: &[u8], tag_len: TagLength) -> Result<Vec<u8>, Error> {
+		let (ekey, message) = try!(verify_tag(secret, shared_mac, encrypted, tag_len));
+		Ok(decrypt_verified(&ekey, &message))
+	}
+
+	/// Decrypt a message with a secret key, rejecting messages longer than `max_len` bytes
+	/// before any key agreement or hashing is done, which bounds the work spent on
+	/// messages from untrusted peers. The tag is then computed over the ciphertext in place.
+	pub fn decrypt_with_max_len(secret: &Secret, shared_mac: &[u8], encrypted: &[u8], max_len: usize) -> Result<Vec<u8>, Error> {
+		if encrypted.len() > max_len {
+			return Err(Error::MessageTooLarge);
 		}
+		decrypt(secret, shared_mac, encrypted)
+	}
 
-		let e = &encrypted[1..];
-		let p = Public::from_slice(&e[0..64]);
-		let z = try!(ecdh::agree(secret, &p));
-		let mut key = [0u8; 32];
-		kdf(&z, &[0u8; 0], &mut key);
-		let ekey = &key[0..16];
-		let mkey_material = &key[16..32];
-		let mut hasher = Sha256::new();
-		let mut mkey = [0u8; 32];
-		hasher.input(mkey_material);
-		hasher.result(&mut mkey);
+	/// Decrypt a message with a secret key, passing the plain text to `sink` in blocks
+	/// of `aes::SINK_BLOCK_SIZE` bytes instead of collecting it.
+	/// The tag is checked over the whole message before anything is decrypted,
+	/// so `sink` never sees unauthenticated plain text.
+	pub fn decrypt_to_sink<F: FnMut(&[u8])>(secret: &Secret, shared_mac: &[u8], encrypted: &[u8], sink: F) -> Result<(), Error> {
+		let (ekey, message) = try!(verify_tag(secret, shared_mac, encrypted, TagLength::Full));
+		aes::decrypt_to_sink(&ekey, &message.iv, message.ciphertext, sink);
+		Ok(())
+	}
 
-		let clen = encrypted.len() - meta_len;
-		let cipher_with_iv = &e[64..(64+16+clen)];
-		let cipher_iv = &cipher_with_iv[0..16];
-		let cipher_no_iv = &cipher_with_iv[16..];
-		let msg_mac = &e[(64+16+clen)..];
-
-		// Verify tag
-		let mut hmac = Hmac::new(Sha256::new(), &mkey);
-		hmac.input(cipher_with_iv);
-		hmac.input(shared_mac);
-		let mut mac = [0u8; 32];
-		hmac.raw_result(&mut mac);
-		if &mac[..] != msg_mac {
-			return Err(Error::InvalidMessage);
+	/// Check that a message was encrypted for the given secret key and hasn't been
+	/// tampered with, without decrypting it.
+	pub fn verify(secret: &Secret, shared_mac: &[u8], encrypted: &[u8]) -> Result<(), Error> {
+		verify_tag(secret, shared_mac, encrypted, TagLength::Full).map(|_| ())
+	}
+
+	/// Check many messages for the same secret key at once, running the checks in parallel
+	/// (one thread per CPU at most). Every message has its own ephemeral key, so the key
+	/// agreement is done for each of them. Results are in the order of messages.
+	pub fn verify_batch(secret: &Secret, shared_mac: &[u8], messages: &[&[u8]]) -> Vec<Result<(), Error>> {
+		super::parallel_map(messages, num_cpus::get(), |encrypted| verify(secret, shared_mac, encrypted))
+	}
+
+	/// Decrypt a message with whichever of the given secret keys it was encrypted for,
+	/// returning the index of that key along with the plain text.
+	/// Only the tag is checked for the other keys, so the message is decrypted once.
+	pub fn decrypt_any(secrets: &[Secret], shared_mac: &[u8], encrypted: &[u8]) -> Result<(usize, Vec<u8>), Error> {
+		for (i, secret) in secrets.iter().enumerate() {
+			if let Ok((ekey, message)) = verify_tag(secret, shared_mac, encrypted, TagLength::Full) {
+				return Ok((i, decrypt_verified(&ekey, &message)));
+			}
 		}
+		Err(Error::InvalidMessage)
+	}
 
-		let mut msg = vec![0u8; clen];
-		aes::decrypt(ekey, cipher_iv, cipher_no_iv, &mut msg[..]);
+	/// Encrypt a message with a public key, prepending a hint of which recipient key it's for.
+	///
+	/// The key id is a routing hint only: it's sent in the clear and is NOT covered by the tag,
+	/// so anyone relaying the message can change it. A tampered id just makes the recipient
+	/// pick the wrong key, which then fails the tag check.
+	pub fn encrypt_with_key_id(public: &Public, key_id: u32, shared_mac: &[u8], plain: &[u8]) -> Result<Vec<u8>, Error> {
+		let encrypted = try!(encrypt(public, shared_mac, plain));
+		let mut msg = Vec::with_capacity(KEY_ID_PREFIX_LEN + encrypted.len());
+		msg.push(KEY_ID_VERSION);
+		msg.push((key_id >> 24) as u8);
+		msg.push((key_id >> 16) as u8);
+		msg.push((key_id >> 8) as u8);
+		msg.push(key_id as u8);
+		msg.extend_from_slice(&encrypted);
 		Ok(msg)
 	}
 
+	/// Read the key-id hint of a message produced by `encrypt_with_key_id`, without decrypting it.
+	/// Returns `None` for messages without the hint.
+	pub fn key_id(encrypted: &[u8]) -> Option<u32> {
+		if encrypted.len() < KEY_ID_PREFIX_LEN || encrypted[0] != KEY_ID_VERSION {
+			return None;
+		}
+		Some(encrypted[1..KEY_ID_PREFIX_LEN].iter().fold(0u32, |id, b| (id << 8) | *b as u32))
+	}
+
+	/// Decrypt a message produced by `encrypt_with_key_id`, returning the key-id hint
+	/// along with the plain text. See `encrypt_with_key_id` on why the id isn't authenticated.
+	pub fn decrypt_with_key_id(secret: &Secret, shared_mac: &[u8], encrypted: &[u8]) -> Result<(u32, Vec<u8>), Error> {
+		let id = try!(key_id(encrypted).ok_or(Error::InvalidMessage));
+		let plain = try!(decrypt(secret, shared_mac, &encrypted[KEY_ID_PREFIX_LEN..]));
+		Ok((id, plain))
+	}
+
+	/// Identifier of an encrypted message, for deduplicating relayed payloads.
+	///
+	/// This is the keccak256 hash of the whole message: ephemeral public key, IV,
+	/// ciphertext and the MAC tag. Including the tag means the id doesn't depend on the
+	/// tag length and tampered copies of a message don't collide with the original.
+	pub fn message_id(encrypted: &[u8]) -> [u8; 32] {
+		encrypted.keccak256()
+	}
+
+	// decrypt a message whose tag was already checked.
+	fn decrypt_verified(ekey: &[u8; 16], message: &EciesMessage) -> Vec<u8> {
+		let mut msg = vec![0u8; message.ciphertext.len()];
+		aes::decrypt(ekey, &message.iv, message.ciphertext, &mut msg[..]);
+		msg
+	}
+
+	// check the message tag, returning the cipher key and the parsed message.
+	fn verify_tag<'a>(secret: &Secret, shared_mac: &[u8], encrypted: &'a [u8], tag_len: TagLength) -> Result<([u8; 16], EciesMessage<'a>), Error> {
+		let message = try!(EciesMessage::from_bytes(encrypted, tag_len));
+		let z = try!(ecdh::agree(secret, &message.ephemeral));
+		let (ekey, mkey) = derive_enc_mac_keys(&z);
+
+		let mac = compute_tag(&mkey, &message.iv, message.ciphertext, shared_mac);
+		if !fixed_time_eq(&mac[..tag_len.bytes()], message.tag) {
+			return Err(Error::InvalidMessage);
+		}
+
+		Ok((ekey, message))
+	}
+
 	/// Decrypt single message with a secret key
 	pub fn decrypt_single_message(secret: &Secret, encrypted: &[u8]) -> Result<Vec<u8>, Error> {
 		let meta_len = 64;
@@ -260,25 +845,56 @@ pub mod ecies {
 		}
 
 		let e = encrypted;
-		let p = Public::from_slice(&e[0..64]);
+		let p = try!(ecdh::public_from_slice(&e[0..64]));
 		let z = try!(ecdh::agree(secret, &p));
-		let mut key = [0u8; 32];
-		kdf(&z, &[0u8; 0], &mut key);
-		let ekey = &key[0..16];
-		let mkey_material = &key[16..32];
-		let mut hasher = Sha256::new();
-		let mut mkey = [0u8; 32];
-		hasher.input(mkey_material);
-		hasher.result(&mut mkey);
+		let (ekey, _) = derive_enc_mac_keys(&z);
 
 		let clen = encrypted.len() - meta_len;
 		let cipher = &e[64..(64+clen)];
 		let mut msg = vec![0u8; clen];
 		let iv = H128::from_slice(&z.keccak256()[0..16]);
-		aes::decrypt(ekey, &iv, cipher, &mut msg[..]);
+		aes::decrypt(&ekey, &iv, cipher, &mut msg[..]);
 		Ok(msg)
 	}
 
+	// draw an ephemeral key pair from the given RNG.
+	fn random_keypair<R: Rng>(rng: &mut R) -> Result<KeyPair, Error> {
+		for _ in 0..KEYPAIR_ATTEMPTS {
+			let mut secret = Secret::default();
+			rng.fill_bytes(&mut secret);
+			// out-of-range secrets are rejected; just draw again.
+			if let Ok(pair) = KeyPair::from_secret(secret) {
+				return Ok(pair);
+			}
+		}
+		Err(Error::Random)
+	}
+
+	// draw an initialization vector from the given RNG.
+	fn random_iv<R: Rng>(rng: &mut R) -> H128 {
+		let mut iv = H128::default();
+		rng.fill_bytes(&mut iv);
+		iv
+	}
+
+	/// Derive the AES encryption key and the HMAC key from a shared secret.
+	/// The first half of the KDF output is the cipher key, the second half is
+	/// hashed to produce the MAC key.
+	pub fn derive_enc_mac_keys(shared: &Secret) -> ([u8; 16], [u8; 32]) {
+		let mut key = [0u8; 32];
+		kdf(shared, &[0u8; 0], &mut key);
+
+		let mut ekey = [0u8; 16];
+		ekey.copy_from_slice(&key[0..16]);
+
+		let mut mkey = [0u8; 32];
+		let mut hasher = Sha256::new();
+		hasher.input(&key[16..32]);
+		hasher.result(&mut mkey);
+
+		(ekey, mkey)
+	}
+
 	fn kdf(secret: &Secret, s1: &[u8], dest: &mut [u8]) {
 		let mut hasher = Sha256::new();
 		// SEC/ISO/Shoup specify counter size SHOULD be equivalent
@@ -299,12 +915,261 @@ pub mod ecies {
 	}
 }
 
+/// Run known-answer tests for AES-128-CTR, PBKDF2, scrypt and keccak256 against
+/// embedded vectors. Meant to be called on startup to detect a broken crypto backend.
+/// Returns `Error::SelfTest` naming the first primitive that produced a wrong answer.
+pub fn self_test() -> Result<(), Error> {
+	// NIST SP 800-38A, F.5.1 (first two blocks).
+	const AES_KEY: [u8; 16] = [0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6, 0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf, 0x4f, 0x3c];
+	const AES_IV: [u8; 16] = [0xf0, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8, 0xf9, 0xfa, 0xfb, 0xfc, 0xfd, 0xfe, 0xff];
+	const AES_PLAIN: [u8; 32] = [
+		0x6b, 0xc1, 0xbe, 0xe2, 0x2e, 0x40, 0x9f, 0x96, 0xe9, 0x3d, 0x7e, 0x11, 0x73, 0x93, 0x17, 0x2a,
+		0xae, 0x2d, 0x8a, 0x57, 0x1e, 0x03, 0xac, 0x9c, 0x9e, 0xb7, 0x6f, 0xac, 0x45, 0xaf, 0x8e, 0x51,
+	];
+	const AES_CIPHER: [u8; 32] = [
+		0x87, 0x4d, 0x61, 0x91, 0xb6, 0x20, 0xe3, 0x26, 0x1b, 0xef, 0x68, 0x64, 0x99, 0x0d, 0xb6, 0xce,
+		0x98, 0x06, 0xf6, 0x6b, 0x79, 0x70, 0xfd, 0xff, 0x86, 0x17, 0x18, 0x7b, 0xb9, 0xff, 0xfd, 0xff,
+	];
+	// "password" with salt "parity-self-test", PBKDF2-HMAC-SHA256 (c = 1024) and scrypt (n = 1024, r = 8, p = 1).
+	const KDF_PASSWORD: &'static str = "password";
+	const KDF_SALT: &'static [u8] = b"parity-self-test";
+	const PBKDF2_KEY: [u8; 32] = [
+		0x9e, 0xfb, 0x73, 0x76, 0x28, 0x76, 0xce, 0xc5, 0xa9, 0xbc, 0xc0, 0x0c, 0x7d, 0xc9, 0x3a, 0x90,
+		0xb7, 0xf6, 0x96, 0xdc, 0xb8, 0x9c, 0x5b, 0xfc, 0x17, 0xd0, 0xe9, 0x08, 0xb6, 0x3d, 0x9d, 0xdc,
+	];
+	const SCRYPT_KEY: [u8; 32] = [
+		0xf0, 0xcd, 0x32, 0xca, 0x24, 0x17, 0x91, 0x48, 0xd9, 0xfe, 0xdc, 0xd2, 0x87, 0xf0, 0xdb, 0x95,
+		0xb8, 0xb6, 0x32, 0x66, 0xf3, 0x16, 0xea, 0x8f, 0x4f, 0x15, 0x52, 0x58, 0x73, 0x22, 0xfa, 0x4e,
+	];
+	// keccak256 of the empty string.
+	const KECCAK_EMPTY: [u8; 32] = [
+		0xc5, 0xd2, 0x46, 0x01, 0x86, 0xf7, 0x23, 0x3c, 0x92, 0x7e, 0x7d, 0xb2, 0xdc, 0xc7, 0x03, 0xc0,
+		0xe5, 0x00, 0xb6, 0x53, 0xca, 0x82, 0x27, 0x3b, 0x7b, 0xfa, 0xd8, 0x04, 0x5d, 0x85, 0xa4, 0x70,
+	];
+
+	fn check(ok: bool, primitive: &'static str) -> Result<(), Error> {
+		match ok {
+			true => Ok(()),
+			false => Err(Error::SelfTest(primitive)),
+		}
+	}
+
+	let mut encrypted = [0u8; 32];
+	aes::encrypt(&AES_KEY, &AES_IV, &AES_PLAIN, &mut encrypted);
+	try!(check(encrypted == AES_CIPHER, "aes-128-ctr encrypt"));
+
+	let mut decrypted = [0u8; 32];
+	aes::decrypt(&AES_KEY, &AES_IV, &AES_CIPHER, &mut decrypted);
+	try!(check(decrypted == AES_PLAIN, "aes-128-ctr decrypt"));
+
+	let (right, left) = try!(derive_key_iterations(KDF_PASSWORD, KDF_SALT, 1024));
+	try!(check(right[..] == PBKDF2_KEY[..KEY_LENGTH_AES] && left[..] == PBKDF2_KEY[KEY_LENGTH_AES..], "pbkdf2"));
+
+	let (right, left) = try!(derive_key_scrypt(KDF_PASSWORD, KDF_SALT, 1024, 1, 8));
+	try!(check(right[..] == SCRYPT_KEY[..KEY_LENGTH_AES] && left[..] == SCRYPT_KEY[KEY_LENGTH_AES..], "scrypt"));
+
+	let hash: [u8; 32] = [0u8; 0][..].keccak256();
+	check(hash == KECCAK_EMPTY, "keccak256")
+}
+
 #[cfg(test)]
 mod tests {
+	use rcrypto::blockmodes::{CbcEncryptor, NoPadding};
+	use rcrypto::aessafe::AesSafe128Encryptor;
+	use rcrypto::buffer::{RefReadBuffer, RefWriteBuffer};
+	use rcrypto::symmetriccipher::Encryptor;
+	use std::env;
+	use std::fs::{self, File};
+	use std::io::Write;
+	use {aes, derive_mac, derive_mac_with_aad, derive_key_iterations, derive_key_iterations_with_prf, derive_key_scrypt, derive_keys_scrypt_batch, keccak256_file, self_test, Error, Keccak256, Prf, KEY_LENGTH_AES};
+	#[cfg(feature = "ecc")]
+	use std::str::FromStr;
+	#[cfg(feature = "ecc")]
+	use bigint::hash::{H128, H256};
+	#[cfg(feature = "ecc")]
 	use ethkey::{Random, Generator};
-	use ecies;
+	#[cfg(feature = "ecc")]
+	use rand::{SeedableRng, XorShiftRng};
+	#[cfg(feature = "ecc")]
+	use {ecies, ecdh};
+
+	fn from_hex(hex: &str) -> Vec<u8> {
+		(0..hex.len() / 2).map(|i| u8::from_str_radix(&hex[(2 * i)..(2 * i + 2)], 16).unwrap()).collect()
+	}
+
+	fn encrypt_cbc_raw(k: &[u8], iv: &[u8], plain: &[u8]) -> Vec<u8> {
+		let mut encryptor = CbcEncryptor::new(AesSafe128Encryptor::new(k), NoPadding, iv.to_vec());
+		let mut dest = vec![0u8; plain.len()];
+		encryptor.encrypt(&mut RefReadBuffer::new(plain), &mut RefWriteBuffer::new(&mut dest), true).unwrap();
+		dest
+	}
+
+	#[test]
+	fn derive_mac_checks_key_length() {
+		let cipher_text = [1u8, 2, 3];
+
+		let mac = derive_mac(&[0u8; KEY_LENGTH_AES], &cipher_text).unwrap();
+		assert_eq!(mac.len(), KEY_LENGTH_AES + cipher_text.len());
+		assert_eq!(&mac[KEY_LENGTH_AES..], &cipher_text[..]);
+
+		assert_eq!(derive_mac(&[0u8; KEY_LENGTH_AES - 1], &cipher_text), Err(Error::InvalidKeyLength));
+		assert_eq!(derive_mac(&[0u8; KEY_LENGTH_AES + 1], &cipher_text), Err(Error::InvalidKeyLength));
+	}
+
+	#[test]
+	fn derive_mac_with_aad_appends_aad() {
+		let left_bits = [7u8; KEY_LENGTH_AES];
+		let cipher_text = [1u8, 2, 3];
+		let aad = b"v4";
+
+		let plain = derive_mac(&left_bits, &cipher_text).unwrap();
+		let with_aad = derive_mac_with_aad(&left_bits, &cipher_text, aad).unwrap();
+
+		assert_eq!(derive_mac_with_aad(&left_bits, &cipher_text, &[]).unwrap(), plain);
+		assert!(plain.keccak256() != with_aad.keccak256());
+		assert_eq!(&with_aad[..plain.len()], &plain[..]);
+		assert_eq!(&with_aad[plain.len()..], &aad[..]);
+	}
+
+	#[test]
+	fn scrypt_batch_matches_serial() {
+		let inputs: Vec<_> = (0..5u8).map(|i| (format!("password{}", i), [i; 32])).collect();
+
+		let serial: Vec<_> = inputs.iter().map(|&(ref password, ref salt)| derive_key_scrypt(password, salt, 1024, 1, 8).unwrap()).collect();
+		assert_eq!(derive_keys_scrypt_batch(&inputs, 1024, 1, 8), serial);
+		assert!(derive_keys_scrypt_batch(&[], 1024, 1, 8).is_empty());
+	}
+
+	#[test]
+	fn derive_key_with_sha512_prf() {
+		let salt = b"saltSALTsaltSALTsaltSALTsaltSALTsalt";
+		let (left, right) = derive_key_iterations_with_prf("passwordPASSWORDpassword", salt, 4096, Prf::HmacSha512).unwrap();
+		assert_eq!(left, from_hex("8c0511f4c6e597c6ac6315d8f0362e22"));
+		assert_eq!(right, from_hex("5f3c501495ba23b868c005174dc4ee71"));
+
+		// sha256 stays the default.
+		let (left, right) = derive_key_iterations("passwordPASSWORDpassword", salt, 4096).unwrap();
+		assert_eq!(left, from_hex("348c89dbcbd32b2f32d814b8116e84cf"));
+		assert_eq!(right, from_hex("2b17347ebc1800181c4e2a1fb8dd53e1"));
+		assert_eq!(Prf::default(), Prf::HmacSha256);
+	}
+
+	#[test]
+	fn self_test_passes() {
+		assert_eq!(self_test(), Ok(()));
+	}
+
+	#[test]
+	fn derive_key_salt_lengths() {
+		let (left16, right16) = derive_key_iterations("password", &[1u8; 16], 16).unwrap();
+		let (left32, right32) = derive_key_iterations("password", &[1u8; 32], 16).unwrap();
+		assert_eq!(left16.len(), KEY_LENGTH_AES);
+		assert_eq!(right16.len(), KEY_LENGTH_AES);
+		assert!(left16 != left32 || right16 != right32);
+		assert_eq!(derive_key_iterations("password", &[1u8; 8], 16), Err(Error::InvalidSaltLength));
+
+		assert!(derive_key_scrypt("password", &[1u8; 16], 1024, 1, 8).is_ok());
+		assert!(derive_key_scrypt("password", &[1u8; 32], 1024, 1, 8).is_ok());
+		assert_eq!(derive_key_scrypt("password", &[1u8; 8], 1024, 1, 8), Err(Error::InvalidSaltLength));
+	}
+
+	#[test]
+	fn keccak256_of_file() {
+		let mut path = env::temp_dir();
+		path.push("ethcrypto-keccak256-file-test");
+
+		// spans several read blocks.
+		let content: Vec<u8> = (0..20000).map(|i| i as u8).collect();
+		File::create(&path).unwrap().write_all(&content).unwrap();
+		let hash = keccak256_file(&path).unwrap();
+		let expected: [u8; 32] = content.keccak256();
+		assert_eq!(hash, expected);
+
+		File::create(&path).unwrap();
+		let empty: [u8; 32] = [0u8; 0].keccak256();
+		assert_eq!(keccak256_file(&path).unwrap(), empty);
+
+		fs::remove_file(&path).unwrap();
+		assert!(keccak256_file(&path).is_err());
+	}
 
 	#[test]
+	fn aes_backends_produce_identical_output() {
+		let key = [7u8; 16];
+		let iv = [9u8; 16];
+		assert!(aes::Backend::Safe.is_available());
+		assert!(aes::best_backend().is_available());
+
+		for len in &[0usize, 1, 15, 16, 17, 1000] {
+			let plain: Vec<u8> = (0..*len).map(|i| i as u8).collect();
+			let mut expected = vec![0u8; *len];
+			aes::encrypt_with_backend(aes::Backend::Safe, &key, &iv, &plain, &mut expected);
+
+			// falls back to the safe backend if not available.
+			let mut encrypted = vec![0u8; *len];
+			aes::encrypt_with_backend(aes::Backend::AesNi, &key, &iv, &plain, &mut encrypted);
+			assert_eq!(encrypted, expected);
+
+			let mut decrypted = vec![0u8; *len];
+			aes::decrypt_with_backend(aes::Backend::AesNi, &key, &iv, &encrypted, &mut decrypted);
+			assert_eq!(decrypted, plain);
+			aes::decrypt(&key, &iv, &encrypted, &mut decrypted);
+			assert_eq!(decrypted, plain);
+		}
+	}
+
+	#[test]
+	fn aes_ctr_iv_for_counter() {
+		let base = [0xffu8, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe, 1, 2, 3, 4, 5, 6, 7, 8];
+		assert_eq!(aes::ctr_iv_for_counter(&base, 0), base);
+
+		let ivs: Vec<_> = (0..100u64).map(|counter| aes::ctr_iv_for_counter(&base, counter)).collect();
+		for (i, iv) in ivs.iter().enumerate() {
+			assert!(ivs[(i + 1)..].iter().all(|other| other != iv));
+			// the lower half, incremented per block, is left alone.
+			assert_eq!(&iv[8..], &base[8..]);
+		}
+
+		// the upper half wraps around.
+		assert_eq!(aes::ctr_iv_for_counter(&base, 2), [0u8, 0, 0, 0, 0, 0, 0, 0, 1, 2, 3, 4, 5, 6, 7, 8]);
+		assert_eq!(aes::ctr_iv_for_counter(&[0u8; 16], 0x0102), [0u8, 0, 0, 0, 0, 0, 1, 2, 0, 0, 0, 0, 0, 0, 0, 0]);
+	}
+
+	#[test]
+	fn aes_cbc_verified_padding() {
+		let key = [7u8; 16];
+		let iv = [9u8; 16];
+
+		let mut valid = b"So many books, so little time".to_vec();
+		valid.extend_from_slice(&[3u8; 3]);
+		let mut wrong_bytes = b"So many books, so little time".to_vec();
+		wrong_bytes.extend_from_slice(&[1u8, 2, 3]);
+		let mut zero_pad = b"So many books, so little time".to_vec();
+		zero_pad.extend_from_slice(&[1u8, 2, 0]);
+		let mut too_long = b"So many books, so little time".to_vec();
+		too_long.extend_from_slice(&[1u8, 2, 17]);
+
+		let mut dest = [0u8; 32];
+		let encrypted = encrypt_cbc_raw(&key, &iv, &valid);
+		assert_eq!(aes::decrypt_cbc_verified(&key, &iv, &encrypted, &mut dest), Ok(29));
+		assert_eq!(&dest[..29], b"So many books, so little time");
+		// matches the padding-checking decryption
+		let mut pkcs_dest = [0u8; 32];
+		assert_eq!(aes::decrypt_cbc(&key, &iv, &encrypted, &mut pkcs_dest).ok(), Some(29));
+
+		for invalid in &[wrong_bytes, zero_pad, too_long] {
+			let mut dest = [0u8; 32];
+			let encrypted = encrypt_cbc_raw(&key, &iv, invalid);
+			assert_eq!(aes::decrypt_cbc_verified(&key, &iv, &encrypted, &mut dest), Err(Error::InvalidMessage));
+			assert_eq!(dest, [0u8; 32]);
+		}
+
+		// malformed ciphertext is reported the same way
+		assert_eq!(aes::decrypt_cbc_verified(&key, &iv, &encrypted[..31], &mut dest), Err(Error::InvalidMessage));
+	}
+
+	#[test]
+	#[cfg(feature = "ecc")]
 	fn ecies_shared() {
 		let kp = Random.generate().unwrap();
 		let message = b"So many books, so little time";
@@ -321,6 +1186,100 @@ mod tests {
 	}
 
 	#[test]
+	#[cfg(feature = "ecc")]
+	fn ecies_truncated_tag() {
+		let kp = Random.generate().unwrap();
+		let message = b"So many books, so little time";
+		let shared = b"shared";
+
+		let encrypted = ecies::encrypt_with_tag_len(kp.public(), shared, message, ecies::TagLength::Truncated).unwrap();
+		assert_eq!(encrypted.len(), 1 + 64 + 16 + message.len() + 16);
+
+		let decrypted = ecies::decrypt_with_tag_len(kp.secret(), shared, &encrypted, ecies::TagLength::Truncated).unwrap();
+		assert_eq!(decrypted[..], message[..]);
+
+		// a full-length tag is not accepted in place of a truncated one, nor vice versa.
+		assert!(ecies::decrypt(kp.secret(), shared, &encrypted).is_err());
+		let full = ecies::encrypt(kp.public(), shared, message).unwrap();
+		assert!(ecies::decrypt_with_tag_len(kp.secret(), shared, &full, ecies::TagLength::Truncated).is_err());
+	}
+
+	#[test]
+	#[cfg(feature = "ecc")]
+	fn ecies_truncated_tag_detects_tampering() {
+		let kp = Random.generate().unwrap();
+		let message = b"So many books, so little time";
+		let shared = b"shared";
+
+		let encrypted = ecies::encrypt_with_tag_len(kp.public(), shared, message, ecies::TagLength::Truncated).unwrap();
+
+		// flip a bit in the ciphertext, then in the tag.
+		let mut tampered = encrypted.clone();
+		tampered[1 + 64 + 16] ^= 1;
+		assert_eq!(ecies::decrypt_with_tag_len(kp.secret(), shared, &tampered, ecies::TagLength::Truncated), Err(Error::InvalidMessage));
+
+		let mut tampered = encrypted.clone();
+		let last = tampered.len() - 1;
+		tampered[last] ^= 1;
+		assert_eq!(ecies::decrypt_with_tag_len(kp.secret(), shared, &tampered, ecies::TagLength::Truncated), Err(Error::InvalidMessage));
+	}
+
+	#[test]
+	#[cfg(feature = "ecc")]
+	fn ecies_verify_without_decrypting() {
+		let kp = Random.generate().unwrap();
+		let message = b"So many books, so little time";
+		let shared = b"shared";
+
+		let encrypted = ecies::encrypt(kp.public(), shared, message).unwrap();
+		assert_eq!(ecies::verify(kp.secret(), shared, &encrypted), Ok(()));
+		assert_eq!(ecies::verify(kp.secret(), b"incorrect", &encrypted), Err(Error::InvalidMessage));
+
+		let mut tampered = encrypted.clone();
+		tampered[1 + 64 + 16] ^= 1;
+		assert_eq!(ecies::verify(kp.secret(), shared, &tampered), Err(Error::InvalidMessage));
+		assert_eq!(ecies::verify(kp.secret(), shared, &encrypted[..10]), Err(Error::InvalidMessage));
+	}
+
+	#[test]
+	#[cfg(feature = "ecc")]
+	fn ecies_decrypt_any() {
+		let keys: Vec<_> = (0..3).map(|_| Random.generate().unwrap()).collect();
+		let secrets: Vec<_> = keys.iter().map(|kp| kp.secret().clone()).collect();
+		let message = b"So many books, so little time";
+		let shared = b"shared";
+
+		let encrypted = ecies::encrypt(keys[1].public(), shared, message).unwrap();
+		let (index, decrypted) = ecies::decrypt_any(&secrets, shared, &encrypted).unwrap();
+		assert_eq!(index, 1);
+		assert_eq!(decrypted[..], message[..]);
+
+		assert_eq!(ecies::decrypt_any(&[secrets[0].clone(), secrets[2].clone()], shared, &encrypted), Err(Error::InvalidMessage));
+		assert_eq!(ecies::decrypt_any(&[], shared, &encrypted), Err(Error::InvalidMessage));
+	}
+
+	#[test]
+	#[cfg(feature = "ecc")]
+	fn ecies_message_id() {
+		let kp = Random.generate().unwrap();
+		let shared = b"shared";
+
+		let encrypted = ecies::encrypt(kp.public(), shared, b"So many books").unwrap();
+		let copy = encrypted.clone();
+		assert_eq!(ecies::message_id(&encrypted), ecies::message_id(&copy));
+
+		// same plain text, but a fresh ephemeral key and IV.
+		let other = ecies::encrypt(kp.public(), shared, b"So many books").unwrap();
+		assert!(ecies::message_id(&encrypted) != ecies::message_id(&other));
+
+		let mut tampered = encrypted.clone();
+		let last = tampered.len() - 1;
+		tampered[last] ^= 1;
+		assert!(ecies::message_id(&encrypted) != ecies::message_id(&tampered));
+	}
+
+	#[test]
+	#[cfg(feature = "ecc")]
 	fn ecies_shared_single() {
 		let kp = Random.generate().unwrap();
 		let message = b"So many books, so little time";
@@ -329,5 +1288,245 @@ mod tests {
 		let decrypted = ecies::decrypt_single_message(kp.secret(), &encrypted).unwrap();
 		assert_eq!(decrypted[..message.len()], message[..]);
 	}
-}
 
+	#[test]
+	#[cfg(feature = "ecc")]
+	fn ecies_derive_enc_mac_keys() {
+		let shared = H256::from_str("a100df7a048e50ed308ea696dc600215098141cb391e9527329df289f9383f65").unwrap();
+		let (ekey, mkey) = ecies::derive_enc_mac_keys(&shared);
+
+		assert_eq!(H128::from_slice(&ekey), H128::from_str("95ca7207a4e65b8373b6f46d1a0fba74").unwrap());
+		assert_eq!(H256::from_slice(&mkey), H256::from_str("3c8c0e92b784690beafa1e3b6450159e1118f55cb78e4c535d1cad376f944c65").unwrap());
+	}
+
+	#[test]
+	#[cfg(feature = "ecc")]
+	fn ecies_with_deterministic_rng() {
+		let kp = Random.generate().unwrap();
+		let message = b"So many books, so little time";
+		let shared = b"shared";
+
+		let mut rng1 = XorShiftRng::from_seed([1, 2, 3, 4]);
+		let mut rng2 = XorShiftRng::from_seed([1, 2, 3, 4]);
+		let encrypted1 = ecies::encrypt_with_rng(&mut rng1, kp.public(), shared, message).unwrap();
+		let encrypted2 = ecies::encrypt_with_rng(&mut rng2, kp.public(), shared, message).unwrap();
+		assert_eq!(encrypted1, encrypted2);
+
+		let decrypted = ecies::decrypt(kp.secret(), shared, &encrypted1).unwrap();
+		assert_eq!(decrypted[..], message[..]);
+	}
+
+	#[test]
+	#[cfg(feature = "ecc")]
+	fn ecies_encrypt_into_reuses_buffer() {
+		let kp = Random.generate().unwrap();
+		let shared = b"shared";
+		let mut out = Vec::new();
+
+		for message in &[&b"So many books, so little time"[..], &b"short"[..], &[7u8; 100][..]] {
+			let mut rng1 = XorShiftRng::from_seed([1, 2, 3, 4]);
+			let mut rng2 = XorShiftRng::from_seed([1, 2, 3, 4]);
+			let encrypted = ecies::encrypt_with_rng(&mut rng1, kp.public(), shared, message).unwrap();
+			ecies::encrypt_into_with_rng(&mut rng2, kp.public(), shared, message, &mut out).unwrap();
+			assert_eq!(out, encrypted);
+		}
+
+		ecies::encrypt_into(kp.public(), shared, b"hello", &mut out).unwrap();
+		assert_eq!(ecies::decrypt(kp.secret(), shared, &out).unwrap(), b"hello");
+	}
+
+	#[test]
+	#[cfg(feature = "ecc")]
+	fn ecies_decrypt_to_sink() {
+		let kp = Random.generate().unwrap();
+		let shared = b"shared";
+		// spans several sink blocks, the last one partial.
+		let message: Vec<u8> = (0..(3 * aes::SINK_BLOCK_SIZE + 100)).map(|i| i as u8).collect();
+		let encrypted = ecies::encrypt(kp.public(), shared, &message).unwrap();
+
+		let mut streamed = Vec::new();
+		let mut blocks = 0;
+		ecies::decrypt_to_sink(kp.secret(), shared, &encrypted, |block| {
+			blocks += 1;
+			streamed.extend_from_slice(block);
+		}).unwrap();
+		assert_eq!(blocks, 4);
+		assert_eq!(streamed, ecies::decrypt(kp.secret(), shared, &encrypted).unwrap());
+
+		// nothing reaches the sink if the tag doesn't match.
+		let mut tampered = encrypted.clone();
+		tampered[1 + 64 + 16] ^= 1;
+		let mut called = false;
+		assert_eq!(ecies::decrypt_to_sink(kp.secret(), shared, &tampered, |_| called = true), Err(Error::InvalidMessage));
+		assert!(!called);
+	}
+
+	#[test]
+	#[cfg(feature = "ecc")]
+	fn ecies_decrypt_with_len() {
+		let kp = Random.generate().unwrap();
+		let message = b"So many books, so little time";
+		let encrypted = ecies::encrypt(kp.public(), b"shared", message).unwrap();
+
+		let (plain, len) = ecies::decrypt_with_len(kp.secret(), b"shared", &encrypted).unwrap();
+		assert_eq!(len, message.len());
+		assert_eq!(len, plain.len());
+		assert_eq!(&plain[..len], &message[..]);
+		assert_eq!(ecies::decrypt_with_len(kp.secret(), b"other", &encrypted), Err(Error::InvalidMessage));
+	}
+
+	#[test]
+	#[cfg(feature = "ecc")]
+	fn ecies_key_id_roundtrip() {
+		let kp = Random.generate().unwrap();
+		let message = b"Routed to the right key";
+		let encrypted = ecies::encrypt_with_key_id(kp.public(), 0xdeadbeef, b"shared", message).unwrap();
+
+		assert_eq!(encrypted[0], ecies::KEY_ID_VERSION);
+		assert_eq!(ecies::key_id(&encrypted), Some(0xdeadbeef));
+		let (id, plain) = ecies::decrypt_with_key_id(kp.secret(), b"shared", &encrypted).unwrap();
+		assert_eq!(id, 0xdeadbeef);
+		assert_eq!(&plain[..], &message[..]);
+
+		// messages without the hint are rejected
+		let plain_encrypted = ecies::encrypt(kp.public(), b"shared", message).unwrap();
+		assert_eq!(ecies::key_id(&plain_encrypted), None);
+		assert_eq!(ecies::decrypt_with_key_id(kp.secret(), b"shared", &plain_encrypted), Err(Error::InvalidMessage));
+	}
+
+	#[test]
+	#[cfg(feature = "ecc")]
+	fn ecies_message_roundtrip() {
+		let kp = Random.generate().unwrap();
+		let encrypted = ecies::encrypt(kp.public(), b"shared", b"Layout matters").unwrap();
+
+		let message = ecies::EciesMessage::from_bytes(&encrypted, ecies::TagLength::Full).unwrap();
+		assert_eq!(message.prefix, 0x04);
+		assert_eq!(message.ciphertext.len(), b"Layout matters".len());
+		assert_eq!(message.tag.len(), 32);
+		assert_eq!(message.to_bytes(), encrypted);
+
+		// re-encoded messages still decrypt.
+		let reencoded = message.to_bytes();
+		assert_eq!(ecies::decrypt(kp.secret(), b"shared", &reencoded).unwrap(), b"Layout matters".to_vec());
+
+		let truncated = ecies::encrypt_with_tag_len(kp.public(), b"shared", b"short tag", ecies::TagLength::Truncated).unwrap();
+		let message = ecies::EciesMessage::from_bytes(&truncated, ecies::TagLength::Truncated).unwrap();
+		assert_eq!(message.tag.len(), 16);
+		assert_eq!(message.to_bytes(), truncated);
+	}
+
+	#[test]
+	#[cfg(feature = "ecc")]
+	fn ecies_message_rejects_malformed() {
+		use ecies::{EciesMessage, TagLength};
+
+		let kp = Random.generate().unwrap();
+		let encrypted = ecies::encrypt(kp.public(), b"shared", b"hello").unwrap();
+
+		// too short to hold the overhead.
+		let short = &encrypted[..EciesMessage::overhead(TagLength::Full) - 1];
+		assert_eq!(EciesMessage::from_bytes(short, TagLength::Full), Err(Error::InvalidMessage));
+		assert_eq!(EciesMessage::from_bytes(&[], TagLength::Full), Err(Error::InvalidMessage));
+
+		// unknown prefix.
+		let mut bad_prefix = encrypted.clone();
+		bad_prefix[0] = 0x01;
+		assert_eq!(EciesMessage::from_bytes(&bad_prefix, TagLength::Full), Err(Error::InvalidMessage));
+
+		// ephemeral key not on the curve.
+		let mut bad_key = encrypted.clone();
+		bad_key[1..65].copy_from_slice(&[1u8; 64]);
+		assert_eq!(EciesMessage::from_bytes(&bad_key, TagLength::Full), Err(Error::InvalidMessage));
+	}
+
+	#[test]
+	#[cfg(feature = "ecc")]
+	fn public_to_address_matches_known_pair() {
+		use ethkey::Public;
+		use public_to_address;
+
+		// public key of the secret key `1`.
+		let public = Public::from_slice(&from_hex("79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798483ada7726a3c4655da4fbfc0e1108a8fd17b448a68554199c47d08ffb10d4b8"));
+		assert_eq!(public_to_address(&public).to_vec(), from_hex("7e5f4552091a69125d5dfcb7b8c2659029395bdf"));
+
+		let kp = Random.generate().unwrap();
+		assert_eq!(&public_to_address(kp.public())[..], &kp.address()[..]);
+	}
+
+	#[test]
+	#[cfg(feature = "ecc")]
+	fn ecies_verify_batch() {
+		let kp = Random.generate().unwrap();
+		let other = Random.generate().unwrap();
+
+		let valid = ecies::encrypt(kp.public(), b"shared", b"hello").unwrap();
+		let mut tampered = ecies::encrypt(kp.public(), b"shared", b"world").unwrap();
+		let last = tampered.len() - 1;
+		tampered[last] ^= 1;
+		let foreign = ecies::encrypt(other.public(), b"shared", b"hello").unwrap();
+		let also_valid = ecies::encrypt(kp.public(), b"shared", b"").unwrap();
+
+		let messages = vec![&valid[..], &tampered[..], &foreign[..], &also_valid[..], &valid[..0]];
+		let results = ecies::verify_batch(kp.secret(), b"shared", &messages);
+		assert_eq!(results, vec![Ok(()), Err(Error::InvalidMessage), Err(Error::InvalidMessage), Ok(()), Err(Error::InvalidMessage)]);
+		assert!(ecies::verify_batch(kp.secret(), b"shared", &[]).is_empty());
+	}
+
+	#[test]
+	#[cfg(feature = "ecc")]
+	fn ecies_decrypt_rejects_messages_over_max_len() {
+		let kp = Random.generate().unwrap();
+		let plain = vec![7u8; 1024];
+		let encrypted = ecies::encrypt(kp.public(), b"shared", &plain).unwrap();
+
+		assert_eq!(ecies::decrypt_with_max_len(kp.secret(), b"shared", &encrypted, encrypted.len()), Ok(plain));
+		assert_eq!(ecies::decrypt_with_max_len(kp.secret(), b"shared", &encrypted, encrypted.len() - 1), Err(Error::MessageTooLarge));
+
+		// garbage isn't even parsed: it would fail as an invalid message otherwise.
+		let garbage = vec![0u8; 4096];
+		assert_eq!(ecies::decrypt(kp.secret(), b"shared", &garbage), Err(Error::InvalidMessage));
+		assert_eq!(ecies::decrypt_with_max_len(kp.secret(), b"shared", &garbage, 1024), Err(Error::MessageTooLarge));
+	}
+
+	#[test]
+	#[cfg(feature = "ecc")]
+	fn ecies_encrypt_to_compressed() {
+		let kp = Random.generate().unwrap();
+		let mut compressed = [0u8; 33];
+		compressed[0] = 2 + (kp.public()[63] & 1);
+		compressed[1..33].copy_from_slice(&kp.public()[0..32]);
+
+		let encrypted = ecies::encrypt_to_compressed(&compressed, b"shared", b"hello").unwrap();
+		assert_eq!(ecies::decrypt(kp.secret(), b"shared", &encrypted).unwrap(), b"hello".to_vec());
+		assert_eq!(ecdh::public_from_compressed(&compressed), Ok(kp.public().clone()));
+
+		// unknown prefix.
+		let mut bad_prefix = compressed;
+		bad_prefix[0] = 4;
+		assert_eq!(ecies::encrypt_to_compressed(&bad_prefix, b"shared", b"hello"), Err(Error::InvalidMessage));
+
+		// x coordinate beyond the field size.
+		let mut off_curve = [0xffu8; 33];
+		off_curve[0] = 2;
+		assert_eq!(ecies::encrypt_to_compressed(&off_curve, b"shared", b"hello"), Err(Error::InvalidMessage));
+	}
+
+	#[test]
+	#[cfg(feature = "ecc")]
+	fn ecies_rejects_off_curve_public() {
+		let kp = Random.generate().unwrap();
+		let off_curve = [1u8; 64];
+		assert_eq!(ecdh::public_from_slice(&off_curve), Err(Error::InvalidMessage));
+		assert_eq!(ecdh::public_from_slice(&kp.public()[..]), Ok(kp.public().clone()));
+		assert_eq!(ecdh::public_from_slice(&kp.public()[..63]), Err(Error::InvalidMessage));
+
+		let mut encrypted = ecies::encrypt(kp.public(), b"shared", b"hello").unwrap();
+		encrypted[1..65].copy_from_slice(&off_curve);
+		assert_eq!(ecies::decrypt(kp.secret(), b"shared", &encrypted), Err(Error::InvalidMessage));
+
+		let mut single = ecies::encrypt_single_message(kp.public(), b"hello").unwrap();
+		single[0..64].copy_from_slice(&off_curve);
+		assert_eq!(ecies::decrypt_single_message(kp.secret(), &single), Err(Error::InvalidMessage));
+	}
+}
@@ -19,6 +19,7 @@ use json;
 #[derive(Debug, PartialEq, Clone)]
 pub enum Prf {
 	HmacSha256,
+	HmacSha512,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -48,6 +49,7 @@ impl From<json::Prf> for Prf {
 	fn from(json: json::Prf) -> Self {
 		match json {
 			json::Prf::HmacSha256 => Prf::HmacSha256,
+			json::Prf::HmacSha512 => Prf::HmacSha512,
 		}
 	}
 }
@@ -56,6 +58,7 @@ impl Into<json::Prf> for Prf {
 	fn into(self) -> json::Prf {
 		match self {
 			Prf::HmacSha256 => json::Prf::HmacSha256,
+			Prf::HmacSha512 => json::Prf::HmacSha512,
 		}
 	}
 }
@@ -81,7 +81,8 @@ impl Crypto {
 
 		// two parts of derived key
 		// DK = [ DK[0..15] DK[16..31] ] = [derived_left_bits, derived_right_bits]
-		let (derived_left_bits, derived_right_bits) = crypto::derive_key_iterations(password, &salt, iterations);
+		let (derived_left_bits, derived_right_bits) = crypto::derive_key_iterations(password, &salt, iterations)
+			.expect("32-byte salt is longer than MIN_SALT_LENGTH; qed");
 
 		let mut ciphertext = [0u8; 32];
 
@@ -89,7 +90,9 @@ impl Crypto {
 		crypto::aes::encrypt(&derived_left_bits, &iv, &**secret, &mut ciphertext);
 
 		// KECCAK(DK[16..31] ++ <ciphertext>), where DK[16..31] - derived_right_bits
-		let mac = crypto::derive_mac(&derived_right_bits, &ciphertext).keccak256();
+		let mac = crypto::derive_mac(&derived_right_bits, &ciphertext)
+			.expect("derived key halves are KEY_LENGTH_AES bytes long; qed")
+			.keccak256();
 
 		Crypto {
 			cipher: Cipher::Aes128Ctr(Aes128Ctr {
@@ -108,11 +111,19 @@ impl Crypto {
 
 	pub fn secret(&self, password: &str) -> Result<Secret, Error> {
 		let (derived_left_bits, derived_right_bits) = match self.kdf {
-			Kdf::Pbkdf2(ref params) => crypto::derive_key_iterations(password, &params.salt, params.c),
+			Kdf::Pbkdf2(ref params) => {
+				let prf = match params.prf {
+					Prf::HmacSha256 => crypto::Prf::HmacSha256,
+					Prf::HmacSha512 => crypto::Prf::HmacSha512,
+				};
+				crypto::derive_key_iterations_with_prf(password, &params.salt, params.c, prf)
+			},
 			Kdf::Scrypt(ref params) => crypto::derive_key_scrypt(password, &params.salt, params.n, params.p, params.r),
-		};
+		}.expect("keystore salts are 32 bytes long, longer than MIN_SALT_LENGTH; qed");
 
-		let mac = crypto::derive_mac(&derived_right_bits, &self.ciphertext).keccak256();
+		let mac = crypto::derive_mac(&derived_right_bits, &self.ciphertext)
+			.expect("derived key halves are KEY_LENGTH_AES bytes long; qed")
+			.keccak256();
 
 		if mac != self.mac {
 			return Err(Error::InvalidPassword);
@@ -63,6 +63,7 @@ impl Visitor for KdfSerVisitor {
 #[derive(Debug, PartialEq)]
 pub enum Prf {
 	HmacSha256,
+	HmacSha512,
 }
 
 impl Serialize for Prf {
@@ -70,6 +71,7 @@ impl Serialize for Prf {
 	where S: Serializer {
 		match *self {
 			Prf::HmacSha256 => serializer.serialize_str("hmac-sha256"),
+			Prf::HmacSha512 => serializer.serialize_str("hmac-sha512"),
 		}
 	}
 }
@@ -89,6 +91,7 @@ impl Visitor for PrfVisitor {
 	fn visit_str<E>(&mut self, value: &str) -> Result<Self::Value, E> where E: SerdeError {
 		match value {
 			"hmac-sha256" => Ok(Prf::HmacSha256),
+			"hmac-sha512" => Ok(Prf::HmacSha512),
 			_ => Err(SerdeError::custom(Error::InvalidPrf)),
 		}
 	}
@@ -21,6 +21,7 @@
 extern crate ethcore_ipc as ipc;
 extern crate ethcore_ipc_nano as nanoipc;
 extern crate semver;
+extern crate libc;
 #[macro_use] extern crate log;
 
 pub mod service;
@@ -32,6 +33,7 @@ use std::sync::{Arc,RwLock};
 use service::{HypervisorService, IpcModuleId};
 use std::process::{Command,Child};
 use std::collections::HashMap;
+use std::time::{Duration, Instant};
 
 pub use service::{HypervisorServiceClient, CLIENT_MODULE_ID, SYNC_MODULE_ID};
 
@@ -46,6 +48,15 @@ pub struct Hypervisor {
 	pub io_path: String,
 }
 
+/// Outcome of the hypervisor shutdown
+#[derive(Debug, Default, PartialEq)]
+pub struct ShutdownReport {
+	/// Modules which exited on their own after being signalled
+	pub stopped: Vec<IpcModuleId>,
+	/// Modules which had to be killed after the wait time elapsed
+	pub killed: Vec<IpcModuleId>,
+}
+
 /// Boot arguments for binary
 pub struct BootArgs {
 	cli: Option<Vec<String>>,
@@ -183,20 +194,73 @@ impl Hypervisor {
 	}
 
 	/// Shutdown the ipc and all managed child processes
-	pub fn shutdown(&self, wait_time: Option<std::time::Duration>) {
-		if wait_time.is_some() { std::thread::sleep(wait_time.unwrap()) }
-
+	/// Every module is signalled to stop and given `wait_time` to exit,
+	/// the ones still running afterwards are killed
+	pub fn shutdown(&self, wait_time: Option<Duration>) -> ShutdownReport {
+		let mut report = ShutdownReport::default();
 		let mut childs = self.processes.write().unwrap();
-		for (ref mut module, ref mut child) in childs.iter_mut() {
+
+		for (module, child) in childs.iter() {
 			trace!(target: "hypervisor", "Stopping process module: {}", module);
-			child.kill().unwrap();
+			signal_stop(child);
+		}
+
+		let deadline = Instant::now() + wait_time.unwrap_or(Duration::new(0, 0));
+		loop {
+			let mut exited = Vec::new();
+			for (module, child) in childs.iter_mut() {
+				if has_exited(child) { exited.push(*module); }
+			}
+			for module in exited {
+				childs.remove(&module);
+				report.stopped.push(module);
+			}
+
+			if childs.is_empty() || Instant::now() >= deadline { break; }
+			std::thread::sleep(Duration::from_millis(10));
 		}
+
+		for (module, mut child) in childs.drain() {
+			warn!(target: "hypervisor", "Process module {} did not stop in time, killing", module);
+			if let Err(e) = child.kill() {
+				warn!(target: "hypervisor", "Error killing process module {}: {:?}", module, e);
+			}
+			let _ = child.wait();
+			report.killed.push(module);
+		}
+
+		report.stopped.sort();
+		report.killed.sort();
+		report
 	}
 }
 
+/// Asks the child process to terminate (the same way Ctrl-C does)
+#[cfg(unix)]
+fn signal_stop(child: &Child) {
+	unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGINT); }
+}
+
+#[cfg(not(unix))]
+fn signal_stop(_child: &Child) {
+}
+
+/// Checks (without blocking) whether the child process has exited, reaping it if so
+#[cfg(unix)]
+fn has_exited(child: &mut Child) -> bool {
+	let pid = child.id() as libc::pid_t;
+	let mut status = 0;
+	unsafe { libc::waitpid(pid, &mut status, libc::WNOHANG) == pid }
+}
+
+#[cfg(not(unix))]
+fn has_exited(_child: &mut Child) -> bool {
+	false
+}
+
 impl Drop for Hypervisor {
 	fn drop(&mut self) {
-		self.shutdown(Some(std::time::Duration::new(1, 0)));
+		self.shutdown(Some(Duration::new(1, 0)));
 	}
 }
 
@@ -239,4 +303,45 @@ mod tests {
 
 		assert_eq!(true, hypervisor.modules_ready());
 	}
+
+	#[cfg(unix)]
+	fn stub_module(script: &str) -> ::std::process::Child {
+		::std::process::Command::new("sh").arg("-c").arg(script).spawn().unwrap()
+	}
+
+	#[test]
+	#[cfg(unix)]
+	fn shutdown_stops_modules() {
+		let url = "ipc:///tmp/test-parity-hypervisor-30.ipc";
+		let hypervisor = Hypervisor::with_url(url);
+		{
+			let mut processes = hypervisor.processes.write().unwrap();
+			processes.insert(8080, stub_module("exec sleep 30"));
+			processes.insert(8081, stub_module("exec sleep 30"));
+		}
+
+		let report = hypervisor.shutdown(Some(::std::time::Duration::from_secs(5)));
+
+		assert_eq!(report, ShutdownReport { stopped: vec![8080, 8081], killed: vec![] });
+		assert!(hypervisor.processes.read().unwrap().is_empty());
+	}
+
+	#[test]
+	#[cfg(unix)]
+	fn shutdown_kills_unresponsive_modules() {
+		let url = "ipc:///tmp/test-parity-hypervisor-40.ipc";
+		let hypervisor = Hypervisor::with_url(url);
+		{
+			let mut processes = hypervisor.processes.write().unwrap();
+			processes.insert(8080, stub_module("exec sleep 30"));
+			processes.insert(8081, stub_module("trap '' INT; exec sleep 30"));
+		}
+		// let the stub install its signal trap
+		::std::thread::sleep(::std::time::Duration::from_millis(200));
+
+		let report = hypervisor.shutdown(Some(::std::time::Duration::from_millis(500)));
+
+		assert_eq!(report, ShutdownReport { stopped: vec![8080], killed: vec![8081] });
+		assert!(hypervisor.processes.read().unwrap().is_empty());
+	}
 }
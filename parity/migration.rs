@@ -16,11 +16,11 @@
 
 use std::fs;
 use std::fs::File;
-use std::io::{Read, Write, Error as IoError, ErrorKind};
+use std::io::{Read, Error as IoError, ErrorKind};
 use std::path::{Path, PathBuf};
 use std::fmt::{Display, Formatter, Error as FmtError};
 use util::journaldb::Algorithm;
-use util::migration::{Manager as MigrationManager, Config as MigrationConfig, Error as MigrationError, Migration};
+use util::migration::{Manager as MigrationManager, Config as MigrationConfig, Error as MigrationError, Migration, commit_version};
 use util::kvdb::{CompactionProfile, Database, DatabaseConfig};
 use ethcore::migrations;
 use ethcore::db;
@@ -108,10 +108,10 @@ fn current_version(path: &Path) -> Result<u32, Error> {
 
 /// Writes current database version to the file.
 /// Creates a new file if the version file does not exist yet.
+/// Must only be called after all migrated databases are in place.
 fn update_version(path: &Path) -> Result<(), Error> {
 	try!(fs::create_dir_all(path));
-	let mut file = try!(File::create(version_file_path(path)));
-	try!(file.write_all(format!("{}", CURRENT_VERSION).as_bytes()));
+	try!(commit_version(&version_file_path(path), CURRENT_VERSION));
 	Ok(())
 }
 
@@ -327,3 +327,40 @@ mod legacy {
 		Ok(manager)
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use std::fs::{self, File};
+	use devtools::RandomTempPath;
+	use util::journaldb::Algorithm;
+	use util::kvdb::{CompactionProfile, Database};
+	use util::migration::commit_version;
+	use super::{migrate, current_version, version_file_path, consolidated_database_path, legacy, CURRENT_VERSION};
+
+	#[test]
+	fn interrupted_migration_keeps_version() {
+		// given
+		let temp = RandomTempPath::create_dir();
+		let path = temp.as_path();
+		commit_version(&version_file_path(path), 8).unwrap();
+		Database::open_default(legacy::blocks_database_path(path).to_str().unwrap()).unwrap();
+		// the extras database can't be opened, so consolidation dies half-way through.
+		File::create(legacy::extras_database_path(path)).unwrap();
+
+		// when
+		assert!(migrate(path, Algorithm::Archive, CompactionProfile::default()).is_err());
+
+		// then
+		assert_eq!(current_version(path).unwrap(), 8);
+		assert!(fs::metadata(legacy::blocks_database_path(path)).is_ok());
+
+		// when
+		fs::remove_file(legacy::extras_database_path(path)).unwrap();
+		migrate(path, Algorithm::Archive, CompactionProfile::default()).unwrap();
+
+		// then
+		assert_eq!(current_version(path).unwrap(), CURRENT_VERSION);
+		assert!(fs::metadata(consolidated_database_path(path)).is_ok());
+		assert!(fs::metadata(legacy::blocks_database_path(path)).is_err());
+	}
+}
@@ -24,6 +24,38 @@ use self::no_ipc_deps::*;
 use self::ipc_deps::*;
 use ethcore_logger::Config as LogConfig;
 use std::path::Path;
+use std::fmt::Debug;
+use std::time::Duration;
+use std::thread;
+
+/// Number of attempts made to connect to an IPC module before giving up.
+pub const IPC_CONNECT_ATTEMPTS: usize = 5;
+/// Delay (in milliseconds) before the first reconnection attempt. Doubled after every failure.
+pub const IPC_CONNECT_BACKOFF_MS: u64 = 100;
+
+/// Runs `connect` up to `attempts` times, sleeping with exponential backoff in between.
+/// Returns the last error if none of the attempts succeeded.
+#[cfg_attr(not(feature="ipc"), allow(dead_code))]
+pub fn connect_with_retry<T, E, F>(attempts: usize, backoff_ms: u64, mut connect: F) -> Result<T, E>
+	where E: Debug, F: FnMut() -> Result<T, E>
+{
+	let mut backoff = backoff_ms;
+	let mut attempt = 1;
+	loop {
+		match connect() {
+			Ok(client) => return Ok(client),
+			Err(e) => {
+				if attempt >= attempts {
+					return Err(e);
+				}
+				debug!(target: "ipc", "Connection attempt {}/{} failed: {:?}. Retrying in {}ms.", attempt, attempts, e, backoff);
+				thread::sleep(Duration::from_millis(backoff));
+				backoff = backoff.saturating_mul(2);
+				attempt += 1;
+			}
+		}
+	}
+}
 
 pub mod service_urls {
 	use std::path::PathBuf;
@@ -67,7 +99,7 @@ mod ipc_deps {
 	pub use ethsync::{SyncClient, NetworkManagerClient, ServiceConfiguration};
 	pub use ethcore::client::ChainNotifyClient;
 	pub use hypervisor::{SYNC_MODULE_ID, BootArgs, HYPERVISOR_IPC_URL};
-	pub use nanoipc::{GuardedSocket, NanoSocket, init_client};
+	pub use nanoipc::{GuardedSocket, NanoSocket, WithSocket, init_client};
 	pub use ipc::IpcSocket;
 	pub use ipc::binary::serialize;
 }
@@ -111,6 +143,16 @@ fn sync_arguments(io_path: &str, sync_cfg: SyncConfig, net_cfg: NetworkConfigura
 	BootArgs::new().stdin(service_payload).cli(cli_args)
 }
 
+/// Connects to the IPC module at `url`, retrying while the module is still binding its socket.
+#[cfg(feature="ipc")]
+fn ipc_client<S>(url: &str) -> Result<GuardedSocket<S>, NetworkError> where S: WithSocket<NanoSocket> {
+	connect_with_retry(IPC_CONNECT_ATTEMPTS, IPC_CONNECT_BACKOFF_MS, || init_client::<S>(url))
+		.map_err(|e| NetworkError::StdIo(::std::io::Error::new(
+			::std::io::ErrorKind::NotConnected,
+			format!("Unable to connect to IPC module at {}: {:?}", url, e),
+		)))
+}
+
 #[cfg(feature="ipc")]
 pub fn sync
 	(
@@ -129,14 +171,16 @@ pub fn sync
 	hypervisor.start();
 	hypervisor.wait_for_startup();
 
-	let sync_client = init_client::<SyncClient<_>>(
-		&service_urls::with_base(&hypervisor.io_path, service_urls::SYNC)).unwrap();
-	let notify_client = init_client::<ChainNotifyClient<_>>(
-		&service_urls::with_base(&hypervisor.io_path, service_urls::SYNC_NOTIFY)).unwrap();
-	let manage_client = init_client::<NetworkManagerClient<_>>(
-		&service_urls::with_base(&hypervisor.io_path, service_urls::NETWORK_MANAGER)).unwrap();
+	// on failure the hypervisor is dropped, which shuts the spawned module down
+	let sync_client = try!(ipc_client::<SyncClient<_>>(
+		&service_urls::with_base(&hypervisor.io_path, service_urls::SYNC)));
+	let notify_client = try!(ipc_client::<ChainNotifyClient<_>>(
+		&service_urls::with_base(&hypervisor.io_path, service_urls::SYNC_NOTIFY)));
+	let manage_client = try!(ipc_client::<NetworkManagerClient<_>>(
+		&service_urls::with_base(&hypervisor.io_path, service_urls::NETWORK_MANAGER)));
 
 	*hypervisor_ref = Some(hypervisor);
+
 	Ok((sync_client, manage_client, notify_client))
 }
 
@@ -151,6 +195,68 @@ pub fn sync
 	)
 	-> Result<SyncModules, NetworkError>
 {
+	sync_modules(sync_cfg, net_cfg, client)
+}
+
+/// Sets up the sync modules in-process on top of given client, without starting the network.
+/// Any `BlockChainClient` will do, so module wiring can be tested against a mock client.
+#[cfg(not(feature="ipc"))]
+pub fn sync_modules(sync_cfg: SyncConfig, net_cfg: NetworkConfiguration, client: Arc<BlockChainClient>) -> Result<SyncModules, NetworkError> {
 	let eth_sync = try!(EthSync::new(sync_cfg, client, net_cfg));
 	Ok((eth_sync.clone() as Arc<SyncProvider>, eth_sync.clone() as Arc<ManageNetwork>, eth_sync.clone() as Arc<ChainNotify>))
 }
+
+#[cfg(test)]
+mod tests {
+	use std::cell::Cell;
+	use super::connect_with_retry;
+
+	/// Stub of an IPC module which binds its socket only after a few connection attempts.
+	struct LateBinder {
+		binds_after: usize,
+		attempts: Cell<usize>,
+	}
+
+	impl LateBinder {
+		fn connect(&self) -> Result<&'static str, &'static str> {
+			self.attempts.set(self.attempts.get() + 1);
+			if self.attempts.get() > self.binds_after { Ok("client") } else { Err("not bound") }
+		}
+	}
+
+	#[test]
+	fn should_retry_until_module_binds() {
+		let module = LateBinder { binds_after: 2, attempts: Cell::new(0) };
+		assert_eq!(connect_with_retry(5, 1, || module.connect()), Ok("client"));
+		assert_eq!(module.attempts.get(), 3);
+	}
+
+	#[test]
+	fn should_return_error_after_all_attempts_fail() {
+		let module = LateBinder { binds_after: 10, attempts: Cell::new(0) };
+		assert_eq!(connect_with_retry(3, 1, || module.connect()), Err("not bound"));
+		assert_eq!(module.attempts.get(), 3);
+	}
+
+	#[test]
+	#[cfg(not(feature="ipc"))]
+	fn should_build_sync_modules_against_mock_client() {
+		use std::sync::Arc;
+		use ethcore::client::{TestBlockChainClient, EachBlockWith};
+		use ethsync::{SyncConfig, NetworkConfiguration};
+		use super::sync_modules;
+
+		let client = TestBlockChainClient::new();
+		client.add_blocks(10, EachBlockWith::Nothing);
+		let sync_cfg = SyncConfig { network_id: 42.into(), ..Default::default() };
+		let net_cfg = NetworkConfiguration { max_peers: 7, ..NetworkConfiguration::new_local() };
+
+		let (sync, manage, _) = sync_modules(sync_cfg, net_cfg, Arc::new(client)).unwrap();
+
+		let status = sync.status();
+		assert_eq!(status.network_id, 42.into());
+		assert_eq!(status.start_block_number, 10);
+		assert_eq!(status.last_imported_block_number, Some(10));
+		assert_eq!(manage.network_config().max_peers, 7);
+	}
+}
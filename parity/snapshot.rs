@@ -21,7 +21,7 @@ use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use ethcore_logger::{setup_log, Config as LogConfig};
-use ethcore::snapshot::{Progress, RestorationStatus, SnapshotService};
+use ethcore::snapshot::{Progress, RestorationMode, RestorationStatus, SnapshotService};
 use ethcore::snapshot::io::{SnapshotReader, PackedReader, PackedWriter};
 use ethcore::service::ClientService;
 use ethcore::client::{Mode, DatabaseCompactionProfile, Switch, VMType};
@@ -121,18 +121,15 @@ impl SnapshotCommand {
 		// drop the client so we don't restore while it has open DB handles.
 		drop(service);
 
-		try!(snapshot.init_restore(manifest.clone()).map_err(|e| {
+		try!(snapshot.init_restore(manifest.clone(), RestorationMode::Full).map_err(|e| {
 			format!("Failed to begin restoration: {}", e)
 		}));
 
-		let (num_state, num_blocks) = (manifest.state_hashes.len(), manifest.block_hashes.len());
-
 		let informant_handle = snapshot.clone();
 		::std::thread::spawn(move || {
- 			while let RestorationStatus::Ongoing = informant_handle.status() {
- 				let (state_chunks, block_chunks) = informant_handle.chunks_done();
+ 			while let RestorationStatus::Ongoing { state_chunks_done, block_chunks_done, state_chunks, block_chunks } = informant_handle.status() {
  				info!("Processed {}/{} state chunks and {}/{} block chunks.",
- 					state_chunks, num_state, block_chunks, num_blocks);
+ 					state_chunks_done, state_chunks, block_chunks_done, block_chunks);
 
  				::std::thread::sleep(Duration::from_secs(5));
  			}
@@ -161,7 +158,8 @@ impl SnapshotCommand {
 		}
 
 		match snapshot.status() {
-			RestorationStatus::Ongoing => Err("Snapshot file is incomplete and missing chunks.".into()),
+			RestorationStatus::Ongoing { .. } => Err("Snapshot file is incomplete and missing chunks.".into()),
+			RestorationStatus::Finalizing => Err("Snapshot restoration is still being finalized.".into()),
 			RestorationStatus::Failed => Err("Snapshot restoration failed.".into()),
 			RestorationStatus::Inactive => {
 				info!("Restoration complete.");
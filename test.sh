@@ -15,4 +15,5 @@ esac
 
 . ./scripts/targets.sh
 cargo test --release --features "$FEATURES" $TARGETS $1 \
+	&& cargo test --release --features capi --manifest-path ethcrypto/Cargo.toml $1
 
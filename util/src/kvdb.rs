@@ -411,6 +411,134 @@ impl Database {
 	}
 }
 
+/// Generic key-value database.
+///
+/// Implemented by the RocksDB-backed `Database` and by the in-memory `InMemory`.
+pub trait KeyValueDB: Sync + Send {
+	/// Creates new transaction for this database.
+	fn transaction(&self) -> DBTransaction {
+		DBTransaction {
+			ops: Vec::with_capacity(256),
+		}
+	}
+
+	/// Get value by key.
+	fn get(&self, col: Option<u32>, key: &[u8]) -> Result<Option<Bytes>, String>;
+
+	/// Get value by partial key. Only searches flushed values.
+	fn get_by_prefix(&self, col: Option<u32>, prefix: &[u8]) -> Option<Box<[u8]>>;
+
+	/// Queue transaction to be committed on the next `flush`.
+	fn write_buffered(&self, transaction: DBTransaction);
+
+	/// Commit transaction to database.
+	fn write(&self, transaction: DBTransaction) -> Result<(), String> {
+		self.write_buffered(transaction);
+		self.flush()
+	}
+
+	/// Commit buffered changes to database.
+	fn flush(&self) -> Result<(), String>;
+
+	/// Get iterator over flushed data of given column.
+	fn iter<'a>(&'a self, col: Option<u32>) -> Box<Iterator<Item=(Box<[u8]>, Box<[u8]>)> + 'a>;
+}
+
+impl KeyValueDB for Database {
+	fn get(&self, col: Option<u32>, key: &[u8]) -> Result<Option<Bytes>, String> {
+		Database::get(self, col, key)
+	}
+
+	fn get_by_prefix(&self, col: Option<u32>, prefix: &[u8]) -> Option<Box<[u8]>> {
+		Database::get_by_prefix(self, col, prefix)
+	}
+
+	fn write_buffered(&self, transaction: DBTransaction) {
+		Database::write_buffered(self, transaction)
+	}
+
+	fn write(&self, transaction: DBTransaction) -> Result<(), String> {
+		Database::write(self, transaction)
+	}
+
+	fn flush(&self) -> Result<(), String> {
+		Database::flush(self)
+	}
+
+	fn iter<'a>(&'a self, col: Option<u32>) -> Box<Iterator<Item=(Box<[u8]>, Box<[u8]>)> + 'a> {
+		Box::new(Database::iter(self, col))
+	}
+}
+
+/// Key-value database kept entirely in memory. Writes are applied immediately.
+/// Useful for tests and for data which doesn't need to outlive the process.
+pub struct InMemory {
+	columns: RwLock<HashMap<Option<u32>, BTreeMap<Vec<u8>, Bytes>>>,
+}
+
+/// Create an in-memory database with given number of columns.
+/// The default column (`None`) is always available.
+pub fn in_memory(num_cols: u32) -> InMemory {
+	let mut columns = HashMap::new();
+	columns.insert(None, BTreeMap::new());
+	for col in 0..num_cols {
+		columns.insert(Some(col), BTreeMap::new());
+	}
+
+	InMemory {
+		columns: RwLock::new(columns),
+	}
+}
+
+impl KeyValueDB for InMemory {
+	fn get(&self, col: Option<u32>, key: &[u8]) -> Result<Option<Bytes>, String> {
+		match self.columns.read().get(&col) {
+			Some(map) => Ok(map.get(key).cloned()),
+			None => Err(format!("No such column: {:?}", col)),
+		}
+	}
+
+	fn get_by_prefix(&self, col: Option<u32>, prefix: &[u8]) -> Option<Box<[u8]>> {
+		self.columns.read().get(&col).and_then(|map| map.iter()
+			.find(|&(key, _)| key.starts_with(prefix))
+			.map(|(_, value)| value.clone().into_boxed_slice()))
+	}
+
+	fn write_buffered(&self, transaction: DBTransaction) {
+		let mut columns = self.columns.write();
+		for op in transaction.ops {
+			match op {
+				DBOp::Insert { col, key, value } => {
+					if let Some(map) = columns.get_mut(&col) {
+						map.insert(key.to_vec(), value);
+					}
+				},
+				DBOp::InsertCompressed { col, key, value } => {
+					if let Some(map) = columns.get_mut(&col) {
+						// stored compressed, just like `Database` does on flush.
+						let compressed = UntrustedRlp::new(&value).compress(RlpType::Blocks);
+						map.insert(key.to_vec(), compressed.to_vec());
+					}
+				},
+				DBOp::Delete { col, key } => {
+					if let Some(map) = columns.get_mut(&col) {
+						map.remove(&*key);
+					}
+				},
+			}
+		}
+	}
+
+	fn flush(&self) -> Result<(), String> {
+		Ok(())
+	}
+
+	fn iter<'a>(&'a self, col: Option<u32>) -> Box<Iterator<Item=(Box<[u8]>, Box<[u8]>)> + 'a> {
+		let map = self.columns.read().get(&col).cloned().unwrap_or_else(BTreeMap::new);
+		Box::new(map.into_iter().map(|(key, value)| (key.into_boxed_slice(), value.into_boxed_slice())))
+	}
+}
+
 #[cfg(test)]
 mod tests {
 	use hash::*;
@@ -471,6 +599,33 @@ mod tests {
 		assert_eq!(&*db.get(None, &key1).unwrap().unwrap(), b"horse");
 	}
 
+	#[test]
+	fn in_memory_kvdb() {
+		let db = in_memory(1);
+		let key1 = H256::from_str("02c69be41d0b7e40352fc85be1cd65eb03d40ef8427a0ca4596b1ead9a00e9fc").unwrap();
+		let key2 = H256::from_str("03c69be41d0b7e40352fc85be1cd65eb03d40ef8427a0ca4596b1ead9a00e9fc").unwrap();
+
+		let mut batch = db.transaction();
+		batch.put(None, &key1, b"cat");
+		batch.put(Some(0), &key2, b"dog");
+		db.write(batch).unwrap();
+
+		assert_eq!(&*db.get(None, &key1).unwrap().unwrap(), b"cat");
+		assert!(db.get(None, &key2).unwrap().is_none());
+		assert_eq!(&*db.get_by_prefix(Some(0), &key2[..4]).unwrap(), b"dog");
+		assert!(db.get(Some(1), &key1).is_err());
+
+		let mut batch = db.transaction();
+		batch.delete(None, &key1);
+		batch.put(None, &key2, b"horse");
+		db.write(batch).unwrap();
+
+		let contents: Vec<_> = db.iter(None).collect();
+		assert_eq!(contents.len(), 1);
+		assert_eq!(&*contents[0].0, &*key2);
+		assert_eq!(&*contents[0].1, b"horse");
+	}
+
 	#[test]
 	fn kvdb() {
 		let path = RandomTempPath::create_dir();
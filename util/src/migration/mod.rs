@@ -20,6 +20,7 @@ mod tests;
 
 use std::collections::BTreeMap;
 use std::fs;
+use std::io::Write;
 use std::path::{Path, PathBuf};
 
 use ::kvdb::{CompactionProfile, Database, DatabaseConfig, DBTransaction};
@@ -141,6 +142,74 @@ impl<T: SimpleMigration> Migration for T {
 	}
 }
 
+/// A simple migration which drops every key matching a predicate and leaves the rest untouched.
+pub struct PrunePredicate<F> {
+	columns: Option<u32>,
+	version: u32,
+	predicate: F,
+}
+
+impl<F> PrunePredicate<F> where F: FnMut(&[u8]) -> bool + 'static {
+	/// Create a new pruning migration to the given version.
+	/// Keys for which `predicate` returns `true` are not carried over.
+	pub fn new(columns: Option<u32>, version: u32, predicate: F) -> Self {
+		PrunePredicate {
+			columns: columns,
+			version: version,
+			predicate: predicate,
+		}
+	}
+}
+
+impl<F> SimpleMigration for PrunePredicate<F> where F: FnMut(&[u8]) -> bool + 'static {
+	fn columns(&self) -> Option<u32> { self.columns }
+
+	fn version(&self) -> u32 { self.version }
+
+	fn simple_migrate(&mut self, key: Vec<u8>, value: Vec<u8>) -> Option<(Vec<u8>, Vec<u8>)> {
+		if (self.predicate)(&key) {
+			None
+		} else {
+			Some((key, value))
+		}
+	}
+}
+
+/// Record `version` in the version marker file at `path`.
+///
+/// The marker is written to a temporary file, flushed to disk and only then moved over
+/// the old one, after which the directory entry is flushed as well. Call this once the
+/// migrated database has replaced the old one: if the migration is interrupted at any
+/// earlier point the previous version is left in place.
+pub fn commit_version(path: &Path, version: u32) -> Result<(), Error> {
+	let mut temp_path = path.to_owned();
+	temp_path.set_extension("tmp");
+	{
+		let mut file = try!(fs::File::create(&temp_path));
+		try!(file.write_all(format!("{}", version).as_bytes()));
+		try!(file.sync_all());
+	}
+	try!(fs::rename(&temp_path, path));
+	if let Some(parent) = path.parent() {
+		try!(sync_dir(parent));
+	}
+	Ok(())
+}
+
+// Flush a directory, making renames within it durable.
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> Result<(), Error> {
+	let path = if path.as_os_str().is_empty() { Path::new(".") } else { path };
+	try!(try!(fs::File::open(path)).sync_all());
+	Ok(())
+}
+
+// Directories can't be opened as files here; the rename itself is all we can do.
+#[cfg(not(unix))]
+fn sync_dir(_path: &Path) -> Result<(), Error> {
+	Ok(())
+}
+
 /// Get the path where all databases reside.
 fn database_path(path: &Path) -> PathBuf {
 	let mut temp_path = path.to_owned();
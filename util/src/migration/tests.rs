@@ -19,10 +19,12 @@
 //! are performed in temp sub-directories.
 
 use common::*;
-use migration::{Config, SimpleMigration, Manager};
+use migration::{Config, SimpleMigration, Manager, PrunePredicate, commit_version};
 use kvdb::Database;
 
 use devtools::RandomTempPath;
+use std::fs::{self, File};
+use std::io::{Read, Write};
 use std::path::PathBuf;
 
 fn db_path(path: &Path) -> PathBuf {
@@ -83,6 +85,12 @@ impl SimpleMigration for Migration1 {
 	}
 }
 
+fn read_version(path: &Path) -> String {
+	let mut s = String::new();
+	File::open(path).unwrap().read_to_string(&mut s).unwrap();
+	s
+}
+
 #[test]
 fn one_simple_migration() {
 	let dir = RandomTempPath::create_dir();
@@ -179,6 +187,24 @@ fn noop_and_second_migration() {
 	verify_migration(&end_path, expected);
 }
 
+#[test]
+fn prune_predicate_drops_matching_keys() {
+	let dir = RandomTempPath::create_dir();
+	let db_path = db_path(dir.as_path());
+	let mut manager = Manager::new(Config::default());
+	make_db(&db_path, map![vec![0xde, 1] => vec![1], vec![0xde, 2] => vec![2], vec![3] => vec![3]]);
+	let expected = map![vec![3] => vec![3]];
+
+	manager.add_migration(PrunePredicate::new(None, 1, |key: &[u8]| key.first() == Some(&0xde))).unwrap();
+	let end_path = manager.execute(&db_path, 0).unwrap();
+
+	verify_migration(&end_path, expected);
+
+	let db = Database::open_default(end_path.to_str().unwrap()).unwrap();
+	assert!(db.get(None, &[0xde, 1]).unwrap().is_none());
+	assert!(db.get(None, &[0xde, 2]).unwrap().is_none());
+}
+
 #[test]
 fn is_migration_needed() {
 	let mut manager = Manager::new(Config::default());
@@ -189,3 +215,21 @@ fn is_migration_needed() {
 	assert!(manager.is_needed(1));
 	assert!(!manager.is_needed(2));
 }
+
+#[test]
+fn interrupted_version_commit_keeps_version() {
+	let dir = RandomTempPath::create_dir();
+	let mut version_path = dir.as_path().to_owned();
+	version_path.push("db_version");
+	commit_version(&version_path, 8).unwrap();
+
+	// a partially written marker left behind by a crash must not be picked up.
+	let mut temp_path = version_path.clone();
+	temp_path.set_extension("tmp");
+	File::create(&temp_path).unwrap().write_all(b"1").unwrap();
+	assert_eq!(read_version(&version_path), "8");
+
+	commit_version(&version_path, 9).unwrap();
+	assert_eq!(read_version(&version_path), "9");
+	assert!(fs::metadata(&temp_path).is_err());
+}